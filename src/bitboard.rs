@@ -0,0 +1,92 @@
+// Small helpers for evaluation terms that are easiest to express on 64 bit square sets,
+// with bit n standing for square n (a1 = 0, h8 = 63)
+use crate::piece::Color;
+
+pub const FILE_A: u64 = 0x0101_0101_0101_0101;
+pub const FILE_H: u64 = FILE_A << 7;
+
+pub fn file_mask(file: usize) -> u64 {
+    FILE_A << file
+}
+
+pub fn rank_mask(rank: usize) -> u64 {
+    0xFF << (8 * rank)
+}
+
+pub fn adjacent_files(file: usize) -> u64 {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+
+// All squares on ranks strictly in front of the given rank, from color's point of view
+pub fn ranks_ahead(color: Color, rank: usize) -> u64 {
+    match color {
+        Color::White if rank < 7 => u64::MAX << (8 * (rank + 1)),
+        Color::Black => (1 << (8 * rank)) - 1,
+        _ => 0,
+    }
+}
+
+pub fn pawn_attacks(color: Color, pawns: u64) -> u64 {
+    match color {
+        Color::White => ((pawns & !FILE_A) << 7) | ((pawns & !FILE_H) << 9),
+        Color::Black => ((pawns & !FILE_A) >> 9) | ((pawns & !FILE_H) >> 7),
+    }
+}
+
+pub fn squares(mut bitboard: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::piece::Color;
+    use crate::square::Square::*;
+
+    use super::{pawn_attacks, ranks_ahead, squares};
+
+    #[test]
+    fn test_pawn_attacks_do_not_wrap_around() {
+        let pawns = (1 << A2.as_index()) | (1 << H7.as_index());
+
+        assert_eq!(
+            pawn_attacks(Color::White, pawns),
+            (1 << B3.as_index()) | (1 << G8.as_index())
+        );
+        assert_eq!(
+            pawn_attacks(Color::Black, pawns),
+            (1 << B1.as_index()) | (1 << G6.as_index())
+        );
+    }
+
+    #[test]
+    fn test_ranks_ahead_at_the_edges() {
+        assert_eq!(ranks_ahead(Color::White, 7), 0);
+        assert_eq!(ranks_ahead(Color::Black, 0), 0);
+        assert_eq!(ranks_ahead(Color::White, 6), 0xFF << 56);
+        assert_eq!(ranks_ahead(Color::Black, 1), 0xFF);
+    }
+
+    #[test]
+    fn test_squares_iterates_set_bits() {
+        let bitboard = (1 << C3.as_index()) | (1 << H8.as_index());
+
+        assert_eq!(
+            squares(bitboard).collect::<Vec<_>>(),
+            vec![C3.as_index(), H8.as_index()]
+        );
+    }
+}
@@ -1,9 +1,18 @@
 use crate::board_builder::BoardBuilder;
+use crate::eval_params::{eval_params, EvalParams};
+use crate::evaluate::PieceScores;
 use crate::move_generation::{Flag, Move};
+use crate::nnue::{Accumulator, Network};
 use crate::piece::{Color, Piece};
 use crate::square::Square;
-use anyhow::{anyhow, Result};
+use crate::variant::{adjacent_squares, Variant};
+use crate::zobrist::{
+    castling_key, checks_given_key, compute_pawn_key, compute_zobrist_key, en_passant_key,
+    piece_key, pocket_key, side_to_move_key,
+};
+use anyhow::{anyhow, bail, Result};
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct Board {
@@ -13,6 +22,17 @@ pub struct Board {
     pub full_move_number: u32,
     pub board_state: BoardState,
     pub board_state_history: Vec<BoardState>,
+    pub piece_scores: PieceScores,
+    // Only present when evaluating with a network
+    pub accumulator: Option<Accumulator>,
+    // Castling moves are written king takes rook, and FENs name the castling rooks if needed
+    pub chess960: bool,
+    pub variant: Variant,
+    // Crazyhouse only
+    pub pockets: Pockets,
+    // The evaluation weights when the board was set up, so evaluating never has to wait on the
+    // lock around the global ones
+    pub eval_params: Arc<EvalParams>,
 }
 
 impl Default for Board {
@@ -24,6 +44,12 @@ impl Default for Board {
             full_move_number: 1,
             board_state: BoardState::default(),
             board_state_history: Vec::new(),
+            piece_scores: PieceScores::default(),
+            accumulator: None,
+            chess960: false,
+            variant: Variant::Standard,
+            pockets: Pockets::default(),
+            eval_params: eval_params(),
         }
     }
 }
@@ -120,6 +146,10 @@ impl Board {
                             empty_squares = 0;
                         }
                         fen.push(piece.to_symbol(color));
+                        if self.variant.has_drops() && self.board_state.promoted & (1 << index) != 0
+                        {
+                            fen.push('~');
+                        }
                     }
                     _ => empty_squares += 1,
                 }
@@ -131,6 +161,9 @@ impl Board {
                 fen.push('/');
             }
         }
+        if self.variant.has_drops() {
+            fen.push_str(&format!("[{}]", self.pockets.to_symbols()));
+        }
 
         fen.push(' ');
         match self.to_move {
@@ -139,25 +172,7 @@ impl Board {
         };
 
         fen.push(' ');
-        if self.board_state.white_kingside_castling_priviledge {
-            fen.push('K');
-        }
-        if self.board_state.white_queenside_castling_priviledge {
-            fen.push('Q');
-        }
-        if self.board_state.black_kingside_castling_priviledge {
-            fen.push('k');
-        }
-        if self.board_state.black_queenside_castling_priviledge {
-            fen.push('q');
-        }
-        if !(self.board_state.white_kingside_castling_priviledge
-            || self.board_state.white_queenside_castling_priviledge
-            || self.board_state.black_kingside_castling_priviledge
-            || self.board_state.black_queenside_castling_priviledge)
-        {
-            fen.push('-')
-        }
+        fen.push_str(&self.castling_field(false));
 
         // TODO: Should Talia support the newer FEN spec where en passant squares are only listed
         // if a opposite-color pawn is there to actually capture it?
@@ -182,14 +197,170 @@ impl Board {
         fen.push(' ');
         fen.push_str(&self.full_move_number.to_string());
 
+        // The checks each side has given so far, as on Lichess
+        if self.variant == Variant::ThreeCheck {
+            let [white, black] = self.board_state.checks_given;
+            fen.push_str(&format!(" +{white}+{black}"));
+        }
+
         fen
     }
 
+    // Chess960 start positions are numbered 0 to 959 by the Scharnagl scheme, and number 518 is
+    // the standard start position
+    // Source: https://www.chessprogramming.org/Reinhard_Scharnagl#Chess960Numbering
+    pub fn chess960_position(index: u32) -> Result<Self> {
+        if index >= 960 {
+            bail!("Chess960 positions are numbered 0 to 959, not {index}");
+        }
+        const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+
+        let mut back_rank = [None; 8];
+        let mut n = index as usize;
+        back_rank[2 * (n % 4) + 1] = Some('b');
+        n /= 4;
+        back_rank[2 * (n % 4)] = Some('b');
+        n /= 4;
+        let empty = |back_rank: &[Option<char>; 8]| -> Vec<usize> {
+            (0..8).filter(|&file| back_rank[file].is_none()).collect()
+        };
+        back_rank[empty(&back_rank)[n % 6]] = Some('q');
+        n /= 6;
+        let (first, second) = KNIGHT_PLACEMENTS[n];
+        let squares = empty(&back_rank);
+        back_rank[squares[first]] = Some('n');
+        back_rank[squares[second]] = Some('n');
+        // The king always ends up between the rooks
+        for (file, piece) in empty(&back_rank).into_iter().zip(['r', 'k', 'r']) {
+            back_rank[file] = Some(piece);
+        }
+
+        let black: String = back_rank.iter().flatten().collect();
+        let fen = format!(
+            "{black}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            black.to_uppercase()
+        );
+        let mut board = BoardBuilder::try_from_fen(&fen)?;
+        board.chess960 = true;
+        Ok(board)
+    }
+
+    // Like to_fen, but the castling rooks are always named by their file, as in Shredder-FEN
+    pub fn to_shredder_fen(&self) -> String {
+        let mut fields: Vec<String> = self.to_fen().split(' ').map(String::from).collect();
+        fields[2] = self.castling_field(true);
+        fields.join(" ")
+    }
+
+    // KQkq where that is enough to tell which rook castles (X-FEN), the file of the rook
+    // otherwise
+    fn castling_field(&self, shredder: bool) -> String {
+        let state = &self.board_state;
+        let squares = &state.castling_squares;
+        let rights = [
+            (
+                Color::White,
+                state.white_kingside_castling_priviledge,
+                squares.kingside_rook[0],
+                'K',
+            ),
+            (
+                Color::White,
+                state.white_queenside_castling_priviledge,
+                squares.queenside_rook[0],
+                'Q',
+            ),
+            (
+                Color::Black,
+                state.black_kingside_castling_priviledge,
+                squares.kingside_rook[1],
+                'k',
+            ),
+            (
+                Color::Black,
+                state.black_queenside_castling_priviledge,
+                squares.queenside_rook[1],
+                'q',
+            ),
+        ];
+
+        let mut field = String::new();
+        for (color, can_castle, rook_square, letter) in rights {
+            if !can_castle {
+                continue;
+            }
+            // The outermost rook on that side of the king is the one KQkq stands for
+            let king_square = squares.king[color as usize];
+            let outer_squares = match rook_square > king_square {
+                true => rook_square + 1..king_square - king_square % 8 + 8,
+                false => king_square - king_square % 8..rook_square,
+            };
+            let is_outermost = !outer_squares
+                .into_iter()
+                .any(|square| self.is_piece_at_square(square, Piece::Rook, color));
+            if shredder || !is_outermost {
+                let file = (b'A' + (rook_square % 8) as u8) as char;
+                match color {
+                    Color::White => field.push(file),
+                    Color::Black => field.push(file.to_ascii_lowercase()),
+                }
+            } else {
+                field.push(letter);
+            }
+        }
+        if field.is_empty() {
+            field.push('-');
+        }
+
+        field
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<usize> {
+        (0..64).find(|&square| self.is_piece_at_square(square, Piece::King, color))
+    }
+
     pub fn move_piece(&mut self, mv: &Move) {
         self.board_state_history.push(self.board_state.clone());
+        let castling_key_before = castling_key(&self.board_state);
+        self.make_move_on_board(mv);
+        // Castling rights can be lost in several places during a move, so their keys are
+        // swapped once the move is made
+        self.board_state.zobrist_key ^= castling_key_before ^ castling_key(&self.board_state);
+        if self.variant == Variant::ThreeCheck && self.is_king_attacked(self.to_move) {
+            let checking_side = self.to_move.opposite_color();
+            let checks = &mut self.board_state.checks_given[checking_side as usize];
+            *checks += 1;
+            self.board_state.zobrist_key ^= checks_given_key(checking_side, *checks - 1)
+                ^ checks_given_key(checking_side, *checks);
+        }
+        self.debug_assert_incremental_state();
+    }
+
+    // The keys are kept up to date move by move, this is only needed after setting up a
+    // position by hand
+    pub fn refresh_zobrist_keys(&mut self) {
+        self.board_state.zobrist_key = compute_zobrist_key(self);
+        self.board_state.pawn_key = compute_pawn_key(self);
+    }
+
+    fn make_move_on_board(&mut self, mv: &Move) {
         // With every move, the ability to en passant expires until a double pawn push
-        let saved_en_passant_square = self.board_state.en_passant_square;
-        self.board_state.en_passant_square = None;
+        let saved_en_passant_square = self.board_state.en_passant_square.take();
+        if let Some(square) = saved_en_passant_square {
+            self.board_state.zobrist_key ^= en_passant_key(square);
+        }
+        self.board_state.exploded.clear();
 
         if self.is_fifty_move_rule_resetting_move(mv) {
             self.board_state.half_move_clock = 0;
@@ -197,11 +368,22 @@ impl Board {
             self.board_state.half_move_clock += 1;
         }
 
+        let is_capture = matches!(
+            mv.flag,
+            Flag::Capture(_) | Flag::CaptureWithPromotion(..) | Flag::EnPassantCapture
+        );
+        if self.variant == Variant::Atomic && is_capture {
+            self.explode(mv, saved_en_passant_square);
+            self.pass_turn();
+            return;
+        }
+
         match mv.flag {
             Flag::PawnDoublePush => {
                 let pawn_one_move_offset = if self.to_move == Color::White { 8 } else { -8 };
                 let en_passant_index = mv.starting_square as isize + pawn_one_move_offset;
                 self.board_state.en_passant_square = Some(en_passant_index as usize);
+                self.board_state.zobrist_key ^= en_passant_key(en_passant_index as usize);
             }
             Flag::EnPassantCapture => {
                 let starting_piece_color =
@@ -214,15 +396,21 @@ impl Board {
                     en_passant_square + 8
                 };
 
-                self.squares[captured_pawn_index] = None;
-                self.colors[captured_pawn_index] = None;
+                self.remove_piece(captured_pawn_index);
+                self.pocket_captured_piece(Piece::Pawn, captured_pawn_index);
             }
             Flag::KingsideCastle => {
-                self.make_kingside_castling_move(mv);
+                self.make_castling_move(mv, true);
                 return;
             }
             Flag::QueensideCastle => {
-                self.make_queenside_castling_move(mv);
+                self.make_castling_move(mv, false);
+                return;
+            }
+            Flag::Drop(piece) => {
+                self.remove_from_pocket(self.to_move, piece);
+                self.put_piece(mv.target_square, piece, self.to_move);
+                self.pass_turn();
                 return;
             }
             _ => (),
@@ -244,76 +432,78 @@ impl Board {
 
         // The the rook moves, castling rights to that particular side is lost
         if self.squares[mv.starting_square].is_some_and(|piece| piece == Piece::Rook) {
-            let is_from_starting_kingside_room_square = if self.to_move == Color::White {
-                mv.starting_square == Square::H1.as_index()
-            } else {
-                mv.starting_square == Square::H8.as_index()
-            };
-            let is_from_starting_queenside_room_square = if self.to_move == Color::White {
-                mv.starting_square == Square::A1.as_index()
-            } else {
-                mv.starting_square == Square::A8.as_index()
-            };
-
-            match self.to_move {
-                Color::White => {
-                    if is_from_starting_kingside_room_square {
-                        self.board_state.white_kingside_castling_priviledge = false;
-                    } else if is_from_starting_queenside_room_square {
-                        self.board_state.white_queenside_castling_priviledge = false;
-                    }
-                }
-                Color::Black => {
-                    if is_from_starting_kingside_room_square {
-                        self.board_state.black_kingside_castling_priviledge = false;
-                    } else if is_from_starting_queenside_room_square {
-                        self.board_state.black_queenside_castling_priviledge = false;
-                    }
-                }
-            }
+            self.lose_castling_rights_of_rook(mv.starting_square, self.to_move);
         }
 
         // The the rook is captured, castling rights to that particular side is lost
         if self.squares[mv.target_square].is_some_and(|piece| piece == Piece::Rook) {
-            let is_to_starting_kingside_room_square = if self.to_move == Color::White {
-                mv.target_square == Square::H8.as_index()
-            } else {
-                mv.target_square == Square::H1.as_index()
-            };
-            let is_to_starting_queenside_room_square = if self.to_move == Color::White {
-                mv.target_square == Square::A8.as_index()
-            } else {
-                mv.target_square == Square::A1.as_index()
-            };
+            self.lose_castling_rights_of_rook(mv.target_square, self.to_move.opposite_color());
+        }
 
-            match self.to_move {
-                Color::White => {
-                    if is_to_starting_kingside_room_square {
-                        self.board_state.black_kingside_castling_priviledge = false;
-                    } else if is_to_starting_queenside_room_square {
-                        self.board_state.black_queenside_castling_priviledge = false;
-                    }
-                }
-                Color::Black => {
-                    if is_to_starting_kingside_room_square {
-                        self.board_state.white_kingside_castling_priviledge = false;
-                    } else if is_to_starting_queenside_room_square {
-                        self.board_state.white_queenside_castling_priviledge = false;
-                    }
-                }
-            }
+        if let Some(captured_piece) = self.squares[mv.target_square] {
+            self.pocket_captured_piece(captured_piece, mv.target_square);
+        }
+
+        // Promoted pieces are remembered wherever they go, since in crazyhouse they turn back
+        // into pawns when captured
+        let promoted = &mut self.board_state.promoted;
+        let is_promoted = *promoted & (1 << mv.starting_square) != 0;
+        *promoted &= !(1 << mv.starting_square | 1 << mv.target_square);
+        if is_promoted || mv.promotion_piece().is_some() {
+            *promoted |= 1 << mv.target_square;
         }
 
+        let (piece, color) = self
+            .remove_piece(mv.starting_square)
+            .expect("cannot make a move from empty square");
         match mv.flag {
-            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => {
-                self.squares[mv.target_square] = Some(piece);
+            Flag::PromoteTo(promoted_piece) | Flag::CaptureWithPromotion(_, promoted_piece) => {
+                self.put_piece(mv.target_square, promoted_piece, color);
+            }
+            _ => self.put_piece(mv.target_square, piece, color),
+        }
+
+        self.pass_turn();
+    }
+
+    // In atomic a capture takes out the capturing piece, the captured piece and every piece
+    // but pawns next to the target square. Whatever went off is kept in the board state, so
+    // unmake_move can put it all back.
+    fn explode(&mut self, mv: &Move, en_passant_square: Option<usize>) {
+        let captured_square = match (&mv.flag, en_passant_square) {
+            (Flag::EnPassantCapture, Some(square)) if self.to_move == Color::White => square - 8,
+            (Flag::EnPassantCapture, Some(square)) => square + 8,
+            _ => mv.target_square,
+        };
+        let surrounding_pieces = adjacent_squares(mv.target_square)
+            .filter(|&square| self.squares[square].is_some_and(|piece| piece != Piece::Pawn));
+        let squares: Vec<usize> = [mv.starting_square, captured_square]
+            .into_iter()
+            .chain(surrounding_pieces)
+            .collect();
+
+        for square in squares {
+            let Some((piece, color)) = self.remove_piece(square) else {
+                continue;
+            };
+            match (piece, color) {
+                (Piece::Rook, _) => self.lose_castling_rights_of_rook(square, color),
+                (Piece::King, Color::White) => {
+                    self.board_state.white_kingside_castling_priviledge = false;
+                    self.board_state.white_queenside_castling_priviledge = false;
+                }
+                (Piece::King, Color::Black) => {
+                    self.board_state.black_kingside_castling_priviledge = false;
+                    self.board_state.black_queenside_castling_priviledge = false;
+                }
+                _ => {}
             }
-            _ => self.squares[mv.target_square] = self.squares[mv.starting_square],
+            self.board_state.exploded.push((square, piece, color));
         }
-        self.colors[mv.target_square] = self.colors[mv.starting_square];
-        self.squares[mv.starting_square] = None;
-        self.colors[mv.starting_square] = None;
+    }
 
+    fn pass_turn(&mut self) {
+        self.board_state.zobrist_key ^= side_to_move_key();
         if self.to_move == Color::White {
             self.to_move = Color::Black;
         } else {
@@ -322,7 +512,49 @@ impl Board {
         }
     }
 
+    // In crazyhouse the captured piece goes to the captor's pocket, as a pawn if it was promoted
+    fn pocket_captured_piece(&mut self, piece: Piece, square: usize) {
+        if !self.variant.has_drops() {
+            return;
+        }
+        match self.board_state.promoted & (1 << square) {
+            0 => self.add_to_pocket(self.to_move, piece),
+            _ => self.add_to_pocket(self.to_move, Piece::Pawn),
+        }
+    }
+
+    fn add_to_pocket(&mut self, color: Color, piece: Piece) {
+        let count = self.pockets.count(color, piece);
+        self.pockets.add(color, piece);
+        self.board_state.zobrist_key ^=
+            pocket_key(color, piece, count) ^ pocket_key(color, piece, count + 1);
+    }
+
+    fn remove_from_pocket(&mut self, color: Color, piece: Piece) {
+        let count = self.pockets.count(color, piece);
+        self.pockets.remove(color, piece);
+        self.board_state.zobrist_key ^=
+            pocket_key(color, piece, count) ^ pocket_key(color, piece, count - 1);
+    }
+
     pub fn unmake_move(&mut self, mv: &Move) -> Result<()> {
+        // Putting the pieces back changes the keys, but the board state from before the move
+        // already has the right ones
+        let (zobrist_key, pawn_key) = self
+            .board_state_history
+            .last()
+            .map(|board_state| (board_state.zobrist_key, board_state.pawn_key))
+            .ok_or(anyhow!("Already at oldest move"))?;
+        self.unmake_move_on_board(mv)?;
+        self.board_state.zobrist_key = zobrist_key;
+        self.board_state.pawn_key = pawn_key;
+        self.debug_assert_incremental_state();
+
+        Ok(())
+    }
+
+    fn unmake_move_on_board(&mut self, mv: &Move) -> Result<()> {
+        let exploded = std::mem::take(&mut self.board_state.exploded);
         self.board_state = self
             .board_state_history
             .pop()
@@ -330,16 +562,44 @@ impl Board {
 
         self.to_move = self.to_move.opposite_color();
 
+        // An atomic capture, where the explosion took the capturing piece along as well
+        if !exploded.is_empty() {
+            for (square, piece, color) in exploded {
+                self.put_piece(square, piece, color);
+            }
+            if self.to_move == Color::Black {
+                self.full_move_number -= 1;
+            }
+            return Ok(());
+        }
+
+        if let Flag::KingsideCastle | Flag::QueensideCastle = mv.flag {
+            self.unmake_castling_move(mv, mv.flag == Flag::KingsideCastle);
+            if self.to_move == Color::Black {
+                self.full_move_number -= 1;
+            }
+            return Ok(());
+        }
+
+        if let Flag::Drop(piece) = mv.flag {
+            self.remove_piece(mv.target_square);
+            self.pockets.add(self.to_move, piece);
+            if self.to_move == Color::Black {
+                self.full_move_number -= 1;
+            }
+            return Ok(());
+        }
+
         let error_message = "Tried to unmake move, but could not find piece";
         // First move the piece back to its starting square
-        let piece = self.squares[mv.target_square].ok_or(anyhow!(error_message))?;
-        let color = self.colors[mv.target_square].ok_or(anyhow!(error_message))?;
+        let (piece, color) = self
+            .remove_piece(mv.target_square)
+            .ok_or(anyhow!(error_message))?;
         self.put_piece(mv.starting_square, piece, color);
 
         match mv.flag {
             Flag::Capture(piece) => {
-                self.squares[mv.target_square] = Some(piece);
-                self.colors[mv.target_square] = Some(self.to_move.opposite_color());
+                self.put_piece(mv.target_square, piece, self.to_move.opposite_color());
             }
             Flag::EnPassantCapture => {
                 let captured_pawn_index = if self.to_move == Color::White {
@@ -348,72 +608,29 @@ impl Board {
                     mv.target_square + 8
                 };
 
-                self.squares[captured_pawn_index] = Some(Piece::Pawn);
-                self.colors[captured_pawn_index] = Some(self.to_move.opposite_color());
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
+                self.put_piece(captured_pawn_index, Piece::Pawn, self.to_move.opposite_color());
             }
             Flag::PromoteTo(_) => {
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
+                self.put_piece(mv.starting_square, Piece::Pawn, self.to_move);
             }
-            Flag::KingsideCastle => match self.to_move {
-                Color::White => {
-                    self.squares[Square::H1.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::H1.as_index()] = Some(Color::White);
-                    self.squares[Square::E1.as_index()] = Some(Piece::King);
-                    self.colors[Square::E1.as_index()] = Some(Color::White);
-                    self.squares[Square::F1.as_index()] = None;
-                    self.colors[Square::F1.as_index()] = None;
-                    self.squares[Square::G1.as_index()] = None;
-                    self.colors[Square::G1.as_index()] = None;
-                }
-                Color::Black => {
-                    self.squares[Square::H8.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::H8.as_index()] = Some(Color::Black);
-                    self.squares[Square::E8.as_index()] = Some(Piece::King);
-                    self.colors[Square::E8.as_index()] = Some(Color::Black);
-                    self.squares[Square::F8.as_index()] = None;
-                    self.colors[Square::F8.as_index()] = None;
-                    self.squares[Square::G8.as_index()] = None;
-                    self.colors[Square::G8.as_index()] = None;
-                }
-            },
-            Flag::QueensideCastle => match self.to_move {
-                Color::White => {
-                    self.squares[Square::A1.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::A1.as_index()] = Some(Color::White);
-                    self.squares[Square::E1.as_index()] = Some(Piece::King);
-                    self.colors[Square::E1.as_index()] = Some(Color::White);
-                    self.squares[Square::C1.as_index()] = None;
-                    self.colors[Square::C1.as_index()] = None;
-                    self.squares[Square::D1.as_index()] = None;
-                    self.colors[Square::D1.as_index()] = None;
-                }
-                Color::Black => {
-                    self.squares[Square::A8.as_index()] = Some(Piece::Rook);
-                    self.colors[Square::A8.as_index()] = Some(Color::Black);
-                    self.squares[Square::E8.as_index()] = Some(Piece::King);
-                    self.colors[Square::E8.as_index()] = Some(Color::Black);
-                    self.squares[Square::C8.as_index()] = None;
-                    self.colors[Square::C8.as_index()] = None;
-                    self.squares[Square::D8.as_index()] = None;
-                    self.colors[Square::D8.as_index()] = None;
-                }
-            },
             Flag::CaptureWithPromotion(captured_piece, _) => {
-                self.squares[mv.target_square] = Some(captured_piece);
-                self.colors[mv.target_square] = Some(self.to_move.opposite_color());
-                self.squares[mv.starting_square] = Some(Piece::Pawn);
-                self.colors[mv.starting_square] = Some(self.to_move);
+                self.put_piece(mv.target_square, captured_piece, self.to_move.opposite_color());
+                self.put_piece(mv.starting_square, Piece::Pawn, self.to_move);
             }
-            _ => {
-                self.squares[mv.target_square] = None;
-                self.colors[mv.target_square] = None;
+            _ => (),
+        }
+
+        // The board state from before the move still knows whether the captured piece was
+        // promoted
+        let captured_piece = match mv.flag {
+            Flag::Capture(piece) | Flag::CaptureWithPromotion(piece, _) => Some(piece),
+            Flag::EnPassantCapture => Some(Piece::Pawn),
+            _ => None,
+        };
+        if let (true, Some(piece)) = (self.variant.has_drops(), captured_piece) {
+            match self.board_state.promoted & (1 << mv.target_square) {
+                0 => self.pockets.remove(self.to_move, piece),
+                _ => self.pockets.remove(self.to_move, Piece::Pawn),
             }
         }
 
@@ -424,9 +641,130 @@ impl Board {
         Ok(())
     }
 
+    // Replaces whatever was on the square before
     pub fn put_piece(&mut self, square: usize, piece: Piece, color: Color) {
+        self.remove_piece(square);
         self.squares[square] = Some(piece);
         self.colors[square] = Some(color);
+        self.toggle_piece_keys(piece, color, square);
+        self.piece_scores
+            .add_piece_with_params(piece, color, square, &self.eval_params);
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.add_piece(piece, color, square);
+        }
+    }
+
+    pub fn remove_piece(&mut self, square: usize) -> Option<(Piece, Color)> {
+        let piece = self.squares[square].take()?;
+        let color = self.colors[square]
+            .take()
+            .expect("a piece on the board must have a color");
+        self.toggle_piece_keys(piece, color, square);
+        self.piece_scores
+            .remove_piece_with_params(piece, color, square, &self.eval_params);
+        if let Some(accumulator) = &mut self.accumulator {
+            accumulator.remove_piece(piece, color, square);
+        }
+
+        Some((piece, color))
+    }
+
+    fn toggle_piece_keys(&mut self, piece: Piece, color: Color, square: usize) {
+        let key = piece_key(piece, color, square);
+        self.board_state.zobrist_key ^= key;
+        if piece == Piece::Pawn {
+            self.board_state.pawn_key ^= key;
+        }
+    }
+
+    // Needed after loading different evaluation weights
+    pub fn set_eval_params(&mut self, params: Arc<EvalParams>) {
+        self.eval_params = params;
+        self.refresh_piece_scores();
+    }
+
+    pub fn refresh_piece_scores(&mut self) {
+        self.piece_scores = PieceScores::from_board(self);
+    }
+
+    // Evaluation goes through the network while one is attached, None switches back to the
+    // classical evaluation
+    pub fn attach_network(&mut self, network: Option<Arc<Network>>) {
+        self.accumulator = network.map(|network| Accumulator::new(network, self));
+    }
+
+    fn debug_assert_incremental_state(&self) {
+        debug_assert_eq!(
+            self.board_state.zobrist_key,
+            compute_zobrist_key(self),
+            "incrementally updated zobrist key does not match the board"
+        );
+        debug_assert_eq!(
+            self.board_state.pawn_key,
+            compute_pawn_key(self),
+            "incrementally updated pawn key does not match the board"
+        );
+        debug_assert_eq!(
+            self.piece_scores,
+            PieceScores::from_board(self),
+            "incrementally updated piece scores do not match the board"
+        );
+        if let Some(accumulator) = &self.accumulator {
+            debug_assert!(
+                accumulator.values() == Accumulator::new(accumulator.network(), self).values(),
+                "incrementally updated accumulator does not match the board"
+            );
+        }
+    }
+
+    // A position counts as repeated as soon as it occurs a second time, whether that is in the
+    // game history or further up the search path. Only positions since the last capture or pawn
+    // move can possibly repeat.
+    pub fn is_repetition(&self) -> bool {
+        self.board_state_history
+            .iter()
+            .rev()
+            .take(self.board_state.half_move_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|board_state| board_state.zobrist_key == self.board_state.zobrist_key)
+    }
+
+    pub fn is_fifty_move_rule_draw(&self) -> bool {
+        self.board_state.half_move_clock >= 100
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        match self.variant {
+            Variant::Standard => {}
+            // A lone knight can still give three checks, and atomic has mates of its own
+            Variant::ThreeCheck | Variant::Atomic => {
+                return self
+                    .squares
+                    .iter()
+                    .all(|piece| piece.is_none_or(|piece| piece == Piece::King))
+            }
+            // Captured pieces can be dropped back, and a bare king can still walk to the hill
+            Variant::Crazyhouse | Variant::KingOfTheHill => return false,
+        }
+        let mut minor_pieces = Vec::new();
+
+        for square in 0..64 {
+            match self.squares[square] {
+                None | Some(Piece::King) => {}
+                Some(piece @ (Piece::Knight | Piece::Bishop)) => minor_pieces.push((piece, square)),
+                Some(_) => return false,
+            }
+        }
+
+        match minor_pieces.as_slice() {
+            [] | [_] => true,
+            // Any number of bishops that all live on the same square color can never mate
+            [(_, first_square), ..] => minor_pieces.iter().all(|&(piece, square)| {
+                piece == Piece::Bishop
+                    && (square / 8 + square % 8) % 2 == (first_square / 8 + first_square % 8) % 2
+            }),
+        }
     }
 
     pub fn is_piece_at_square(&self, index: usize, piece: Piece, color: Color) -> bool {
@@ -436,6 +774,75 @@ impl Board {
         }
     }
 
+    // Like MoveGenerator::is_in_check, but looking outwards from the king so it is cheap
+    // enough to run after every move
+    pub fn is_king_attacked(&self, color: Color) -> bool {
+        let Some(king_square) = (0..64).find(|&sq| self.is_piece_at_square(sq, Piece::King, color))
+        else {
+            return false;
+        };
+        let enemy = color.opposite_color();
+        let (file, rank) = ((king_square % 8) as isize, (king_square / 8) as isize);
+        let square_at = |file: isize, rank: isize| {
+            ((0..8).contains(&file) && (0..8).contains(&rank)).then_some((rank * 8 + file) as usize)
+        };
+        let is_enemy = |file_offset: isize, rank_offset: isize, piece: Piece| {
+            square_at(file + file_offset, rank + rank_offset)
+                .is_some_and(|square| self.is_piece_at_square(square, piece, enemy))
+        };
+
+        let pawn_rank_offset = match color {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+        let knight_offsets = [
+            (1, 2),
+            (2, 1),
+            (2, -1),
+            (1, -2),
+            (-1, -2),
+            (-2, -1),
+            (-2, 1),
+            (-1, 2),
+        ];
+        let king_offsets = [
+            (0, 1),
+            (1, 0),
+            (0, -1),
+            (-1, 0),
+            (1, 1),
+            (1, -1),
+            (-1, -1),
+            (-1, 1),
+        ];
+        if is_enemy(-1, pawn_rank_offset, Piece::Pawn)
+            || is_enemy(1, pawn_rank_offset, Piece::Pawn)
+            || knight_offsets
+                .iter()
+                .any(|&(f, r)| is_enemy(f, r, Piece::Knight))
+            || king_offsets
+                .iter()
+                .any(|&(f, r)| is_enemy(f, r, Piece::King))
+        {
+            return true;
+        }
+
+        // The first piece in each direction, if it is a slider that moves that way
+        king_offsets.iter().any(|&(file_offset, rank_offset)| {
+            let slider = match file_offset == 0 || rank_offset == 0 {
+                true => Piece::Rook,
+                false => Piece::Bishop,
+            };
+            (1..8)
+                .map_while(|n| square_at(file + file_offset * n, rank + rank_offset * n))
+                .find(|&square| !self.is_square_empty(square))
+                .is_some_and(|square| {
+                    self.is_piece_at_square(square, slider, enemy)
+                        || self.is_piece_at_square(square, Piece::Queen, enemy)
+                })
+        })
+    }
+
     pub fn is_square_empty(&self, index: usize) -> bool {
         self.squares[index].is_none() && self.colors[index].is_none()
     }
@@ -450,82 +857,67 @@ impl Board {
         is_pawn_move || is_non_en_passant_capture
     }
 
-    // TODO: Refactor how the board stores castling priviledges so we can clean this up
-    fn make_kingside_castling_move(&mut self, mv: &Move) {
-        if let Color::White = self.to_move {
-            // Move the king
-            self.squares[Square::G1.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::G1.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::F1.as_index()] = self.squares[Square::H1.as_index()];
-            self.colors[Square::F1.as_index()] = self.colors[Square::H1.as_index()];
-            self.squares[Square::H1.as_index()] = None;
-            self.colors[Square::H1.as_index()] = None;
-
-            self.board_state.white_kingside_castling_priviledge = false;
-            self.board_state.white_queenside_castling_priviledge = false;
-        } else {
-            // Move the king
-            self.squares[Square::G8.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::G8.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::F8.as_index()] = self.squares[Square::H8.as_index()];
-            self.colors[Square::F8.as_index()] = self.colors[Square::H8.as_index()];
-            self.squares[Square::H8.as_index()] = None;
-            self.colors[Square::H8.as_index()] = None;
-
-            self.board_state.black_kingside_castling_priviledge = false;
-            self.board_state.black_queenside_castling_priviledge = false;
+    fn lose_castling_rights_of_rook(&mut self, square: usize, color: Color) {
+        let castling_squares = self.board_state.castling_squares;
+        let state = &mut self.board_state;
+        match color {
+            Color::White if square == castling_squares.kingside_rook[0] => {
+                state.white_kingside_castling_priviledge = false
+            }
+            Color::White if square == castling_squares.queenside_rook[0] => {
+                state.white_queenside_castling_priviledge = false
+            }
+            Color::Black if square == castling_squares.kingside_rook[1] => {
+                state.black_kingside_castling_priviledge = false
+            }
+            Color::Black if square == castling_squares.queenside_rook[1] => {
+                state.black_queenside_castling_priviledge = false
+            }
+            _ => {}
         }
+    }
 
-        if self.to_move == Color::White {
-            self.to_move = Color::Black;
-        } else {
-            self.to_move = Color::White;
-            self.full_move_number += 1;
+    fn make_castling_move(&mut self, mv: &Move, kingside: bool) {
+        let rook_square = self
+            .board_state
+            .castling_squares
+            .rook(self.to_move, kingside);
+        let (king_target, rook_target) = CastlingSquares::destinations(self.to_move, kingside);
+
+        // In Chess960 the king or the rook may end up where the other one started, so both
+        // come off the board before either goes back on
+        let king = self.remove_piece(mv.starting_square);
+        let rook = self.remove_piece(rook_square);
+        if let (Some((king, color)), Some((rook, _))) = (king, rook) {
+            self.put_piece(king_target, king, color);
+            self.put_piece(rook_target, rook, color);
         }
-    }
 
-    fn make_queenside_castling_move(&mut self, mv: &Move) {
-        if let Color::White = self.to_move {
-            // Move the king
-            self.squares[Square::C1.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::C1.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::D1.as_index()] = self.squares[Square::A1.as_index()];
-            self.colors[Square::D1.as_index()] = self.colors[Square::A1.as_index()];
-            self.squares[Square::A1.as_index()] = None;
-            self.colors[Square::A1.as_index()] = None;
-
-            self.board_state.white_kingside_castling_priviledge = false;
-            self.board_state.white_queenside_castling_priviledge = false;
-        } else {
-            // Move the king
-            self.squares[Square::C8.as_index()] = self.squares[mv.starting_square];
-            self.colors[Square::C8.as_index()] = self.colors[mv.starting_square];
-            self.squares[mv.starting_square] = None;
-            self.colors[mv.starting_square] = None;
-            // Move the rook
-            self.squares[Square::D8.as_index()] = self.squares[Square::A8.as_index()];
-            self.colors[Square::D8.as_index()] = self.colors[Square::A8.as_index()];
-            self.squares[Square::A8.as_index()] = None;
-            self.colors[Square::A8.as_index()] = None;
-
-            self.board_state.black_kingside_castling_priviledge = false;
-            self.board_state.black_queenside_castling_priviledge = false;
+        match self.to_move {
+            Color::White => {
+                self.board_state.white_kingside_castling_priviledge = false;
+                self.board_state.white_queenside_castling_priviledge = false;
+            }
+            Color::Black => {
+                self.board_state.black_kingside_castling_priviledge = false;
+                self.board_state.black_queenside_castling_priviledge = false;
+            }
         }
+        self.pass_turn();
+    }
 
-        if self.to_move == Color::White {
-            self.to_move = Color::Black;
-        } else {
-            self.to_move = Color::White;
-            self.full_move_number += 1;
+    fn unmake_castling_move(&mut self, mv: &Move, kingside: bool) {
+        let rook_square = self
+            .board_state
+            .castling_squares
+            .rook(self.to_move, kingside);
+        let (king_target, rook_target) = CastlingSquares::destinations(self.to_move, kingside);
+
+        let king = self.remove_piece(king_target);
+        let rook = self.remove_piece(rook_target);
+        if let (Some((king, color)), Some((rook, _))) = (king, rook) {
+            self.put_piece(mv.starting_square, king, color);
+            self.put_piece(rook_square, rook, color);
         }
     }
 }
@@ -541,18 +933,118 @@ pub struct BoardState {
     pub black_kingside_castling_priviledge: bool,
     pub white_queenside_castling_priviledge: bool,
     pub black_queenside_castling_priviledge: bool,
+    pub castling_squares: CastlingSquares,
+    // Squares holding a piece that was promoted, bit n for square n
+    pub promoted: u64,
+    // Three-check only, indexed by the Color giving the checks
+    pub checks_given: [u8; 2],
+    // Atomic only, the square, piece and color of everything the last capture blew up
+    pub exploded: Vec<(usize, Piece, Color)>,
+    pub zobrist_key: u64,
+    // Only covers the pawns, used to cache pawn structure evaluation
+    pub pawn_key: u64,
+}
+
+// Where the kings and rooks castle from, indexed by color. Always e1, h1 and a1 (and e8, h8
+// and a8) unless it's Chess960.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CastlingSquares {
+    pub king: [usize; 2],
+    pub kingside_rook: [usize; 2],
+    pub queenside_rook: [usize; 2],
 }
 
+impl Default for CastlingSquares {
+    fn default() -> Self {
+        Self {
+            king: [Square::E1.as_index(), Square::E8.as_index()],
+            kingside_rook: [Square::H1.as_index(), Square::H8.as_index()],
+            queenside_rook: [Square::A1.as_index(), Square::A8.as_index()],
+        }
+    }
+}
+
+impl CastlingSquares {
+    // The squares the king and the rook end up on, the same as in standard chess
+    pub fn destinations(color: Color, kingside: bool) -> (usize, usize) {
+        let back_rank = match color {
+            Color::White => 0,
+            Color::Black => 56,
+        };
+        match kingside {
+            true => (back_rank + 6, back_rank + 5),
+            false => (back_rank + 2, back_rank + 3),
+        }
+    }
+
+    pub fn rook(&self, color: Color, kingside: bool) -> usize {
+        match kingside {
+            true => self.kingside_rook[color as usize],
+            false => self.queenside_rook[color as usize],
+        }
+    }
+}
+
+// Captured pieces waiting to be dropped back on the board in crazyhouse, indexed by Color and
+// then Piece. Kings are never captured, so they don't get a slot.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Pockets {
+    pub counts: [[u8; 5]; 2],
+}
+
+impl Pockets {
+    pub fn count(&self, color: Color, piece: Piece) -> u8 {
+        self.counts[color as usize][piece as usize]
+    }
+
+    pub fn add(&mut self, color: Color, piece: Piece) {
+        self.counts[color as usize][piece as usize] += 1;
+    }
+
+    pub fn remove(&mut self, color: Color, piece: Piece) {
+        self.counts[color as usize][piece as usize] -= 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().flatten().all(|&count| count == 0)
+    }
+
+    // White's pieces then black's, each from the queen down, like QRRnpp
+    pub fn to_symbols(&self) -> String {
+        let mut symbols = String::new();
+        for color in [Color::White, Color::Black] {
+            for piece in POCKET_PIECES.into_iter().rev() {
+                for _ in 0..self.count(color, piece) {
+                    symbols.push(piece.to_symbol(color));
+                }
+            }
+        }
+        symbols
+    }
+}
+
+pub const POCKET_PIECES: [Piece; 5] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+];
+
 #[cfg(test)]
 mod tests {
     use crate::{
         board::Board,
         board_builder::BoardBuilder,
+        eval_params::EvalParams,
+        evaluate::PieceScores,
         move_generation::{Flag, Move},
         piece::{Color::*, Piece::*},
         square::Square::*,
+        variant::Variant,
     };
     use anyhow::Result;
+    use std::sync::Arc;
 
     #[test]
     fn test_starting_position_board_config() {
@@ -602,7 +1094,8 @@ mod tests {
     #[test]
     fn test_from_fen_empty_board() -> Result<()> {
         let empty_board = Board::default();
-        let empty_board_from_fen = BoardBuilder::try_from_fen("8/8/8/8/8/8/8/8 w - - 0 1")?;
+        let empty_board_from_fen =
+            BoardBuilder::try_from_fen_unchecked("8/8/8/8/8/8/8/8 w - - 0 1")?;
 
         assert_eq!(empty_board, empty_board_from_fen);
 
@@ -741,6 +1234,105 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_chess960_fen_round_trip() -> Result<()> {
+        // A Shredder-FEN reads back as X-FEN, where KQkq is enough to name the rooks
+        let board = BoardBuilder::try_from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        )?;
+        assert!(board.chess960);
+        assert_eq!(
+            board.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(
+            board.to_shredder_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"
+        );
+        assert!(BoardBuilder::try_from_fen(&board.to_fen())? == board);
+
+        // With two rooks on one side of the king, X-FEN names the inner one by its file
+        let fen = "1k1r1r2/8/8/8/8/8/8/1K1R1R2 w Dd - 0 1";
+        let board = BoardBuilder::try_from_fen(fen)?;
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.to_shredder_fen(), fen);
+
+        // Standard positions keep their usual FEN
+        let board = Board::starting_position();
+        assert!(!board.chess960);
+        assert_eq!(
+            board.to_shredder_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chess960_position() -> Result<()> {
+        let board = Board::chess960_position(518)?;
+        assert!(board.chess960);
+        assert_eq!(board.to_fen(), Board::starting_position().to_fen());
+
+        assert_eq!(
+            Board::chess960_position(0)?.to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Board::chess960_position(959)?.to_fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+        assert!(Board::chess960_position(960).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crazyhouse_fen_round_trip() -> Result<()> {
+        let fen = "r1b1k2r/ppp2ppp/2n5/3Q~4/8/8/PPP2PPP/RNB1K2R[NPbp] w KQkq - 0 8";
+        let board = BoardBuilder::try_from_fen(fen)?;
+        assert_eq!(board.variant, Variant::Crazyhouse);
+        assert_eq!(board.pockets.count(White, Knight), 1);
+        assert_eq!(board.pockets.count(Black, Bishop), 1);
+        assert_eq!(board.board_state.promoted, 1 << D5.as_index());
+        assert_eq!(board.to_fen(), fen);
+
+        // The pockets may also be written as a ninth rank
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3/Qp w - - 0 1")?;
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K3[Qp] w - - 0 1");
+        assert!(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3[K] w - - 0 1").is_err());
+        assert!(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3[Q w - - 0 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crazyhouse_captures_fill_pockets() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/3r4/4Q~3/8/8/4K3[] b - - 0 1")?;
+        let original = board.clone();
+
+        let rook_move = Move::from_square(D5, E5, Flag::None);
+        board.move_piece(&rook_move);
+        let capture = Move::from_square(E4, E5, Flag::Capture(Rook));
+        board.move_piece(&capture);
+        assert_eq!(board.pockets.count(White, Rook), 1);
+        assert_eq!(board.board_state.promoted, 1 << E5.as_index());
+        let king_move = Move::from_square(E8, E7, Flag::None);
+        board.move_piece(&king_move);
+        let drop = Move::from_square(E2, E2, Flag::Drop(Rook));
+        board.move_piece(&drop);
+        assert_eq!(board.to_fen(), "8/4k3/8/4Q~3/8/8/4R3/4K3[] b - - 2 3");
+
+        for mv in [drop, king_move, capture, rook_move] {
+            board.unmake_move(&mv)?;
+        }
+        assert!(board == original);
+
+        // The promoted queen goes into black's pocket as a pawn
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/4r3/4Q~3/8/8/4K3[] b - - 0 1")?;
+        board.move_piece(&Move::from_square(E5, E4, Flag::Capture(Queen)));
+        assert_eq!(board.pockets.count(Black, Pawn), 1);
+        assert_eq!(board.pockets.count(Black, Queen), 0);
+        Ok(())
+    }
+
     #[test]
     fn test_pawn_double_push_registers_en_passant_square() {
         let mut board = Board::starting_position();
@@ -1504,4 +2096,43 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_piece_scores_follow_captures_and_promotions() -> Result<()> {
+        let mut board: Board = BoardBuilder::default()
+            .piece(G1, King, White)
+            .piece(B7, Pawn, White)
+            .piece(A8, Rook, Black)
+            .piece(G8, King, Black)
+            .try_into()?;
+        let starting_piece_scores = board.piece_scores.clone();
+        let mv = Move::from_square(B7, A8, Flag::CaptureWithPromotion(Rook, Queen));
+
+        board.move_piece(&mv);
+        assert_eq!(board.piece_scores, PieceScores::from_board(&board));
+        assert_eq!(board.piece_scores.piece_counts[White as usize][Queen as usize], 1);
+        assert_eq!(board.piece_scores.piece_counts[Black as usize][Rook as usize], 0);
+
+        board.unmake_move(&mv)?;
+        assert_eq!(board.piece_scores, starting_piece_scores);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_eval_params_rescores_the_board() -> Result<()> {
+        let mut board = Board::starting_position();
+        let mut params = EvalParams::default();
+        params.material[Queen as usize] = params.material[Queen as usize] * 2;
+        let queen_value = params.material(Queen);
+
+        board.set_eval_params(Arc::new(params));
+        assert_eq!(board.piece_scores, PieceScores::from_board(&board));
+        assert_eq!(
+            board.piece_scores.material[White as usize],
+            Board::starting_position().piece_scores.material[White as usize] + queen_value / 2
+        );
+
+        Ok(())
+    }
 }
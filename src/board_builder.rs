@@ -1,32 +1,92 @@
-use crate::board::{Board, BoardState};
-use crate::move_generation::Move;
+use crate::board::{Board, BoardState, CastlingSquares, Pockets};
+use crate::epd::{parse_move, parse_operations, Epd};
+use crate::eval_params::eval_params;
+use crate::evaluate::PieceScores;
+use crate::move_generation::{Move, MoveGenerator};
 use crate::piece::{Color, Piece};
 use crate::square::Square;
+use crate::variant::{Variant, CHECKS_TO_WIN};
 use anyhow::{anyhow, bail, Result};
-use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+// Why a position can't come up in a game. Move generation and search assume none of these
+// happen, e.g. they panic when a king is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank(Square),
+    CastlingWithoutKing(Color),
+    CastlingWithoutRook(Color, Square),
+    ImpossibleEnPassantSquare(Square),
+    OpponentInCheck(Color),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{color:?} has no king"),
+            PositionError::TooManyKings(color) => write!(f, "{color:?} has more than one king"),
+            PositionError::PawnOnBackRank(square) => {
+                write!(f, "pawn on the first or eighth rank at {square:?}")
+            }
+            PositionError::CastlingWithoutKing(color) => {
+                write!(f, "{color:?} can castle but the king is not on its home square")
+            }
+            PositionError::CastlingWithoutRook(color, square) => {
+                write!(f, "{color:?} can castle but there is no rook on {square:?}")
+            }
+            PositionError::ImpossibleEnPassantSquare(square) => {
+                write!(f, "no pawn can have just moved past the en passant square {square:?}")
+            }
+            PositionError::OpponentInCheck(color) => {
+                write!(f, "{color:?} is in check but it is not their move")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
 
 pub struct BoardBuilder {
     board: Board,
+    validate: bool,
 }
 
 impl BoardBuilder {
     pub fn new() -> Self {
         Self {
             board: Board::default(),
+            validate: true,
         }
     }
 
     pub fn from_starting_position() -> Self {
         Self {
             board: Board::starting_position(),
+            validate: true,
         }
     }
 
     pub fn build_from(board: Board) -> Self {
-        Self { board }
+        Self {
+            board,
+            validate: true,
+        }
+    }
+
+    // For puzzles and studies that need positions that can't come up in a game. Move
+    // generation still needs both kings.
+    pub fn skip_validation(mut self) -> Self {
+        self.validate = false;
+        self
     }
 
     pub fn make_move(mut self, mv: Move) -> Self {
+        // Pieces may have been placed since the last move, so the key saved to the board
+        // history has to be brought up to date first
+        self.board.refresh_zobrist_keys();
         self.board.move_piece(&mv);
         self
     }
@@ -75,42 +135,43 @@ impl BoardBuilder {
     }
 
     pub fn try_from_fen(fen: &str) -> Result<Board> {
+        let board = Self::try_from_fen_unchecked(fen)?;
+        validate_position(&board)?;
+        Ok(board)
+    }
+
+    // Like try_from_fen, but accepts positions that can't come up in a game
+    pub fn try_from_fen_unchecked(fen: &str) -> Result<Board> {
         // 0: board arrangement
         // 1: active color
         // 2: Castling availability
         // 3: En passant square
         // 4: Halfmove clock
         // 5: Fullmove number
+        // 6: Checks given by white and black, only in Three-check
         let fen_string_fields: Vec<&str> = fen.split_whitespace().collect();
+        if fen_string_fields.len() < 6 {
+            bail!("FEN needs six fields: {fen}");
+        }
 
         let mut squares: [Option<Piece>; 64] = [None; 64];
         let mut colors: [Option<Color>; 64] = [None; 64];
+        let mut promoted = 0;
         let mut file = 0;
         let mut rank = 7;
+        let (placement, pocket) = Self::split_pocket(fen_string_fields[0])?;
 
-        for symbol in fen_string_fields[0].chars() {
+        for symbol in placement.chars() {
             match symbol {
                 '/' => {
                     file = 0;
                     rank -= 1;
                 }
                 '1'..='8' => file += symbol.to_digit(10).unwrap(),
+                // Crazyhouse marks promoted pieces with a tilde
+                '~' if file > 0 => promoted |= 1 << (rank * 8 + file as usize - 1),
                 piece_char => {
-                    let (piece, color) = match piece_char {
-                        'P' => (Piece::Pawn, Color::White),
-                        'p' => (Piece::Pawn, Color::Black),
-                        'N' => (Piece::Knight, Color::White),
-                        'n' => (Piece::Knight, Color::Black),
-                        'B' => (Piece::Bishop, Color::White),
-                        'b' => (Piece::Bishop, Color::Black),
-                        'R' => (Piece::Rook, Color::White),
-                        'r' => (Piece::Rook, Color::Black),
-                        'Q' => (Piece::Queen, Color::White),
-                        'q' => (Piece::Queen, Color::Black),
-                        'K' => (Piece::King, Color::White),
-                        'k' => (Piece::King, Color::Black),
-                        _ => bail!("invalid piece symbol in FEN"),
-                    };
+                    let (piece, color) = Self::parse_piece_symbol(piece_char)?;
 
                     let index = rank * 8 + file as usize;
                     squares[index] = Some(piece);
@@ -121,6 +182,14 @@ impl BoardBuilder {
             }
         }
 
+        let mut pockets = Pockets::default();
+        for symbol in pocket.unwrap_or_default().chars() {
+            match Self::parse_piece_symbol(symbol)? {
+                (Piece::King, _) => bail!("kings can't be in a pocket"),
+                (piece, color) => pockets.add(color, piece),
+            }
+        }
+
         let to_move = match fen_string_fields[1] {
             "w" => Color::White,
             "b" => Color::Black,
@@ -129,15 +198,6 @@ impl BoardBuilder {
             }
         };
 
-        let valid_casting_right_chars: HashSet<char> =
-            ['K', 'Q', 'k', 'q', '-'].iter().cloned().collect();
-        let castling_rights: HashSet<char> = fen_string_fields[2].chars().collect();
-        if !castling_rights.is_subset(&valid_casting_right_chars) {
-            bail!(
-                "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q' or '-'",
-            );
-        }
-
         let half_move_clock: u32 = fen_string_fields[4]
             .parse()
             .map_err(|_| anyhow!("failed to parse half move clock from fen"))?;
@@ -146,7 +206,17 @@ impl BoardBuilder {
             .parse()
             .map_err(|_| anyhow!("failed to parse full move number from fen"))?;
 
-        Ok(Board {
+        let checks_given = fen_string_fields
+            .get(6)
+            .map(|field| Self::parse_checks_given(field))
+            .transpose()?;
+        let variant = match (pocket, checks_given) {
+            (Some(_), _) => Variant::Crazyhouse,
+            (None, Some(_)) => Variant::ThreeCheck,
+            (None, None) => Variant::Standard,
+        };
+
+        let mut board = Board {
             squares,
             colors,
             to_move,
@@ -154,16 +224,227 @@ impl BoardBuilder {
             board_state: BoardState {
                 captured_piece: None,
                 en_passant_square: Self::parse_en_passant_square(fen_string_fields[3])?,
-                white_kingside_castling_priviledge: castling_rights.contains(&'K'),
-                black_kingside_castling_priviledge: castling_rights.contains(&'k'),
-                white_queenside_castling_priviledge: castling_rights.contains(&'Q'),
-                black_queenside_castling_priviledge: castling_rights.contains(&'q'),
+                white_kingside_castling_priviledge: false,
+                black_kingside_castling_priviledge: false,
+                white_queenside_castling_priviledge: false,
+                black_queenside_castling_priviledge: false,
+                castling_squares: CastlingSquares::default(),
+                promoted,
+                checks_given: checks_given.unwrap_or_default(),
+                exploded: Vec::new(),
                 half_move_clock,
+                zobrist_key: 0,
+                pawn_key: 0,
             },
             board_state_history: Vec::new(),
+            piece_scores: PieceScores::default(),
+            accumulator: None,
+            chess960: false,
+            variant,
+            pockets,
+            eval_params: eval_params(),
+        };
+        Self::parse_castling_rights(&mut board, fen_string_fields[2])?;
+        board.piece_scores = PieceScores::from_board(&board);
+        board.refresh_zobrist_keys();
+
+        Ok(board)
+    }
+
+    pub fn try_from_epd(epd: &str) -> Result<Epd> {
+        // The first four fields are the same as in a FEN, the move counters come from the
+        // hmvc and fmvn operations instead. Some suites write a full FEN anyway.
+        let mut fields = epd.trim().splitn(5, char::is_whitespace);
+        let position: Vec<&str> = fields.by_ref().take(4).collect();
+        if position.len() < 4 {
+            bail!("EPD needs at least four fields: {epd}");
+        }
+        let mut rest = fields.next().unwrap_or("").trim_start();
+        let mut counters: Vec<&str> = Vec::new();
+        for _ in 0..2 {
+            match rest.split_once(char::is_whitespace) {
+                Some((number, after)) if number.parse::<u32>().is_ok() => {
+                    counters.push(number);
+                    rest = after.trim_start();
+                }
+                None if rest.parse::<u32>().is_ok() => {
+                    counters.push(rest);
+                    rest = "";
+                }
+                _ => break,
+            }
+        }
+
+        let operations = parse_operations(rest)?;
+        let operand = |opcode: &str| {
+            operations
+                .iter()
+                .find(|(name, _)| name == opcode)
+                .and_then(|(_, operands)| operands.first())
+                .map(String::as_str)
+        };
+        let half_move_clock = counters.first().copied().or(operand("hmvc")).unwrap_or("0");
+        let full_move_number = counters.get(1).copied().or(operand("fmvn")).unwrap_or("1");
+        let board = Self::try_from_fen(&format!(
+            "{} {half_move_clock} {full_move_number}",
+            position.join(" ")
+        ))?;
+
+        let parse_moves = |opcode: &str| -> Result<Vec<Move>> {
+            let operands = operations
+                .iter()
+                .find(|(name, _)| name == opcode)
+                .map(|(_, operands)| operands.as_slice())
+                .unwrap_or_default();
+            operands
+                .iter()
+                .map(|text| parse_move(text, &board).map_err(|e| anyhow!("{opcode}: {e}")))
+                .collect()
+        };
+        let best_moves = parse_moves("bm")?;
+        let avoid_moves = parse_moves("am")?;
+
+        // The principal variation is played out move by move
+        let mut principal_variation = Vec::new();
+        let mut pv_board = board.clone();
+        for (_, operands) in operations.iter().filter(|(name, _)| name == "pv") {
+            for text in operands {
+                let mv = parse_move(text, &pv_board).map_err(|e| anyhow!("pv: {e}"))?;
+                pv_board.move_piece(&mv);
+                principal_variation.push(mv);
+            }
+        }
+
+        fn parse_number<T: FromStr>(opcode: &str, text: Option<&str>) -> Result<Option<T>> {
+            text.map(|text| {
+                text.parse()
+                    .map_err(|_| anyhow!("{opcode} is not a number: {text}"))
+            })
+            .transpose()
+        }
+        let analysis_depth = parse_number("acd", operand("acd"))?;
+        let centipawn_evaluation = parse_number("ce", operand("ce"))?;
+
+        Ok(Epd {
+            id: operand("id").map(str::to_string),
+            comment: operand("c0").map(str::to_string),
+            board,
+            best_moves,
+            avoid_moves,
+            principal_variation,
+            analysis_depth,
+            centipawn_evaluation,
+            operations,
         })
     }
 
+    // Crazyhouse FENs end the board with the pockets, either in brackets (.../RNBQKBNR[Pq]) or
+    // as a ninth rank (.../RNBQKBNR/Pq). None if there are no pockets at all.
+    fn split_pocket(placement: &str) -> Result<(&str, Option<&str>)> {
+        if let Some((board, pocket)) = placement.split_once('[') {
+            let pocket = pocket
+                .strip_suffix(']')
+                .ok_or(anyhow!("pocket in fen is never closed"))?;
+            return Ok((board, Some(pocket)));
+        }
+        match placement.match_indices('/').nth(7) {
+            Some((index, _)) => Ok((&placement[..index], Some(&placement[index + 1..]))),
+            None => Ok((placement, None)),
+        }
+    }
+
+    // +N+M, the number of checks white and black have given
+    fn parse_checks_given(field: &str) -> Result<[u8; 2]> {
+        let error = || anyhow!("failed to parse checks given from fen, must be like '+1+0'");
+        let (white, black) = field
+            .strip_prefix('+')
+            .and_then(|field| field.split_once('+'))
+            .ok_or_else(error)?;
+        let parse = |checks: &str| match checks.parse() {
+            Ok(checks) if checks <= CHECKS_TO_WIN => Ok(checks),
+            _ => Err(error()),
+        };
+        Ok([parse(white)?, parse(black)?])
+    }
+
+    fn parse_piece_symbol(symbol: char) -> Result<(Piece, Color)> {
+        let piece = match symbol.to_ascii_lowercase() {
+            'p' => Piece::Pawn,
+            'n' => Piece::Knight,
+            'b' => Piece::Bishop,
+            'r' => Piece::Rook,
+            'q' => Piece::Queen,
+            'k' => Piece::King,
+            _ => bail!("invalid piece symbol in FEN"),
+        };
+        match symbol.is_ascii_uppercase() {
+            true => Ok((piece, Color::White)),
+            false => Ok((piece, Color::Black)),
+        }
+    }
+
+    // KQkq, where K and Q stand for the outermost rook on that side of the king (X-FEN), or the
+    // files of the castling rooks (Shredder-FEN). Anything but e1, h1 and a1 makes it Chess960.
+    fn parse_castling_rights(board: &mut Board, field: &str) -> Result<()> {
+        let mut shredder = false;
+        for symbol in field.chars().filter(|&symbol| symbol != '-') {
+            let color = match symbol.is_ascii_uppercase() {
+                true => Color::White,
+                false => Color::Black,
+            };
+            let back_rank = match color {
+                Color::White => 0..8,
+                Color::Black => 56..64,
+            };
+            let is_own =
+                |square: &usize, piece: Piece| board.is_piece_at_square(*square, piece, color);
+            let default_squares = CastlingSquares::default();
+            let king_square = back_rank
+                .clone()
+                .find(|square| is_own(square, Piece::King))
+                .unwrap_or(default_squares.king[color as usize]);
+
+            // Without a king or rook to castle with the rights stay on the standard squares,
+            // where validation complains about them
+            let rook_square = match symbol.to_ascii_uppercase() {
+                'K' => (king_square + 1..back_rank.end)
+                    .rev()
+                    .find(|square| is_own(square, Piece::Rook))
+                    .unwrap_or(default_squares.kingside_rook[color as usize]),
+                'Q' => (back_rank.start..king_square)
+                    .find(|square| is_own(square, Piece::Rook))
+                    .unwrap_or(default_squares.queenside_rook[color as usize]),
+                file @ 'A'..='H' => {
+                    shredder = true;
+                    back_rank.start + (file as u8 - b'A') as usize
+                }
+                _ => bail!(
+                    "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', 'q' and rook files, or '-'",
+                ),
+            };
+
+            let state = &mut board.board_state;
+            state.castling_squares.king[color as usize] = king_square;
+            if rook_square > king_square {
+                state.castling_squares.kingside_rook[color as usize] = rook_square;
+                match color {
+                    Color::White => state.white_kingside_castling_priviledge = true,
+                    Color::Black => state.black_kingside_castling_priviledge = true,
+                }
+            } else {
+                state.castling_squares.queenside_rook[color as usize] = rook_square;
+                match color {
+                    Color::White => state.white_queenside_castling_priviledge = true,
+                    Color::Black => state.black_queenside_castling_priviledge = true,
+                }
+            }
+        }
+
+        board.chess960 =
+            shredder || board.board_state.castling_squares != CastlingSquares::default();
+        Ok(())
+    }
+
     fn parse_en_passant_square(en_passant_sqaure_field: &str) -> Result<Option<usize>> {
         if en_passant_sqaure_field == "-" {
             return Ok(None);
@@ -177,12 +458,92 @@ impl BoardBuilder {
 
 impl TryInto<Board> for BoardBuilder {
     type Error = anyhow::Error;
-    fn try_into(self) -> Result<Board, Self::Error> {
-        // TODO: Add checks for invalid board states
+    fn try_into(mut self) -> Result<Board, Self::Error> {
+        if self.validate {
+            validate_position(&self.board)?;
+        }
+        self.board.refresh_zobrist_keys();
         Ok(self.board)
     }
 }
 
+pub fn validate_position(board: &Board) -> Result<(), PositionError> {
+    let pieces_at = |piece: Piece, color: Color| {
+        (0..64)
+            .filter(move |&square| {
+                board.squares[square] == Some(piece) && board.colors[square] == Some(color)
+            })
+            .collect::<Vec<usize>>()
+    };
+
+    for color in [Color::White, Color::Black] {
+        match pieces_at(Piece::King, color).len() {
+            0 => return Err(PositionError::MissingKing(color)),
+            1 => {}
+            _ => return Err(PositionError::TooManyKings(color)),
+        }
+    }
+
+    for color in [Color::White, Color::Black] {
+        if let Some(&square) = pieces_at(Piece::Pawn, color)
+            .iter()
+            .find(|&&square| !(8..56).contains(&square))
+        {
+            return Err(PositionError::PawnOnBackRank(Square::from_index(square)));
+        }
+    }
+
+    let state = &board.board_state;
+    let castling_rights = [
+        (Color::White, state.white_kingside_castling_priviledge, true),
+        (Color::White, state.white_queenside_castling_priviledge, false),
+        (Color::Black, state.black_kingside_castling_priviledge, true),
+        (Color::Black, state.black_queenside_castling_priviledge, false),
+    ];
+    for (color, can_castle, kingside) in castling_rights {
+        if !can_castle {
+            continue;
+        }
+        let king_square = state.castling_squares.king[color as usize];
+        let rook_square = state.castling_squares.rook(color, kingside);
+        if !pieces_at(Piece::King, color).contains(&king_square) {
+            return Err(PositionError::CastlingWithoutKing(color));
+        }
+        if !pieces_at(Piece::Rook, color).contains(&rook_square) {
+            return Err(PositionError::CastlingWithoutRook(
+                color,
+                Square::from_index(rook_square),
+            ));
+        }
+    }
+
+    // The pawn that just moved two squares stands in front of the en passant square, and the
+    // square it came from and the one it passed are both empty
+    if let Some(en_passant_square) = state.en_passant_square {
+        let (rank, pawn_square, origin_square) = match board.to_move {
+            Color::White => (5, en_passant_square.wrapping_sub(8), en_passant_square + 8),
+            Color::Black => (2, en_passant_square + 8, en_passant_square.wrapping_sub(8)),
+        };
+        let pawn_color = board.to_move.opposite_color();
+        if en_passant_square / 8 != rank
+            || !pieces_at(Piece::Pawn, pawn_color).contains(&pawn_square)
+            || board.squares[en_passant_square].is_some()
+            || board.squares[origin_square].is_some()
+        {
+            return Err(PositionError::ImpossibleEnPassantSquare(Square::from_index(
+                en_passant_square,
+            )));
+        }
+    }
+
+    let opponent = board.to_move.opposite_color();
+    if MoveGenerator::new(board.clone()).is_in_check(opponent) {
+        return Err(PositionError::OpponentInCheck(opponent));
+    }
+
+    Ok(())
+}
+
 impl Default for BoardBuilder {
     fn default() -> Self {
         Self::new()
@@ -191,8 +552,56 @@ impl Default for BoardBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::board_builder::BoardBuilder;
+    use crate::board::Board;
+    use crate::board_builder::{BoardBuilder, PositionError};
+    use crate::move_generation::{Flag, Move};
+    use crate::piece::{Color::*, Piece};
     use crate::square::Square;
+    use anyhow::Result;
+
+    #[test]
+    fn test_from_epd() -> Result<()> {
+        let epd = BoardBuilder::try_from_epd(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001"; c0 "mate in 3"; acd 12; ce 32000; pv Qg6 fxg6 Nxg6+;"#,
+        )?;
+
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        assert_eq!(epd.comment.as_deref(), Some("mate in 3"));
+        assert_eq!(
+            epd.best_moves,
+            [Move::from_square(Square::G3, Square::G6, Flag::None)]
+        );
+        assert!(epd.avoid_moves.is_empty());
+        assert_eq!(epd.analysis_depth, Some(12));
+        assert_eq!(epd.centipawn_evaluation, Some(32000));
+        assert_eq!(epd.principal_variation.len(), 3);
+        assert_eq!(epd.operation("ce"), Some(["32000".to_string()].as_slice()));
+        assert_eq!(
+            epd.board.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+
+        // Move counters as FEN fields or as operations, and moves in coordinates
+        let epd = BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R w K - 3 40 am e1g1")?;
+        assert_eq!(epd.board.to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 3 40");
+        assert_eq!(
+            epd.avoid_moves,
+            [Move::from_square(
+                Square::E1,
+                Square::G1,
+                Flag::KingsideCastle
+            )]
+        );
+        let epd = BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R b K - hmvc 7; fmvn 12;")?;
+        assert_eq!(epd.board.to_fen(), "4k3/8/8/8/8/8/8/4K2R b K - 7 12");
+
+        assert!(BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R w K").is_err());
+        assert!(BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R w K - bm Ke3").is_err());
+        // Scores that don't fit are an error rather than wrapping around
+        assert!(BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R w K - ce 4294967296").is_err());
+        assert!(BoardBuilder::try_from_epd("4k3/8/8/8/8/8/8/4K2R w K - acd -1").is_err());
+        Ok(())
+    }
 
     #[test]
     fn test_from_fen_invalid_piece_position_char() {
@@ -237,10 +646,81 @@ mod tests {
 
         assert_eq!(
             board.err().unwrap().to_string(),
-            "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', and 'q' or '-'"
+            "invalid castling rights in fen, must be a combination of 'K', 'Q', 'k', 'q' and rook files, or '-'"
         )
     }
 
+    #[test]
+    fn test_from_fen_rejects_illegal_positions() {
+        let cases = [
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", PositionError::MissingKing(Black)),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", PositionError::TooManyKings(White)),
+            (
+                "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+                PositionError::PawnOnBackRank(Square::A1),
+            ),
+            (
+                "p3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                PositionError::PawnOnBackRank(Square::A8),
+            ),
+            (
+                "4k3/8/8/8/8/8/3K4/7R w K - 0 1",
+                PositionError::CastlingWithoutKing(White),
+            ),
+            (
+                "4k2r/8/8/8/8/8/8/4K3 w q - 0 1",
+                PositionError::CastlingWithoutRook(Black, Square::A8),
+            ),
+            (
+                "r3k3/8/8/8/8/8/8/4K3 w kq - 0 1",
+                PositionError::CastlingWithoutRook(Black, Square::H8),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+                PositionError::ImpossibleEnPassantSquare(Square::E6),
+            ),
+            (
+                "4k3/8/8/4p3/8/8/8/4K3 w - e3 0 1",
+                PositionError::ImpossibleEnPassantSquare(Square::E3),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4R1K1 w - - 0 1",
+                PositionError::OpponentInCheck(Black),
+            ),
+        ];
+
+        for (fen, expected) in cases {
+            let error = BoardBuilder::try_from_fen(fen).unwrap_err();
+            assert_eq!(error.downcast_ref::<PositionError>(), Some(&expected), "{fen}");
+            assert!(BoardBuilder::try_from_fen_unchecked(fen).is_ok());
+        }
+        assert_eq!(
+            BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1")
+                .unwrap_err()
+                .to_string(),
+            "Black is in check but it is not their move"
+        );
+    }
+
+    #[test]
+    fn test_from_fen_accepts_legal_positions() -> Result<()> {
+        BoardBuilder::try_from_fen(
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+        )?;
+        BoardBuilder::try_from_fen("r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_validation_opt_out() -> Result<()> {
+        let builder = || BoardBuilder::new().piece(Square::A1, Piece::Knight, White);
+        let board: Result<Board> = builder().try_into();
+        assert!(board.is_err());
+        let board: Board = builder().skip_validation().try_into()?;
+        assert_eq!(board.squares[Square::A1.as_index()], Some(Piece::Knight));
+        Ok(())
+    }
+
     #[test]
     fn test_parse_en_passant_square_none() {
         let field = "-";
@@ -0,0 +1,440 @@
+// Polyglot opening books, so Talia does not have to think about the opening and plays
+// something different every game
+// Source: http://hgm.nubati.net/book_format.html
+//
+// A book is a list of 16 byte entries sorted by position key: the key, the move, a weight and
+// four bytes of learning data nobody uses, all big endian.
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    move_generation::{Flag, Move, MoveGenerator},
+    piece::{Color, Piece},
+    polyglot_keys::POLYGLOT_RANDOM,
+};
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const WHITE_TO_MOVE_OFFSET: usize = 780;
+
+static BOOK: RwLock<Option<Arc<Book>>> = RwLock::new(None);
+// Whether Talia plays from its book at all, as the UCI OwnBook option says
+pub static OWN_BOOK: AtomicBool = AtomicBool::new(false);
+// Stop using the book after this many moves
+pub static BOOK_DEPTH: AtomicU32 = AtomicU32::new(20);
+// Always play the most played move instead of picking one at random by weight
+pub static BOOK_BEST_MOVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            raw_move: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+}
+
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            bail!("book size is not a multiple of {ENTRY_SIZE} bytes");
+        }
+        let entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // Lookups rely on the entries being sorted by key
+        if entries.windows(2).any(|pair| pair[0].key > pair[1].key) {
+            bail!("book entries are not sorted by key");
+        }
+
+        Ok(Self { entries })
+    }
+
+    // Sorted by key like Polyglot wants, and the most played move first within a position
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Self {
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Self { entries }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.to_bytes())
+            .collect()
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries_for(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // The legal book moves in the position, along with their weights
+    pub fn moves(&self, move_generator: &mut MoveGenerator) -> Vec<(Move, u16)> {
+        let legal_moves = move_generator.generate_moves();
+        self.entries_for(polyglot_key(&move_generator.board))
+            .iter()
+            .filter_map(|entry| {
+                let mv = decode_move(entry.raw_move, &move_generator.board, &legal_moves)?;
+                Some((mv, entry.weight))
+            })
+            .collect()
+    }
+
+    // roll picks the move when choosing at random, any number will do
+    pub fn pick_move(
+        &self,
+        move_generator: &mut MoveGenerator,
+        best_move: bool,
+        roll: u64,
+    ) -> Option<Move> {
+        let moves = self.moves(move_generator);
+        if best_move {
+            // The first of the most played moves, so the choice does not depend on the book order
+            let best_weight = moves.iter().map(|(_, weight)| *weight).max()?;
+            return moves
+                .into_iter()
+                .find(|(_, weight)| *weight == best_weight)
+                .map(|(mv, _)| mv);
+        }
+
+        let total_weight: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total_weight == 0 {
+            return moves.into_iter().next().map(|(mv, _)| mv);
+        }
+        let mut roll = roll % total_weight;
+        for (mv, weight) in moves {
+            if roll < weight as u64 {
+                return Some(mv);
+            }
+            roll -= weight as u64;
+        }
+
+        None
+    }
+}
+
+// Returns how many entries the book has
+pub fn load_book(path: &str) -> Result<usize> {
+    let book = Book::open(path)?;
+    let entry_count = book.len();
+    *BOOK.write().unwrap() = Some(Arc::new(book));
+
+    Ok(entry_count)
+}
+
+pub fn clear_book() {
+    *BOOK.write().unwrap() = None;
+}
+
+pub fn active_book() -> Option<Arc<Book>> {
+    BOOK.read().unwrap().clone()
+}
+
+// The book move to play, if the book is in use and still has something to say
+pub fn probe_book(move_generator: &mut MoveGenerator) -> Option<Move> {
+    if !OWN_BOOK.load(Ordering::Relaxed)
+        || move_generator.board.full_move_number > BOOK_DEPTH.load(Ordering::Relaxed)
+        || !move_generator.board.variant.is_standard()
+        // Polyglot keys have no way to tell which rook may still castle in Chess960
+        || move_generator.board.chess960
+    {
+        return None;
+    }
+
+    let roll = RandomState::new().build_hasher().finish();
+    active_book()?.pick_move(move_generator, BOOK_BEST_MOVE.load(Ordering::Relaxed), roll)
+}
+
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+
+    for square in 0..64 {
+        if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+            // Black comes first for every kind of piece
+            let kind = 2 * piece as usize + (color == Color::White) as usize;
+            key ^= POLYGLOT_RANDOM[64 * kind + square];
+        }
+    }
+
+    let castling_priviledges = [
+        board.board_state.white_kingside_castling_priviledge,
+        board.board_state.white_queenside_castling_priviledge,
+        board.board_state.black_kingside_castling_priviledge,
+        board.board_state.black_queenside_castling_priviledge,
+    ];
+    for (offset, can_castle) in castling_priviledges.into_iter().enumerate() {
+        if can_castle {
+            key ^= POLYGLOT_RANDOM[CASTLING_OFFSET + offset];
+        }
+    }
+
+    // Polyglot only counts the en passant square when a pawn can actually take there
+    if let Some(square) = board.board_state.en_passant_square {
+        if can_capture_en_passant(board, square) {
+            key ^= POLYGLOT_RANDOM[EN_PASSANT_OFFSET + square % 8];
+        }
+    }
+
+    if board.to_move == Color::White {
+        key ^= POLYGLOT_RANDOM[WHITE_TO_MOVE_OFFSET];
+    }
+
+    key
+}
+
+fn can_capture_en_passant(board: &Board, en_passant_square: usize) -> bool {
+    // The capturing pawns stand next to the pawn that just moved two squares
+    let pawn_rank = match board.to_move {
+        Color::White => 4,
+        Color::Black => 3,
+    };
+    let file = en_passant_square % 8;
+    [file.checked_sub(1), (file < 7).then_some(file + 1)]
+        .into_iter()
+        .flatten()
+        .any(|file| board.is_piece_at_square(pawn_rank * 8 + file, Piece::Pawn, board.to_move))
+}
+
+// Castling is written as the king taking its own rook, which Chess960 moves already are
+pub fn encode_move(mv: &Move, board: &Board) -> u16 {
+    let target = match mv.flag {
+        _ if board.chess960 => mv.target_square,
+        Flag::KingsideCastle => mv.starting_square + 3,
+        Flag::QueensideCastle => mv.starting_square - 4,
+        _ => mv.target_square,
+    };
+    // Knight to queen are 1 to 4 in Polyglot, just like in Piece
+    let promotion = match mv.flag {
+        Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => piece as u16,
+        _ => 0,
+    };
+
+    (promotion << 12) | ((mv.starting_square as u16) << 6) | target as u16
+}
+
+// Moves are packed as to file, to rank, from file, from rank and promotion piece, three bits
+// each from the lowest bit up. Castling is written as the king taking its own rook.
+fn decode_move(raw_move: u16, board: &Board, legal_moves: &[Move]) -> Option<Move> {
+    let raw_move = raw_move as usize;
+    let mut target = raw_move & 0o77;
+    let start = (raw_move >> 6) & 0o77;
+    let promotion = match (raw_move >> 12) & 0b111 {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None,
+    };
+
+    // Chess960 castling moves already target the rook
+    if !board.chess960
+        && board.squares[start] == Some(Piece::King)
+        && board.colors[target] == board.colors[start]
+    {
+        target = if target > start { start + 2 } else { start - 2 };
+    }
+
+    legal_moves
+        .iter()
+        .find(|mv| {
+            let promoted_to = match mv.flag {
+                Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => Some(piece),
+                _ => None,
+            };
+            mv.starting_square == start && mv.target_square == target && promoted_to == promotion
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        piece::Piece,
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{encode_move, polyglot_key, Book};
+
+    fn key_after_moves(moves: &[&str]) -> Result<u64> {
+        let mut board = Board::starting_position();
+        for mv in moves {
+            let mv = Move::try_from_uci(mv, &mut MoveGenerator::new(board.clone()))?;
+            board.move_piece(&mv);
+        }
+        Ok(polyglot_key(&board))
+    }
+
+    fn entry(key: u64, raw_move: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend(raw_move.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    fn raw_move(start: Square, target: Square) -> u16 {
+        ((start as u16) << 6) | target as u16
+    }
+
+    #[test]
+    fn test_polyglot_keys_match_the_reference() -> Result<()> {
+        // The test positions from the Polyglot book format description
+        assert_eq!(key_after_moves(&[])?, 0x463b96181691fc9c);
+        assert_eq!(key_after_moves(&["e2e4"])?, 0x823c9b50fd114196);
+        assert_eq!(key_after_moves(&["e2e4", "d7d5"])?, 0x0756b94461c50fb0);
+        assert_eq!(key_after_moves(&["e2e4", "d7d5", "e4e5"])?, 0x662fafb965db29d4);
+        assert_eq!(
+            key_after_moves(&["e2e4", "d7d5", "e4e5", "f7f5"])?,
+            0x22a48b5a8e47ff78
+        );
+        assert_eq!(
+            key_after_moves(&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"])?,
+            0x652a607ca3f242c1
+        );
+        assert_eq!(
+            key_after_moves(&["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"])?,
+            0x00fdd303c946bdd9
+        );
+        assert_eq!(
+            key_after_moves(&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"])?,
+            0x3c8123ea7b067637
+        );
+        assert_eq!(
+            key_after_moves(&["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"])?,
+            0x5c3f9b829b279560
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_book_moves() -> Result<()> {
+        let start_key = 0x463b96181691fc9c;
+        let mut bytes = entry(1, 0, 1);
+        bytes.extend(entry(start_key, raw_move(Square::E2, Square::E4), 30));
+        bytes.extend(entry(start_key, raw_move(Square::D2, Square::D4), 10));
+        // Not a legal move, so it is left out
+        bytes.extend(entry(start_key, raw_move(Square::E2, Square::E5), 50));
+        let book = Book::from_bytes(&bytes)?;
+
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let e4 = Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush);
+        let d4 = Move::from_square(Square::D2, Square::D4, Flag::PawnDoublePush);
+
+        assert_eq!(
+            book.moves(&mut move_generator),
+            vec![(e4.clone(), 30), (d4.clone(), 10)]
+        );
+        assert_eq!(book.pick_move(&mut move_generator, true, 35), Some(e4.clone()));
+        assert_eq!(book.pick_move(&mut move_generator, false, 29), Some(e4));
+        assert_eq!(book.pick_move(&mut move_generator, false, 35), Some(d4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_castling_and_promotion_moves() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1")?;
+        let key = polyglot_key(&board);
+        let promotion = (3 << 12) | raw_move(Square::B7, Square::B8);
+        let mut bytes = entry(key, raw_move(Square::E1, Square::H1), 1);
+        bytes.extend(entry(key, promotion, 1));
+        let book = Book::from_bytes(&bytes)?;
+
+        let moves = book.moves(&mut MoveGenerator::new(board.clone()));
+        for (mv, _) in &moves {
+            assert!(bytes
+                .chunks(16)
+                .any(|entry| entry[8..10] == encode_move(mv, &board).to_be_bytes()));
+        }
+
+        assert_eq!(
+            moves,
+            vec![
+                (Move::from_square(Square::E1, Square::G1, Flag::KingsideCastle), 1),
+                (
+                    Move::from_square(Square::B7, Square::B8, Flag::PromoteTo(Piece::Rook)),
+                    1
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chess960_castling_moves() -> Result<()> {
+        // The king on b1 castles with the rooks on a1 and g1
+        let mut board = BoardBuilder::try_from_fen("1k6/8/8/8/8/8/8/RK4R1 w AG - 0 1")?;
+        board.chess960 = true;
+        let kingside = Move::from_square(Square::B1, Square::G1, Flag::KingsideCastle);
+        let queenside = Move::from_square(Square::B1, Square::A1, Flag::QueensideCastle);
+
+        assert_eq!(encode_move(&kingside, &board), raw_move(Square::B1, Square::G1));
+        assert_eq!(encode_move(&queenside, &board), raw_move(Square::B1, Square::A1));
+
+        let key = polyglot_key(&board);
+        let book = Book::from_bytes(&entry(key, raw_move(Square::B1, Square::G1), 1))?;
+        assert_eq!(book.moves(&mut MoveGenerator::new(board)), vec![(kingside, 1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_books() {
+        assert!(Book::from_bytes(&[0; 15]).is_err());
+
+        let mut bytes = entry(2, 0, 1);
+        bytes.extend(entry(1, 0, 1));
+        assert!(Book::from_bytes(&bytes).is_err());
+    }
+}
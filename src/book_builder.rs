@@ -0,0 +1,264 @@
+// Builds a Polyglot opening book out of PGN game collections. Every move played in the first
+// plies of a game is counted for the position it was played in, along with how it scored for
+// the side that played it. Moves that were not played often enough or scored too badly are
+// dropped, and the rest are weighted by the points they scored, like Polyglot itself does.
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    book::{encode_move, polyglot_key, Book, BookEntry},
+    move_generation::{Move, MoveGenerator},
+    pgn::{parse_pgn, PgnGame},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BookBuildOptions {
+    // Only the moves of the first this many plies of every game are counted
+    pub max_ply: u32,
+    pub min_games: u32,
+    // The fraction of the points the move has to score for the side playing it, from 0 to 1
+    pub min_score: f64,
+}
+
+impl Default for BookBuildOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 20,
+            min_games: 3,
+            min_score: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct MoveStats {
+    games: u32,
+    half_points: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    options: BookBuildOptions,
+    stats: HashMap<(u64, u16), MoveStats>,
+    games_added: usize,
+    games_skipped: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BookBuildOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    // Games of other variants, without a result or with an illegal move in the counted plies
+    // are left out
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<()> {
+        let mut board = game.starting_board()?;
+        // Polyglot books only know standard chess
+        if board.chess960 || !board.variant.is_standard() {
+            bail!("game is not standard chess");
+        }
+
+        let mut played = Vec::new();
+        for pgn_move in game.moves.iter().take(self.options.max_ply as usize) {
+            let Some(half_points) = game.result.half_points(board.to_move) else {
+                bail!("game has no result");
+            };
+            let mut move_generator = MoveGenerator::new(board.clone());
+            let mv = Move::try_from_san(&pgn_move.san, &mut move_generator)?;
+            played.push((polyglot_key(&board), encode_move(&mv, &board), half_points));
+            board.move_piece(&mv);
+        }
+
+        for (key, raw_move, half_points) in played {
+            let stats = self.stats.entry((key, raw_move)).or_default();
+            stats.games += 1;
+            stats.half_points += half_points;
+        }
+        self.games_added += 1;
+
+        Ok(())
+    }
+
+    pub fn add_pgn(&mut self, text: &str) -> Result<()> {
+        for game in parse_pgn(text)? {
+            if self.add_game(&game).is_err() {
+                self.games_skipped += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn games_added(&self) -> usize {
+        self.games_added
+    }
+
+    pub fn games_skipped(&self) -> usize {
+        self.games_skipped
+    }
+
+    pub fn build(&self) -> Book {
+        let mut kept: Vec<(u64, u16, MoveStats)> = self
+            .stats
+            .iter()
+            .filter(|(_, stats)| {
+                let score = stats.half_points as f64 / (2 * stats.games) as f64;
+                stats.games >= self.options.min_games && score >= self.options.min_score
+            })
+            .map(|(&(key, raw_move), &stats)| (key, raw_move, stats))
+            .collect();
+        kept.sort_unstable_by_key(|&(key, raw_move, _)| (key, raw_move));
+
+        // Weights only have 16 bits, so popular positions are scaled down to fit. Moves that
+        // only ever lost still get a small weight, or they could never be picked.
+        let mut max_points: HashMap<u64, u32> = HashMap::new();
+        for (key, _, stats) in &kept {
+            let max = max_points.entry(*key).or_default();
+            *max = (*max).max(stats.half_points);
+        }
+        let entries = kept
+            .into_iter()
+            .map(|(key, raw_move, stats)| {
+                let scale = max_points[&key].div_ceil(u16::MAX as u32).max(1);
+                BookEntry {
+                    key,
+                    raw_move,
+                    weight: (stats.half_points / scale).max(1) as u16,
+                    learn: 0,
+                }
+            })
+            .collect();
+
+        Book::from_entries(entries)
+    }
+}
+
+pub fn run_book_build(
+    pgn_paths: &[String],
+    output_path: &str,
+    options: BookBuildOptions,
+) -> Result<()> {
+    let mut builder = BookBuilder::new(options);
+    for path in pgn_paths {
+        builder.add_pgn(&std::fs::read_to_string(path)?)?;
+    }
+    if builder.games_added() == 0 {
+        bail!("no usable games found");
+    }
+
+    let book = builder.build();
+    book.save(output_path)?;
+    println!(
+        "Read {} games, skipped {} of other variants, without a result or with an illegal move",
+        builder.games_added() + builder.games_skipped(),
+        builder.games_skipped()
+    );
+    println!("Wrote {} book entries to {output_path}", book.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        book::Book,
+        move_generation::{Flag, Move, MoveGenerator},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{BookBuildOptions, BookBuilder};
+
+    const GAMES: &str = r#"
+[Event "1"]
+1. e4 e5 2. Nf3 1-0
+
+[Event "2"]
+1. e4 c5 0-1
+
+[Event "3"]
+1. e4 e5 1/2-1/2
+
+[Event "4"]
+1. d4 d5 0-1
+
+[Event "5"]
+1. e4 Ke7 *
+
+[Event "6"]
+1. e4 Ke2 1-0
+
+[Event "7"]
+[Variant "King of the Hill"]
+1. e4 e5 1-0
+
+[Event "8"]
+[Variant "Chess960"]
+[FEN "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"]
+1. e4 e5 1-0
+"#;
+
+    fn build(options: BookBuildOptions) -> Result<(BookBuilder, Book)> {
+        let mut builder = BookBuilder::new(options);
+        builder.add_pgn(GAMES)?;
+        let book = builder.build();
+        Ok((builder, book))
+    }
+
+    #[test]
+    fn test_build_counts_moves_and_results() -> Result<()> {
+        let options = BookBuildOptions {
+            max_ply: 2,
+            min_games: 1,
+            min_score: 0.0,
+        };
+        let (builder, book) = build(options)?;
+
+        assert_eq!(builder.games_added(), 4);
+        assert_eq!(builder.games_skipped(), 4);
+
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let e4 = Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush);
+        let d4 = Move::from_square(Square::D2, Square::D4, Flag::PawnDoublePush);
+        // Three games with e4 scoring 1.5 points, one d4 loss that still gets a weight
+        assert_eq!(book.moves(&mut move_generator), vec![(e4, 3), (d4, 1)]);
+        // Nf3 came after the ply limit
+        assert_eq!(book.len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_filters() -> Result<()> {
+        let options = BookBuildOptions {
+            max_ply: 10,
+            min_games: 2,
+            min_score: 0.5,
+        };
+        let (_, book) = build(options)?;
+
+        // e4 in three games for half the points, and e5 twice for a quarter of them
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let e4 = Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush);
+        assert_eq!(book.moves(&mut move_generator), vec![(e4, 3)]);
+        assert_eq!(book.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_built_book_reads_back() -> Result<()> {
+        let (_, book) = build(BookBuildOptions {
+            min_games: 1,
+            ..Default::default()
+        })?;
+
+        let read_back = Book::from_bytes(&book.to_bytes())?;
+
+        assert_eq!(read_back.len(), book.len());
+        assert_eq!(read_back.to_bytes(), book.to_bytes());
+        Ok(())
+    }
+}
@@ -1,22 +1,77 @@
 use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use crate::{
     board::Board,
-    board_builder::BoardBuilder,
+    board_builder::{validate_position, BoardBuilder},
+    book::{clear_book, load_book, BOOK_BEST_MOVE, BOOK_DEPTH, OWN_BOOK},
+    eval_params::{eval_params, load_eval_params, set_eval_params, EvalParams},
+    eval_trace::trace_evaluation,
     move_generation::{Move, MoveGenerator},
-    search::find_best_move,
+    nnue::{clear_network, load_network, USE_NETWORK},
+    search::{filter_root_moves, find_best_move},
+    syzygy::{clear_tablebases, load_tablebases, TB_PROBE_DEPTH, TB_PROBE_LIMIT},
+    tablebase::{set_provider, Backend, TablebaseConfig},
+    variant::Variant,
 };
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+
+const UCI_OPTIONS: [&str; 18] = [
+    "option name BookBestMove type check default false",
+    "option name BookDepth type spin default 20 min 1 max 100",
+    "option name BookFile type string default <empty>",
+    "option name Contempt type spin default 0 min -100 max 100",
+    "option name EvalFile type string default <empty>",
+    "option name EvalParamsFile type string default <empty>",
+    "option name OwnBook type check default false",
+    "option name SyzygyPath type string default <empty>",
+    "option name TBProbeDepth type spin default 1 min 1 max 100",
+    "option name TBProbeLimit type spin default 7 min 0 max 7",
+    "option name TablebaseBackend type combo default http var http var local var none",
+    "option name TablebaseCache type string default <empty>",
+    "option name TablebaseRetries type spin default 2 min 0 max 10",
+    "option name TablebaseTimeout type spin default 5000 min 100 max 60000",
+    "option name TablebaseUrl type string default http://tablebase.lichess.ovh/standard",
+    "option name UCI_Chess960 type check default false",
+    "option name UCI_Variant type combo default chess var chess var crazyhouse var kingofthehill var 3check var atomic",
+    "option name UseNNUE type check default true",
+];
+
+const GO_PARAMETERS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
 
 pub struct Bot {
     board: Board,
+    tablebase_config: TablebaseConfig,
+    // Set by the GUI for Fischer Random games, which castle by the king taking its own rook
+    chess960: bool,
+    variant: Variant,
+    // Handed to the search with every go command
+    contempt: i32,
 }
 
 impl Bot {
     pub fn new() -> Self {
         Self {
             board: Board::starting_position(),
+            tablebase_config: TablebaseConfig::default(),
+            chess960: false,
+            variant: Variant::Standard,
+            contempt: 0,
         }
     }
 
@@ -44,10 +99,25 @@ impl Bot {
 
     fn process_commands(&mut self, commands: &[&str]) -> Result<()> {
         match commands {
-            ["uci"] => self.respond("uciok"),
+            ["uci"] => {
+                for option in UCI_OPTIONS {
+                    self.respond(option);
+                }
+                self.respond("uciok");
+            }
             ["isready"] => self.respond("readyok"),
+            ["setoption", ..] => self.handle_setoption_command(commands)?,
             ["position", ..] => self.handle_position_command(commands)?,
             ["go", ..] => self.handle_go_command(commands)?,
+            // Not part of UCI, prints a breakdown of the static evaluation of the current position
+            ["eval"] => {
+                let trace = trace_evaluation(&MoveGenerator::new(self.board.clone()));
+                self.respond(&trace.to_table());
+            }
+            ["eval", "json"] => {
+                let trace = trace_evaluation(&MoveGenerator::new(self.board.clone()));
+                self.respond(&trace.to_json()?);
+            }
             // TODO: Handle stop once clock is implemented in searcher
             ["ucinewgame"] | ["stop"] => {}
             ["quit"] => std::process::exit(0),
@@ -63,41 +133,188 @@ impl Bot {
         // (Thanks Sebastian for figuring this out, so I don't have to read the specs <3)
         match pos_command {
             ["position", "startpos", "moves", moves @ ..] => {
-                self.board = Board::starting_position();
+                self.set_board(Board::starting_position())?;
                 self.play_moves_on_board(moves);
 
                 Ok(())
             }
             ["position", "startpos"] => {
-                self.board = Board::starting_position();
+                self.set_board(Board::starting_position())?;
                 Ok(())
             }
-            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5, "moves", moves @ ..] => {
-                let full_fen_string =
-                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+            ["position", "fen", fen @ ..] => {
+                // Three-check FENs carry a seventh field, so take everything up to 'moves'
+                let (fen, moves) = match fen.iter().position(|&token| token == "moves") {
+                    Some(index) => (&fen[..index], &fen[index + 1..]),
+                    None => (fen, &[][..]),
+                };
+                if fen.len() < 6 {
+                    bail!("position command is in an unknown format");
+                }
 
-                self.board = BoardBuilder::try_from_fen(&full_fen_string)?;
+                self.set_board(BoardBuilder::try_from_fen_unchecked(&fen.join(" "))?)?;
                 self.play_moves_on_board(moves);
 
                 Ok(())
             }
-            ["position", "fen", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5] => {
-                let full_fen_string =
-                    format!("{} {} {} {} {} {}", fen_0, fen_1, fen_2, fen_3, fen_4, fen_5);
+            _ => bail!("position command is in an unknown format"),
+        }
+    }
 
-                self.board = BoardBuilder::try_from_fen(&full_fen_string)?;
-                Ok(())
+    fn handle_setoption_command(&mut self, setoption_command: &[&str]) -> Result<()> {
+        // Format: 'setoption name Contempt value 20'
+        // Option names and values may contain spaces, so everything between the keywords counts
+        let ["setoption", "name", option @ ..] = setoption_command else {
+            bail!("setoption command is in an unknown format");
+        };
+        let (name, value) = match option.iter().position(|&token| token == "value") {
+            Some(index) => (option[..index].join(" "), option[index + 1..].join(" ")),
+            None => (option.join(" "), String::new()),
+        };
+
+        match name.as_str() {
+            "BookBestMove" => {
+                BOOK_BEST_MOVE.store(parse_check("BookBestMove", &value)?, Ordering::Relaxed)
             }
-            _ => bail!("position command is in an unknown format"),
+            "BookDepth" => {
+                let depth: u32 = value
+                    .parse()
+                    .map_err(|_| anyhow!("BookDepth must be a whole number of moves"))?;
+                BOOK_DEPTH.store(depth.clamp(1, 100), Ordering::Relaxed);
+            }
+            "BookFile" => match value.as_str() {
+                "" | "<empty>" => clear_book(),
+                path => {
+                    let entry_count = load_book(path)?;
+                    self.respond(&format!("info string loaded {entry_count} book entries"));
+                }
+            },
+            "Contempt" => {
+                let contempt: i32 = value
+                    .parse()
+                    .map_err(|_| anyhow!("contempt must be a whole number of centipawns"))?;
+                self.contempt = contempt.clamp(-100, 100);
+            }
+            "EvalFile" => match value.as_str() {
+                "" | "<empty>" => clear_network(),
+                path => load_network(path)?,
+            },
+            "EvalParamsFile" => {
+                match value.as_str() {
+                    "" | "<empty>" => set_eval_params(EvalParams::default()),
+                    path => load_eval_params(path)?,
+                }
+                self.board.set_eval_params(eval_params());
+            }
+            "OwnBook" => OWN_BOOK.store(parse_check("OwnBook", &value)?, Ordering::Relaxed),
+            "SyzygyPath" => {
+                match value.as_str() {
+                    "" | "<empty>" => clear_tablebases(),
+                    paths => {
+                        let table_count = load_tablebases(paths)?;
+                        self.respond(&format!("info string found {table_count} tablebases"));
+                    }
+                }
+                // The local backend holds on to the tables it was built with
+                if self.tablebase_config.backend == Backend::Local {
+                    self.update_tablebase(self.tablebase_config.clone())?;
+                }
+            }
+            "TBProbeDepth" => {
+                let depth: u32 = value
+                    .parse()
+                    .map_err(|_| anyhow!("TBProbeDepth must be a whole number"))?;
+                TB_PROBE_DEPTH.store(depth.clamp(1, 100), Ordering::Relaxed);
+            }
+            "TBProbeLimit" => {
+                let limit: usize = value
+                    .parse()
+                    .map_err(|_| anyhow!("TBProbeLimit must be a whole number"))?;
+                TB_PROBE_LIMIT.store(limit.min(7), Ordering::Relaxed);
+            }
+            "TablebaseBackend" => {
+                let config = TablebaseConfig {
+                    backend: Backend::try_from(value.as_str())?,
+                    ..self.tablebase_config.clone()
+                };
+                self.update_tablebase(config)?;
+            }
+            "TablebaseCache" => {
+                let cache_directory = match value.as_str() {
+                    "" | "<empty>" => None,
+                    path => Some(path.into()),
+                };
+                let config = TablebaseConfig {
+                    cache_directory,
+                    ..self.tablebase_config.clone()
+                };
+                self.update_tablebase(config)?;
+            }
+            "TablebaseRetries" => {
+                let retries: u32 = value
+                    .parse()
+                    .map_err(|_| anyhow!("TablebaseRetries must be a whole number"))?;
+                let config = TablebaseConfig {
+                    retries: retries.min(10),
+                    ..self.tablebase_config.clone()
+                };
+                self.update_tablebase(config)?;
+            }
+            "TablebaseTimeout" => {
+                let timeout: u64 = value.parse().map_err(|_| {
+                    anyhow!("TablebaseTimeout must be a whole number of milliseconds")
+                })?;
+                let config = TablebaseConfig {
+                    timeout: Duration::from_millis(timeout.clamp(100, 60000)),
+                    ..self.tablebase_config.clone()
+                };
+                self.update_tablebase(config)?;
+            }
+            "TablebaseUrl" => {
+                let config = TablebaseConfig {
+                    url: value,
+                    ..self.tablebase_config.clone()
+                };
+                self.update_tablebase(config)?;
+            }
+            "UCI_Chess960" => {
+                self.chess960 = parse_check("UCI_Chess960", &value)?;
+                self.board.chess960 |= self.chess960;
+            }
+            "UCI_Variant" => {
+                self.variant = Variant::try_from(value.as_str())?;
+                self.board.variant = self.variant;
+            }
+            "UseNNUE" => USE_NETWORK.store(parse_check("UseNNUE", &value)?, Ordering::Relaxed),
+            _ => bail!("unrecognized option: {name}"),
         }
+
+        Ok(())
     }
 
-    fn handle_go_command(&mut self, _go_command: &[&str]) -> Result<()> {
+    // Only keeps the new settings once a provider could be built from them
+    fn update_tablebase(&mut self, config: TablebaseConfig) -> Result<()> {
+        set_provider(config.build()?);
+        self.tablebase_config = config;
+        Ok(())
+    }
+
+    fn handle_go_command(&mut self, go_command: &[&str]) -> Result<()> {
         // TODO: Handle time and increments
         let depth = 6;
         let mut move_generator = MoveGenerator::new(self.board.clone());
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, depth);
+
+        let search_moves = self.parse_search_moves(go_command);
+        if !search_moves.is_empty() {
+            match filter_root_moves(&moves, &search_moves) {
+                Ok(filtered_moves) => moves = filtered_moves,
+                // Searching every move beats not answering the GUI at all
+                Err(e) => self.log(&format!("{e}, searching all moves instead")),
+            }
+        }
+
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, depth, self.contempt);
         self.board.move_piece(&best_move);
 
         self.respond(&format!("bestmove {best_move}"));
@@ -105,6 +322,42 @@ impl Bot {
         Ok(())
     }
 
+    fn parse_search_moves(&self, go_command: &[&str]) -> Vec<Move> {
+        // Format: 'go wtime 1000 searchmoves e2e4 d2d4 btime 1000'
+        // The move list runs until the next go parameter or the end of the command
+        let Some(start) = go_command.iter().position(|&token| token == "searchmoves") else {
+            return Vec::new();
+        };
+
+        let mut move_generator = MoveGenerator::new(self.board.clone());
+        let mut search_moves = Vec::new();
+        for token in go_command[start + 1..]
+            .iter()
+            .take_while(|token| !GO_PARAMETERS.contains(token))
+        {
+            match Move::try_from_uci(token, &mut move_generator) {
+                Ok(mv) => search_moves.push(mv),
+                Err(e) => self.log(&format!("ignoring search move {token}: {e}")),
+            }
+        }
+
+        search_moves
+    }
+
+    fn set_board(&mut self, mut board: Board) -> Result<()> {
+        // A Shredder or X-FEN castling field makes it Chess960 even without UCI_Chess960
+        board.chess960 |= self.chess960;
+        // FENs already tell crazyhouse and Three-check apart, but not King of the Hill or
+        // atomic, and 'position startpos' tells nothing at all
+        if !self.variant.is_standard() {
+            board.variant = self.variant;
+        }
+        // Only checked once the variant is known, since the kings may touch in atomic
+        validate_position(&board)?;
+        self.board = board;
+        Ok(())
+    }
+
     fn play_moves_on_board(&mut self, moves: &[&str]) {
         for mv in moves {
             // Need a move generator to check if the move is legal
@@ -130,6 +383,14 @@ impl Bot {
     }
 }
 
+fn parse_check(name: &str, value: &str) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("{name} must be true or false"),
+    }
+}
+
 impl Default for Bot {
     fn default() -> Self {
         Bot::new()
@@ -142,8 +403,14 @@ mod tests {
         board::Board,
         board_builder::BoardBuilder,
         bot::Bot,
+        eval_params::EvalParams,
+        evaluate::Score,
         move_generation::{Flag, Move},
+        nnue::active_network,
+        piece::{Color, Piece},
         square::Square,
+        tablebase::Backend,
+        variant::Variant,
     };
 
     #[test]
@@ -212,4 +479,350 @@ mod tests {
 
         assert!(bot.board == expected_board);
     }
+
+    #[test]
+    fn test_uci_command_position_with_shredder_fen_castles_chess960() {
+        let mut bot = Bot::new();
+        let command = [
+            "position",
+            "fen",
+            "4k3/8/8/8/8/8/8/RK5R",
+            "w",
+            "HA",
+            "-",
+            "0",
+            "1",
+            "moves",
+            "b1a1",
+        ];
+        bot.process_commands(&command).unwrap();
+
+        assert!(bot.board.chess960);
+        assert!(bot
+            .board
+            .is_piece_at_square(Square::C1.as_index(), Piece::King, Color::White));
+        assert!(bot
+            .board
+            .is_piece_at_square(Square::D1.as_index(), Piece::Rook, Color::White));
+    }
+
+    #[test]
+    fn test_uci_command_position_with_three_check_fen_and_moves() {
+        let mut bot = Bot::new();
+        let command = [
+            "position",
+            "fen",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            "w",
+            "KQkq",
+            "-",
+            "0",
+            "1",
+            "+1+2",
+            "moves",
+            "e2e4",
+        ];
+        bot.process_commands(&command).unwrap();
+
+        assert_eq!(bot.board.variant, Variant::ThreeCheck);
+        assert_eq!(bot.board.board_state.checks_given, [1, 2]);
+        assert_eq!(
+            bot.board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 +1+2"
+        );
+    }
+
+    #[test]
+    fn test_uci_command_position_with_short_fen() {
+        let mut bot = Bot::new();
+        let command = ["position", "fen", "8/8/8/8/8/8/8/8", "w", "moves", "e2e4"];
+        let result = bot.process_commands(&command);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "position command is in an unknown format"
+        );
+    }
+
+    #[test]
+    fn test_parse_search_moves() {
+        let bot = Bot::new();
+        let command = ["go", "searchmoves", "e2e4", "g1f3", "depth", "6"];
+        let search_moves = bot.parse_search_moves(&command);
+
+        assert!(
+            search_moves
+                == [
+                    Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush),
+                    Move::from_square(Square::G1, Square::F3, Flag::None),
+                ]
+        );
+    }
+
+    #[test]
+    fn test_parse_search_moves_skips_illegal_moves() {
+        let bot = Bot::new();
+        let command = ["go", "wtime", "1000", "searchmoves", "e2e5", "d2d4"];
+        let search_moves = bot.parse_search_moves(&command);
+
+        let expected_move = Move::from_square(Square::D2, Square::D4, Flag::PawnDoublePush);
+
+        assert!(search_moves == [expected_move]);
+    }
+
+    #[test]
+    fn test_parse_search_moves_absent() {
+        let bot = Bot::new();
+        let command = ["go", "depth", "6"];
+
+        assert!(bot.parse_search_moves(&command).is_empty());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_contempt() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "Contempt", "value", "35"];
+        bot.process_commands(&command).unwrap();
+
+        assert_eq!(bot.contempt, 35);
+    }
+
+    #[test]
+    fn test_uci_command_setoption_contempt_is_clamped() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "Contempt", "value", "500"];
+        bot.process_commands(&command).unwrap();
+
+        assert_eq!(bot.contempt, 100);
+    }
+
+    #[test]
+    fn test_uci_command_setoption_invalid_contempt() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "Contempt", "value", "lots"];
+        let result = bot.process_commands(&command);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "contempt must be a whole number of centipawns"
+        );
+    }
+
+    #[test]
+    fn test_uci_command_setoption_unknown_option() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "Move", "Overhead", "value", "10"];
+        let result = bot.process_commands(&command);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "unrecognized option: Move Overhead"
+        );
+    }
+
+    #[test]
+    fn test_uci_command_eval() {
+        let mut bot = Bot::new();
+
+        assert!(bot.process_commands(&["eval"]).is_ok());
+        assert!(bot.process_commands(&["eval", "json"]).is_ok());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_missing_eval_file() {
+        let mut bot = Bot::new();
+        let command = [
+            "setoption",
+            "name",
+            "EvalFile",
+            "value",
+            "/does/not/exist.nnue",
+        ];
+
+        assert!(bot.process_commands(&command).is_err());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_empty_eval_file() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "EvalFile", "value", "<empty>"];
+        bot.process_commands(&command).unwrap();
+
+        assert!(active_network().is_none());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_invalid_use_nnue() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "UseNNUE", "value", "maybe"];
+        let result = bot.process_commands(&command);
+
+        assert_eq!(result.err().unwrap().to_string(), "UseNNUE must be true or false");
+    }
+
+    #[test]
+    fn test_uci_command_setoption_eval_params_file() {
+        let mut bot = Bot::new();
+        // Only the trapped rook weight changes, and only for as long as the test runs, since
+        // other tests set up boards with the global weights in parallel
+        let path = std::env::temp_dir().join("talia_test_trapped_rook_params.json");
+        std::fs::write(&path, r#"{"trapped_rook": {"middle_game": -55, "end_game": -20}}"#)
+            .unwrap();
+        let command = [
+            "setoption",
+            "name",
+            "EvalParamsFile",
+            "value",
+            path.to_str().unwrap(),
+        ];
+        bot.process_commands(&command).unwrap();
+
+        assert_eq!(bot.board.eval_params.trapped_rook, Score::new(-55, -20));
+        assert_eq!(bot.board.eval_params.material, EvalParams::default().material);
+
+        let command = ["setoption", "name", "EvalParamsFile", "value", "<empty>"];
+        bot.process_commands(&command).unwrap();
+
+        assert!(*bot.board.eval_params == EvalParams::default());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_missing_eval_params_file() {
+        let mut bot = Bot::new();
+        let command = [
+            "setoption",
+            "name",
+            "EvalParamsFile",
+            "value",
+            "/does/not/exist.json",
+        ];
+
+        assert!(bot.process_commands(&command).is_err());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_missing_syzygy_path() {
+        let mut bot = Bot::new();
+        let command = [
+            "setoption",
+            "name",
+            "SyzygyPath",
+            "value",
+            "/does/not/exist",
+        ];
+
+        assert!(bot.process_commands(&command).is_err());
+    }
+
+    #[test]
+    fn test_uci_command_setoption_invalid_tb_probe_limit() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "TBProbeLimit", "value", "all"];
+        let result = bot.process_commands(&command);
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "TBProbeLimit must be a whole number"
+        );
+    }
+
+    #[test]
+    fn test_uci_command_setoption_tablebase_backend() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "TablebaseBackend", "value", "gaviota"];
+        assert!(bot.process_commands(&command).is_err());
+
+        // Nothing to probe without SyzygyPath, so the backend stays as it was
+        let command = ["setoption", "name", "TablebaseBackend", "value", "local"];
+        assert!(bot.process_commands(&command).is_err());
+        assert!(bot.tablebase_config.backend == Backend::Http);
+    }
+
+    #[test]
+    fn test_uci_command_setoption_chess960() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "UCI_Chess960", "value", "true"];
+        bot.process_commands(&command).unwrap();
+
+        // Castling is sent as the king taking its own rook
+        let command = [
+            "position",
+            "fen",
+            "1k6/8/8/8/8/8/8/RK4R1",
+            "w",
+            "AG",
+            "-",
+            "0",
+            "1",
+            "moves",
+            "b1g1",
+        ];
+        bot.process_commands(&command).unwrap();
+        assert!(bot.board.chess960);
+        assert_eq!(bot.board.to_fen(), "1k6/8/8/8/8/8/8/R4RK1 b - - 1 1");
+    }
+
+    #[test]
+    fn test_uci_command_setoption_variant() {
+        let mut bot = Bot::new();
+        let command = ["setoption", "name", "UCI_Variant", "value", "kingofthehill"];
+        bot.process_commands(&command).unwrap();
+
+        // The variant sticks to every position set up afterwards
+        bot.process_commands(&["position", "startpos", "moves", "e2e4"])
+            .unwrap();
+        assert_eq!(bot.board.variant, Variant::KingOfTheHill);
+
+        let command = ["setoption", "name", "UCI_Variant", "value", "suicide"];
+        assert!(bot.process_commands(&command).is_err());
+    }
+
+    #[test]
+    fn test_uci_command_position_with_touching_kings() {
+        let mut bot = Bot::new();
+        let command = [
+            "position",
+            "fen",
+            "4k3/4K3/8/8/8/8/8/8",
+            "w",
+            "-",
+            "-",
+            "0",
+            "1",
+        ];
+        assert!(bot.process_commands(&command).is_err());
+
+        // Neither king is in check in atomic, since it can't be captured
+        let command = ["setoption", "name", "UCI_Variant", "value", "atomic"];
+        bot.process_commands(&command).unwrap();
+        let command = [
+            "position",
+            "fen",
+            "4k3/4K3/8/8/8/8/8/8",
+            "w",
+            "-",
+            "-",
+            "0",
+            "1",
+        ];
+        bot.process_commands(&command).unwrap();
+        assert_eq!(bot.board.variant, Variant::Atomic);
+    }
+
+    #[test]
+    fn test_uci_command_setoption_book_options() {
+        let mut bot = Bot::new();
+        let command = [
+            "setoption",
+            "name",
+            "BookFile",
+            "value",
+            "/does/not/exist.bin",
+        ];
+        assert!(bot.process_commands(&command).is_err());
+
+        let command = ["setoption", "name", "OwnBook", "value", "yes"];
+        let result = bot.process_commands(&command);
+        assert_eq!(result.err().unwrap().to_string(), "OwnBook must be true or false");
+    }
 }
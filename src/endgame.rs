@@ -0,0 +1,359 @@
+// Knowledge about specific endings that the general evaluation gets wrong: mating a bare king,
+// and endings that are drawn despite a material advantage
+// Source: https://www.chessprogramming.org/Mop-up_Evaluation
+use crate::{
+    board::Board,
+    kpk,
+    piece::{Color, Piece},
+};
+
+// Scale factors are out of this, which leaves the end game score untouched
+pub const NORMAL_SCALE: i32 = 64;
+const OPPOSITE_BISHOPS_SCALE: i32 = 32;
+
+// Bonus per step the losing king is pushed away from the centre
+const PUSH_TO_EDGE_WEIGHT: i32 = 10;
+// Bonus per step the winning king is closer to the losing king
+const PUSH_CLOSE_WEIGHT: i32 = 4;
+// Bonus per step the losing king is closer to a corner the bishop can cover, with bishop and knight
+const PUSH_TO_CORNER_WEIGHT: i32 = 20;
+// On top of the pawn for a won king and pawn ending, kept below what a new queen is worth so the
+// search still wants to promote
+const KPK_WIN_BONUS: i32 = 400;
+// Bonus per rank a winning pawn has advanced, so the search makes progress
+const KPK_PAWN_ADVANCE_WEIGHT: i32 = 20;
+
+// A specialised evaluation from white's point of view for an ending the general evaluation plays
+// badly, or None if the position is not one of them. Bare king endings are covered, where the
+// winning side has to drive the king to the edge to mate it, and king and pawn against king,
+// which is looked up in the KPK bitbase.
+pub fn evaluate_endgame(board: &Board) -> Option<i32> {
+    if let Some(eval) = evaluate_kpk(board) {
+        return Some(eval);
+    }
+
+    let counts = &board.piece_scores.piece_counts;
+
+    for strong_side in [Color::White, Color::Black] {
+        let weak_side = strong_side.opposite_color();
+        let strong_counts = &counts[strong_side as usize];
+        if !is_bare_king(&counts[weak_side as usize]) || strong_counts[Piece::Pawn as usize] > 0 {
+            continue;
+        }
+
+        let strong_king = king_square(board, strong_side)?;
+        let weak_king = king_square(board, weak_side)?;
+        let material = board.piece_scores.material[strong_side as usize].end_game;
+        let push_close = PUSH_CLOSE_WEIGHT * (14 - manhattan_distance(strong_king, weak_king));
+
+        let eval = if is_bishop_and_knight(strong_counts) {
+            // Mate can only be forced in a corner the bishop covers
+            let bishop_square = piece_squares(board, Piece::Bishop, strong_side).next()?;
+            let corners = if is_light_square(bishop_square) {
+                [7, 56]
+            } else {
+                [0, 63]
+            };
+            let corner_distance = corners
+                .iter()
+                .map(|&corner| distance(weak_king, corner))
+                .min()?;
+            material + push_close + PUSH_TO_CORNER_WEIGHT * (7 - corner_distance)
+        } else if has_mating_material(board, strong_side) {
+            material + push_close + PUSH_TO_EDGE_WEIGHT * centre_distance(weak_king)
+        } else {
+            continue;
+        };
+
+        return Some(match strong_side {
+            Color::White => eval,
+            Color::Black => -eval,
+        });
+    }
+
+    None
+}
+
+fn evaluate_kpk(board: &Board) -> Option<i32> {
+    if !kpk::probe_board(board)? {
+        return Some(0);
+    }
+
+    let strong_side = [Color::White, Color::Black]
+        .into_iter()
+        .find(|&color| board.piece_scores.piece_counts[color as usize][Piece::Pawn as usize] > 0)?;
+    let pawn = piece_squares(board, Piece::Pawn, strong_side).next()?;
+    let relative_rank = match strong_side {
+        Color::White => pawn / 8,
+        Color::Black => 7 - pawn / 8,
+    } as i32;
+    let eval = board.piece_scores.material[strong_side as usize].end_game
+        + KPK_WIN_BONUS
+        + KPK_PAWN_ADVANCE_WEIGHT * relative_rank;
+
+    Some(match strong_side {
+        Color::White => eval,
+        Color::Black => -eval,
+    })
+}
+
+// How much of its end game advantage the stronger side can hope to turn into a win, out of
+// NORMAL_SCALE
+pub fn scale_factor(board: &Board, strong_side: Color) -> i32 {
+    let counts = &board.piece_scores.piece_counts;
+    let strong_counts = &counts[strong_side as usize];
+    let weak_counts = &counts[strong_side.opposite_color() as usize];
+
+    if strong_counts[Piece::Pawn as usize] == 0 {
+        let strong_material = non_pawn_material(strong_counts);
+        let weak_material = non_pawn_material(weak_counts);
+
+        // Two knights cannot force mate
+        if strong_material == 2 * Piece::Knight.piece_value()
+            && strong_counts[Piece::Knight as usize] == 2
+        {
+            return 0;
+        }
+        // Up a minor piece or less without pawns is hardly ever enough
+        if strong_material - weak_material <= Piece::Bishop.piece_value() {
+            return if strong_material < Piece::Rook.piece_value() {
+                0
+            } else if weak_material <= Piece::Bishop.piece_value() {
+                4
+            } else {
+                14
+            };
+        }
+    }
+
+    if is_rook_pawn_fortress(board, strong_side) {
+        return 0;
+    }
+
+    if is_opposite_bishops_ending(board) {
+        return OPPOSITE_BISHOPS_SCALE;
+    }
+
+    NORMAL_SCALE
+}
+
+// A lone rook pawn, even with a bishop that does not cover the promotion square, cannot get past
+// a king sitting in front of it
+fn is_rook_pawn_fortress(board: &Board, strong_side: Color) -> bool {
+    let counts = &board.piece_scores.piece_counts;
+    let strong_counts = &counts[strong_side as usize];
+    let weak_side = strong_side.opposite_color();
+    if !is_bare_king(&counts[weak_side as usize]) {
+        return false;
+    }
+    let has_only_pawns_and_bishop = strong_counts[Piece::Knight as usize] == 0
+        && strong_counts[Piece::Rook as usize] == 0
+        && strong_counts[Piece::Queen as usize] == 0
+        && strong_counts[Piece::Bishop as usize] <= 1;
+    if !has_only_pawns_and_bishop || strong_counts[Piece::Pawn as usize] == 0 {
+        return false;
+    }
+
+    let mut pawn_files = piece_squares(board, Piece::Pawn, strong_side).map(|square| square % 8);
+    let Some(file) = pawn_files.next() else {
+        return false;
+    };
+    if (file != 0 && file != 7) || pawn_files.any(|other_file| other_file != file) {
+        return false;
+    }
+
+    let promotion_square = match strong_side {
+        Color::White => 56 + file,
+        Color::Black => file,
+    };
+    let bishop_covers_promotion_square = piece_squares(board, Piece::Bishop, strong_side)
+        .any(|square| is_light_square(square) == is_light_square(promotion_square));
+    let Some(weak_king) = king_square(board, weak_side) else {
+        return false;
+    };
+
+    !bishop_covers_promotion_square && distance(weak_king, promotion_square) <= 1
+}
+
+// With one bishop each on different colors and nothing but pawns besides, the defender can
+// usually blockade on the squares the other bishop never reaches
+fn is_opposite_bishops_ending(board: &Board) -> bool {
+    let counts = &board.piece_scores.piece_counts;
+    let only_bishop = |counts: &[u8; 6]| {
+        counts[Piece::Bishop as usize] == 1
+            && non_pawn_material(counts) == Piece::Bishop.piece_value()
+    };
+    if !only_bishop(&counts[0]) || !only_bishop(&counts[1]) {
+        return false;
+    }
+
+    let white_bishop = piece_squares(board, Piece::Bishop, Color::White).next();
+    let black_bishop = piece_squares(board, Piece::Bishop, Color::Black).next();
+    match (white_bishop, black_bishop) {
+        (Some(white), Some(black)) => is_light_square(white) != is_light_square(black),
+        _ => false,
+    }
+}
+
+fn has_mating_material(board: &Board, color: Color) -> bool {
+    let counts = &board.piece_scores.piece_counts[color as usize];
+    if counts[Piece::Queen as usize] > 0 || counts[Piece::Rook as usize] > 0 {
+        return true;
+    }
+
+    let mut bishops = piece_squares(board, Piece::Bishop, color);
+    match bishops.next() {
+        Some(first) => bishops.any(|other| is_light_square(other) != is_light_square(first)),
+        None => false,
+    }
+}
+
+fn is_bishop_and_knight(counts: &[u8; 6]) -> bool {
+    counts[Piece::Knight as usize] == 1
+        && counts[Piece::Bishop as usize] == 1
+        && counts[Piece::Rook as usize] == 0
+        && counts[Piece::Queen as usize] == 0
+}
+
+fn is_bare_king(counts: &[u8; 6]) -> bool {
+    counts[..Piece::King as usize]
+        .iter()
+        .all(|&count| count == 0)
+}
+
+// Uses the fixed middle game values, loaded evaluation weights should not move these thresholds
+fn non_pawn_material(counts: &[u8; 6]) -> i32 {
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|&piece| counts[piece as usize] as i32 * piece.piece_value())
+        .sum()
+}
+
+fn king_square(board: &Board, color: Color) -> Option<usize> {
+    piece_squares(board, Piece::King, color).next()
+}
+
+fn piece_squares(board: &Board, piece: Piece, color: Color) -> impl Iterator<Item = usize> + '_ {
+    (0..64).filter(move |&square| board.is_piece_at_square(square, piece, color))
+}
+
+fn is_light_square(square: usize) -> bool {
+    (square / 8 + square % 8) % 2 == 1
+}
+
+// Number of king moves between two squares
+pub fn distance(a: usize, b: usize) -> i32 {
+    let file_distance = (a % 8).abs_diff(b % 8);
+    let rank_distance = (a / 8).abs_diff(b / 8);
+    std::cmp::max(file_distance, rank_distance) as i32
+}
+
+fn manhattan_distance(a: usize, b: usize) -> i32 {
+    ((a % 8).abs_diff(b % 8) + (a / 8).abs_diff(b / 8)) as i32
+}
+
+// 0 in the four centre squares, 6 in the corners
+fn centre_distance(square: usize) -> i32 {
+    let file = square % 8;
+    let rank = square / 8;
+    let file_distance = std::cmp::max(3 - file as i32, file as i32 - 4);
+    let rank_distance = std::cmp::max(3 - rank as i32, rank as i32 - 4);
+    file_distance + rank_distance
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board_builder::BoardBuilder, piece::Color};
+    use anyhow::Result;
+
+    use super::{evaluate_endgame, scale_factor, NORMAL_SCALE, OPPOSITE_BISHOPS_SCALE};
+
+    fn endgame_eval(fen: &str) -> Result<Option<i32>> {
+        Ok(evaluate_endgame(&BoardBuilder::try_from_fen(fen)?))
+    }
+
+    #[test]
+    fn test_rook_mate_prefers_king_on_edge() -> Result<()> {
+        let edge = endgame_eval("7k/8/5K2/8/8/8/8/R7 w - - 0 1")?.unwrap();
+        let centre = endgame_eval("8/8/5K2/3k4/8/8/8/R7 w - - 0 1")?.unwrap();
+
+        assert!(edge > centre);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_black_mop_up_is_negative() -> Result<()> {
+        let eval = endgame_eval("8/8/8/3k4/8/8/8/q3K3 w - - 0 1")?.unwrap();
+
+        assert!(eval < 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bishop_and_knight_prefer_bishop_corner() -> Result<()> {
+        // The dark squared bishop can only help mate on a1 or h8
+        let right_corner = endgame_eval("7k/8/5K2/8/8/8/8/1N2B3 w - - 0 1")?.unwrap();
+        let wrong_corner = endgame_eval("k7/8/2K5/8/8/8/8/1N2B3 w - - 0 1")?.unwrap();
+
+        assert!(right_corner > wrong_corner);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_specialised_evaluation_with_pawns() -> Result<()> {
+        assert_eq!(endgame_eval("4k3/4p3/8/8/8/8/8/R3K3 w - - 0 1")?, None);
+        assert_eq!(endgame_eval("4k3/8/8/8/8/8/4P3/R3K3 w - - 0 1")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_king_and_pawn_uses_bitbase() -> Result<()> {
+        assert!(endgame_eval("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")?.unwrap() > 400);
+        // The defending king holds the opposition in front of the pawn
+        assert_eq!(endgame_eval("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1")?, Some(0));
+        assert!(endgame_eval("8/8/8/4p3/4k3/8/8/4K3 b - - 0 1")?.unwrap() < -400);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insufficient_mating_material_scales_to_draw() -> Result<()> {
+        let lone_knight = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1")?;
+        let two_knights = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1")?;
+        let rook_against_bishop = BoardBuilder::try_from_fen("4k3/8/8/2b5/8/8/8/R3K3 w - - 0 1")?;
+
+        assert_eq!(scale_factor(&lone_knight, Color::White), 0);
+        assert_eq!(scale_factor(&two_knights, Color::White), 0);
+        assert!(scale_factor(&rook_against_bishop, Color::White) < NORMAL_SCALE / 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_bishop_rook_pawn_is_a_draw() -> Result<()> {
+        // The light squared bishop does not cover h8, where the black king is waiting
+        let wrong_bishop = BoardBuilder::try_from_fen("7k/8/8/7P/8/8/8/3BK3 w - - 0 1")?;
+        // A dark squared bishop does
+        let right_bishop = BoardBuilder::try_from_fen("7k/8/8/7P/8/8/8/2B1K3 w - - 0 1")?;
+
+        assert_eq!(scale_factor(&wrong_bishop, Color::White), 0);
+        assert_eq!(scale_factor(&right_bishop, Color::White), NORMAL_SCALE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_opposite_coloured_bishops_are_scaled() -> Result<()> {
+        let opposite = BoardBuilder::try_from_fen("4k3/pp6/4b3/8/8/8/PPP5/2B1K3 w - - 0 1")?;
+        let same = BoardBuilder::try_from_fen("4k3/pp6/3b4/8/8/8/PPP5/2B1K3 w - - 0 1")?;
+
+        assert_eq!(scale_factor(&opposite, Color::White), OPPOSITE_BISHOPS_SCALE);
+        assert_eq!(scale_factor(&same, Color::White), NORMAL_SCALE);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+// Extended Position Description: a FEN without the move counters followed by operations, as
+// used by test suites like WAC and STS. Each position is searched and the move Talia picks is
+// checked against the best moves (bm) and the moves to avoid (am) of the position.
+// Source: https://www.chessprogramming.org/Extended_Position_Description
+use std::{
+    fs,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    move_generation::{Move, MoveGenerator},
+    search::{search_root_moves, COUNTER, TB_WIN},
+};
+
+#[derive(Clone)]
+pub struct Epd {
+    pub board: Board,
+    // Every operation in the order it was written, including the ones below
+    pub operations: Vec<(String, Vec<String>)>,
+    pub id: Option<String>,
+    pub comment: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    pub principal_variation: Vec<Move>,
+    // acd and ce: the depth and centipawn score of an earlier analysis
+    pub analysis_depth: Option<u32>,
+    pub centipawn_evaluation: Option<i32>,
+}
+
+impl Epd {
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+}
+
+// Splits the operations into opcodes and operands. Operations end with a semicolon, and
+// operands in quotes may contain spaces and semicolons.
+pub fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    operations.push((opcode, std::mem::take(&mut words)));
+                }
+            }
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("operand is never closed: \"{word}"),
+                    }
+                }
+                words.push(word);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';') {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+        }
+    }
+    // The semicolon after the last operation is often left out
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+
+    Ok(operations)
+}
+
+// Suites mostly write moves in standard algebraic notation, but some use coordinates
+pub fn parse_move(text: &str, board: &Board) -> Result<Move> {
+    let mut move_generator = MoveGenerator::new(board.clone());
+    Move::try_from_san(text, &mut move_generator).or_else(|error| match text.len() {
+        4 | 5 if text.is_ascii() => Move::try_from_uci(text, &mut move_generator),
+        _ => Err(error),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EpdRunOptions {
+    pub depth: u32,
+    // With a time limit the search keeps deepening until a depth finishes after the time is
+    // up, since a search can't be stopped halfway
+    pub move_time: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EpdPositionResult {
+    pub id: String,
+    pub best_move: String,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: i32,
+    pub time_ms: u128,
+    // None if the position has neither best nor avoid moves to check against
+    pub solved: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EpdSummary {
+    pub positions: usize,
+    pub solved: usize,
+    pub unscored: usize,
+    // Lines that could not be parsed or searched
+    pub skipped: usize,
+    pub time_ms: u128,
+    pub results: Vec<EpdPositionResult>,
+}
+
+pub fn solve_position(epd: &Epd, options: EpdRunOptions) -> Result<EpdPositionResult> {
+    let mut move_generator = MoveGenerator::new(epd.board.clone());
+    if move_generator.generate_moves().is_empty() {
+        bail!("no legal moves");
+    }
+
+    // The depth is searched first, and deeper ones after it while there is time left
+    let start_time = Instant::now();
+    let mut depth = options.depth.saturating_sub(1);
+    let mut nodes = 0;
+    let (best_move, score) = loop {
+        depth += 1;
+        let mut moves = move_generator.generate_moves();
+        // A book move would solve the position without searching it
+        let result = search_root_moves(&mut moves, &mut move_generator, depth, 0);
+        nodes += COUNTER.load(Ordering::Relaxed);
+
+        let out_of_time = options
+            .move_time
+            .is_none_or(|move_time| start_time.elapsed() >= move_time);
+        // A mate or tablebase result won't get any better by searching deeper
+        if depth >= options.depth && (out_of_time || result.1.abs() >= TB_WIN) {
+            break result;
+        }
+    };
+
+    let solved = match (epd.best_moves.is_empty(), epd.avoid_moves.is_empty()) {
+        (true, true) => None,
+        _ => Some(
+            (epd.best_moves.is_empty() || epd.best_moves.contains(&best_move))
+                && !epd.avoid_moves.contains(&best_move),
+        ),
+    };
+
+    Ok(EpdPositionResult {
+        id: epd.id.clone().unwrap_or_default(),
+        best_move: best_move.to_san(&epd.board),
+        score,
+        depth,
+        nodes,
+        time_ms: start_time.elapsed().as_millis(),
+        solved,
+    })
+}
+
+pub fn run_epd(path: &str, options: EpdRunOptions, json_path: Option<&str>) -> Result<()> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| anyhow!("could not read test suite {path}: {e}"))?;
+    let mut summary = EpdSummary {
+        positions: 0,
+        solved: 0,
+        unscored: 0,
+        skipped: 0,
+        time_ms: 0,
+        results: Vec::new(),
+    };
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = BoardBuilder::try_from_epd(line)
+            .and_then(|epd| solve_position(&epd, options).map(|result| (epd, result)));
+        let (epd, mut result) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                println!("line {}: {e}, skipped", line_number + 1);
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        if result.id.is_empty() {
+            result.id = format!("line {}", line_number + 1);
+        }
+
+        let expected = [("bm", &epd.best_moves), ("am", &epd.avoid_moves)]
+            .iter()
+            .filter(|(_, moves)| !moves.is_empty())
+            .map(|(opcode, moves)| {
+                let moves: Vec<String> = moves.iter().map(|mv| mv.to_san(&epd.board)).collect();
+                format!("{opcode} {}", moves.join(" "))
+            })
+            .collect::<Vec<_>>();
+        let expected = match expected.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", expected.join(", ")),
+        };
+        let verdict = match result.solved {
+            Some(true) => "solved",
+            Some(false) => "failed",
+            None => "unscored",
+        };
+        println!(
+            "{:<16} {verdict:<8} {:<8}{expected} score {}, depth {}, {} ms",
+            result.id, result.best_move, result.score, result.depth, result.time_ms
+        );
+
+        summary.positions += 1;
+        match result.solved {
+            Some(true) => summary.solved += 1,
+            Some(false) => {}
+            None => summary.unscored += 1,
+        }
+        summary.time_ms += result.time_ms;
+        summary.results.push(result);
+    }
+    if summary.positions == 0 {
+        bail!("no positions found in {path}");
+    }
+
+    let scored = summary.positions - summary.unscored;
+    println!(
+        "Solved {}/{scored} ({:.1}%) in {:.1} s",
+        summary.solved,
+        100.0 * summary.solved as f64 / scored.max(1) as f64,
+        summary.time_ms as f64 / 1000.0
+    );
+    if summary.skipped > 0 {
+        println!("Skipped {} lines", summary.skipped);
+    }
+    if let Some(json_path) = json_path {
+        fs::write(json_path, serde_json::to_string_pretty(&summary)?)?;
+        println!("Summary written to {json_path}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{parse_operations, run_epd, solve_position, EpdRunOptions};
+
+    #[test]
+    fn test_parse_operations() -> Result<()> {
+        let operations =
+            parse_operations(r#"bm Qg6 Rxf7+; id "WAC; 001"; c0 "two  words" ;acd 5"#)?;
+
+        assert_eq!(
+            operations,
+            [
+                ("bm".to_string(), vec!["Qg6".to_string(), "Rxf7+".to_string()]),
+                ("id".to_string(), vec!["WAC; 001".to_string()]),
+                ("c0".to_string(), vec!["two  words".to_string()]),
+                ("acd".to_string(), vec!["5".to_string()]),
+            ]
+        );
+        assert!(parse_operations(r#"id "never closed"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_position() -> Result<()> {
+        let options = EpdRunOptions {
+            depth: 2,
+            move_time: None,
+        };
+        let epd = BoardBuilder::try_from_epd(
+            r#"6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id "back rank";"#,
+        )?;
+        let result = solve_position(&epd, options)?;
+
+        assert_eq!(result.id, "back rank");
+        assert_eq!(result.best_move, "Ra8#");
+        assert_eq!(result.solved, Some(true));
+
+        // The same move fails when it is the one to avoid
+        let mut epd = epd;
+        epd.avoid_moves = vec![Move::from_square(Square::A1, Square::A8, Flag::None)];
+        epd.best_moves.clear();
+        assert_eq!(solve_position(&epd, options)?.solved, Some(false));
+
+        epd.avoid_moves.clear();
+        assert_eq!(solve_position(&epd, options)?.solved, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_lines_are_skipped() -> Result<()> {
+        let directory = std::env::temp_dir().join("talia_test_epd_skipped");
+        std::fs::create_dir_all(&directory)?;
+        let suite = directory.join("suite.epd");
+        let json = directory.join("summary.json");
+        std::fs::write(
+            &suite,
+            concat!(
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"back rank\";\n",
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Qa8#;\n",
+                "not an epd line\n",
+            ),
+        )?;
+        let options = EpdRunOptions {
+            depth: 2,
+            move_time: None,
+        };
+        run_epd(suite.to_str().unwrap(), options, json.to_str())?;
+
+        let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json)?)?;
+        assert_eq!(summary["positions"], 1);
+        assert_eq!(summary["solved"], 1);
+        assert_eq!(summary["skipped"], 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::sync::{Arc, LazyLock, RwLock};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    evaluate::Score,
+    piece::{Color, Piece, END_GAME_PIECE_VALUE_TABLE, PIECE_VALUE_TABLE},
+    piece_square_table::{END_GAME_PIECE_SQUARE_TABLES, MIDDLE_GAME_PIECE_SQUARE_TABLES},
+};
+
+// The weights evaluation uses: the built in defaults unless a parameter file has been loaded
+static EVAL_PARAMS: LazyLock<RwLock<Arc<EvalParams>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(DEFAULT_EVAL_PARAMS)));
+
+// Weights of the evaluation terms. Every value is a bonus from the point of view of the side
+// that owns the feature, so penalties are negative.
+// Missing fields in a parameter file keep their default value, so a file only needs the weights
+// it changes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    // Indexed by Piece
+    pub material: [Score; 6],
+    // Indexed by Piece, then by square laid out like the tables in piece_square_table.rs
+    #[serde(with = "piece_square_tables_serde")]
+    pub piece_square_tables: [[Score; 64]; 6],
+    // Indexed by rank from the pawn's own side, so index 6 is one step from promoting
+    pub passed_pawn: [Score; 8],
+    pub isolated_pawn: Score,
+    pub doubled_pawn: Score,
+    pub backward_pawn: Score,
+    pub connected_pawn: Score,
+    pub pawn_island: Score,
+    // Per square a piece attacks that is neither occupied by a friendly piece nor covered by an
+    // enemy pawn, indexed by Piece
+    pub mobility: [Score; 6],
+    // Per pawn on the two ranks directly in front of a king that is still on its back ranks
+    pub king_pawn_shield: Score,
+    // Per file next to or on the king's file that has no pawns / no friendly pawns
+    pub king_open_file: Score,
+    pub king_semi_open_file: Score,
+    // Per attacked square in the enemy king zone (the king and its neighbouring squares),
+    // indexed by Piece
+    pub king_attack_weight: [i32; 6],
+    // Percentage of the summed attack weight that counts, indexed by the number of attackers.
+    // A lone attacker is rarely dangerous.
+    pub king_attackers_scale: [i32; 8],
+    pub bishop_pair: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub rook_on_seventh: Score,
+    // A knight deep in enemy territory, defended by a pawn and out of reach of enemy pawns
+    pub knight_outpost: Score,
+    // A bishop on a7/h7 (a2/h2 for black) shut in by an enemy pawn on b6/g6 (b3/g3)
+    pub trapped_bishop: Score,
+    // A rook stuck in the corner by its own king, which can no longer castle to free it
+    pub trapped_rook: Score,
+}
+
+pub const DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    material: default_material(),
+    piece_square_tables: default_piece_square_tables(),
+    passed_pawn: [
+        Score::new(0, 0),
+        Score::new(5, 10),
+        Score::new(5, 15),
+        Score::new(10, 25),
+        Score::new(20, 45),
+        Score::new(35, 75),
+        Score::new(60, 120),
+        Score::new(0, 0),
+    ],
+    isolated_pawn: Score::new(-10, -15),
+    doubled_pawn: Score::new(-10, -25),
+    backward_pawn: Score::new(-8, -10),
+    connected_pawn: Score::new(8, 6),
+    pawn_island: Score::new(-5, -10),
+    mobility: [
+        Score::new(0, 0), // Pawn
+        Score::new(4, 4), // Knight
+        Score::new(5, 5), // Bishop
+        Score::new(2, 4), // Rook
+        Score::new(1, 2), // Queen
+        Score::new(0, 0), // King
+    ],
+    king_pawn_shield: Score::new(10, 0),
+    king_open_file: Score::new(-25, 0),
+    king_semi_open_file: Score::new(-10, 0),
+    king_attack_weight: [
+        0,  // Pawn
+        20, // Knight
+        20, // Bishop
+        40, // Rook
+        80, // Queen
+        0,  // King
+    ],
+    king_attackers_scale: [0, 0, 50, 75, 88, 94, 97, 99],
+    bishop_pair: Score::new(30, 50),
+    rook_open_file: Score::new(25, 10),
+    rook_semi_open_file: Score::new(10, 5),
+    rook_on_seventh: Score::new(20, 30),
+    knight_outpost: Score::new(20, 10),
+    trapped_bishop: Score::new(-100, -100),
+    trapped_rook: Score::new(-40, -10),
+};
+
+const fn default_material() -> [Score; 6] {
+    let mut material = [Score::new(0, 0); 6];
+    let mut piece = 0;
+    while piece < 6 {
+        material[piece] = Score::new(PIECE_VALUE_TABLE[piece], END_GAME_PIECE_VALUE_TABLE[piece]);
+        piece += 1;
+    }
+    material
+}
+
+const fn default_piece_square_tables() -> [[Score; 64]; 6] {
+    let mut tables = [[Score::new(0, 0); 64]; 6];
+    let mut piece = 0;
+    while piece < 6 {
+        let mut square = 0;
+        while square < 64 {
+            tables[piece][square] = Score::new(
+                MIDDLE_GAME_PIECE_SQUARE_TABLES[piece][square],
+                END_GAME_PIECE_SQUARE_TABLES[piece][square],
+            );
+            square += 1;
+        }
+        piece += 1;
+    }
+    tables
+}
+
+// Boards keep their own snapshot of the weights from when they were set up, so evaluation never
+// has to take the lock
+pub fn eval_params() -> Arc<EvalParams> {
+    EVAL_PARAMS.read().unwrap().clone()
+}
+
+// Boards set up before the switch still evaluate with the old weights, see
+// Board::set_eval_params
+pub fn set_eval_params(params: EvalParams) {
+    *EVAL_PARAMS.write().unwrap() = Arc::new(params);
+}
+
+pub fn load_eval_params(path: &str) -> Result<()> {
+    set_eval_params(EvalParams::load(path)?);
+    Ok(())
+}
+
+impl EvalParams {
+    // Parameter files are JSON, in the same format `talia tune` writes
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read evaluation parameters {path}: {e}"))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| anyhow!("invalid evaluation parameters in {path}: {e}"))
+    }
+
+    pub fn material(&self, piece: Piece) -> Score {
+        self.material[piece as usize]
+    }
+
+    pub fn position(&self, piece: Piece, square: usize, color: Color) -> Score {
+        self.piece_square_tables[piece as usize][Piece::square_table_index(square, color)]
+    }
+
+    // Every weight, in a fixed order, so tuning can treat them as one long list of numbers
+    pub fn weights(&self) -> Vec<i32> {
+        self.clone()
+            .weights_mut()
+            .into_iter()
+            .map(|weight| *weight)
+            .collect()
+    }
+
+    pub fn set_weights(&mut self, weights: &[i32]) {
+        for (weight, &value) in self.weights_mut().into_iter().zip(weights) {
+            *weight = value;
+        }
+    }
+
+    fn weights_mut(&mut self) -> Vec<&mut i32> {
+        let scores = self
+            .material
+            .iter_mut()
+            .chain(self.piece_square_tables.iter_mut().flatten())
+            .chain(self.passed_pawn.iter_mut())
+            .chain([
+                &mut self.isolated_pawn,
+                &mut self.doubled_pawn,
+                &mut self.backward_pawn,
+                &mut self.connected_pawn,
+                &mut self.pawn_island,
+            ])
+            .chain(self.mobility.iter_mut())
+            .chain([
+                &mut self.king_pawn_shield,
+                &mut self.king_open_file,
+                &mut self.king_semi_open_file,
+                &mut self.bishop_pair,
+                &mut self.rook_open_file,
+                &mut self.rook_semi_open_file,
+                &mut self.rook_on_seventh,
+                &mut self.knight_outpost,
+                &mut self.trapped_bishop,
+                &mut self.trapped_rook,
+            ]);
+
+        scores
+            .flat_map(|score| [&mut score.middle_game, &mut score.end_game])
+            .chain(self.king_attack_weight.iter_mut())
+            .chain(self.king_attackers_scale.iter_mut())
+            .collect()
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_EVAL_PARAMS
+    }
+}
+
+// serde only handles arrays of up to 32 elements by itself
+mod piece_square_tables_serde {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::evaluate::Score;
+
+    pub fn serialize<S: Serializer>(
+        tables: &[[Score; 64]; 6],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let tables: Vec<&[Score]> = tables.iter().map(|table| table.as_slice()).collect();
+        tables.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[[Score; 64]; 6], D::Error> {
+        let tables: Vec<Vec<Score>> = Vec::deserialize(deserializer)?;
+        let tables: Vec<[Score; 64]> = tables
+            .into_iter()
+            .map(|table| {
+                table
+                    .try_into()
+                    .map_err(|_| D::Error::custom("a piece square table needs 64 squares"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        tables
+            .try_into()
+            .map_err(|_| D::Error::custom("expected a piece square table for each of 6 pieces"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        evaluate::Score,
+        piece::{Color, Piece},
+    };
+    use anyhow::Result;
+
+    use super::{EvalParams, DEFAULT_EVAL_PARAMS};
+
+    #[test]
+    fn test_default_params_match_piece_tables() {
+        let params = DEFAULT_EVAL_PARAMS;
+
+        assert_eq!(params.material(Piece::Rook).middle_game, Piece::Rook.piece_value());
+        assert_eq!(
+            params.position(Piece::Knight, 18, Color::White).end_game,
+            Piece::Knight.end_game_position_value(18, Color::White)
+        );
+    }
+
+    #[test]
+    fn test_weights_round_trip() {
+        let mut params = EvalParams::default();
+        let mut weights = params.weights();
+        weights[0] += 1;
+        params.set_weights(&weights);
+
+        assert_eq!(params.material(Piece::Pawn).middle_game, 101);
+        assert_eq!(params.weights(), weights);
+    }
+
+    #[test]
+    fn test_load_partial_params_file() -> Result<()> {
+        let path = std::env::temp_dir().join("talia_test_partial_params.json");
+        std::fs::write(&path, r#"{"bishop_pair": {"middle_game": 40, "end_game": 60}}"#)?;
+        let params = EvalParams::load(path.to_str().unwrap())?;
+
+        assert_eq!(params.bishop_pair, Score::new(40, 60));
+        assert_eq!(params.material, DEFAULT_EVAL_PARAMS.material);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_invalid_params_file() -> Result<()> {
+        let path = std::env::temp_dir().join("talia_test_invalid_params.json");
+        std::fs::write(&path, r#"{"bishop_pair": 40}"#)?;
+
+        assert!(EvalParams::load(path.to_str().unwrap()).is_err());
+        assert!(EvalParams::load("/does/not/exist.json").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_json_round_trip() -> Result<()> {
+        let json = serde_json::to_string(&DEFAULT_EVAL_PARAMS)?;
+
+        assert_eq!(serde_json::from_str::<EvalParams>(&json)?, DEFAULT_EVAL_PARAMS);
+
+        Ok(())
+    }
+}
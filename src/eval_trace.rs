@@ -0,0 +1,181 @@
+// A breakdown of the evaluation into its individual terms, for figuring out why Talia likes or
+// dislikes a position
+use std::fmt::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    evaluate::{
+        evaluate_positional_terms, game_phase, pocket_material_by_side, taper_with_endgames,
+        variant_progress_by_side, Score, MAX_PHASE,
+    },
+    move_generation::MoveGenerator,
+    pawn_structure::pawn_structure_by_side,
+    piece::Color,
+    variant::Variant,
+};
+
+#[derive(Debug, Serialize)]
+pub struct EvalTerm {
+    pub name: &'static str,
+    pub white: Score,
+    pub black: Score,
+}
+
+impl EvalTerm {
+    fn new(name: &'static str, scores: [Score; 2]) -> Self {
+        Self {
+            name,
+            white: scores[Color::White as usize],
+            black: scores[Color::Black as usize],
+        }
+    }
+
+    // From white's point of view
+    pub fn total(&self) -> Score {
+        self.white - self.black
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvalTrace {
+    pub terms: Vec<EvalTerm>,
+    pub phase: i32,
+    pub max_phase: i32,
+    // The sum of all terms from white's point of view, before and after tapering. Endgame
+    // knowledge is only applied to the tapered evaluation.
+    pub total: Score,
+    pub eval: i32,
+}
+
+impl EvalTrace {
+    pub fn to_table(&self) -> String {
+        let mut table = String::new();
+        let separator = "-------------+-------------+-------------+-------------";
+
+        // Writing to a String cannot fail
+        writeln!(table, "     Term    |    White    |    Black    |    Total").unwrap();
+        writeln!(table, "             |   MG    EG  |   MG    EG  |   MG    EG").unwrap();
+        writeln!(table, "{separator}").unwrap();
+        for term in &self.terms {
+            writeln!(
+                table,
+                "{:>12} | {} | {} | {}",
+                term.name,
+                format_score(term.white),
+                format_score(term.black),
+                format_score(term.total())
+            )
+            .unwrap();
+        }
+        writeln!(table, "{separator}").unwrap();
+        writeln!(
+            table,
+            "{:>12} | {:>11} | {:>11} | {}",
+            "Total",
+            "",
+            "",
+            format_score(self.total)
+        )
+        .unwrap();
+        writeln!(table).unwrap();
+        writeln!(table, "Phase: {}/{}", self.phase, self.max_phase).unwrap();
+        write!(table, "Evaluation: {} (white side)", self.eval).unwrap();
+
+        table
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+fn format_score(score: Score) -> String {
+    format!("{:>5} {:>5}", score.middle_game, score.end_game)
+}
+
+// Adds up to the same number as evaluate, but from white's point of view
+pub fn trace_evaluation(move_generator: &MoveGenerator) -> EvalTrace {
+    let board = &move_generator.board;
+    let params = &board.eval_params;
+    let positional_terms = evaluate_positional_terms(move_generator, params);
+
+    let mut terms = vec![
+        EvalTerm::new("Material", board.piece_scores.material),
+        EvalTerm::new("PST", board.piece_scores.position),
+        EvalTerm::new("Pawns", pawn_structure_by_side(board, params)),
+        EvalTerm::new("Mobility", positional_terms.mobility),
+        EvalTerm::new("King safety", positional_terms.king_safety),
+        EvalTerm::new("Pieces", positional_terms.pieces),
+    ];
+    match board.variant {
+        Variant::Standard => {}
+        Variant::Crazyhouse => {
+            terms.insert(1, EvalTerm::new("Pockets", pocket_material_by_side(board, params)))
+        }
+        Variant::KingOfTheHill | Variant::ThreeCheck | Variant::Atomic => {
+            terms.push(EvalTerm::new("Variant", variant_progress_by_side(board)))
+        }
+    }
+    let total = terms
+        .iter()
+        .fold(Score::default(), |total, term| total + term.total());
+    let phase = game_phase(board);
+
+    EvalTrace {
+        terms,
+        phase,
+        max_phase: MAX_PHASE,
+        total,
+        eval: taper_with_endgames(board, total),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board_builder::BoardBuilder, evaluate::evaluate, move_generation::MoveGenerator};
+    use anyhow::Result;
+
+    use super::trace_evaluation;
+
+    #[test]
+    fn test_trace_matches_evaluate() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "r1bqkb1r/pp3ppp/2n1pn2/2pp4/3P4/2PBPN2/PP3PPP/RNBQK2R b KQkq - 0 6",
+        )?;
+        let move_generator = MoveGenerator::new(board);
+        let trace = trace_evaluation(&move_generator);
+
+        // Black to move, so evaluate answers from black's point of view
+        assert_eq!(trace.eval, -evaluate(&move_generator));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_table() -> Result<()> {
+        let move_generator =
+            MoveGenerator::new(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1")?);
+        let table = trace_evaluation(&move_generator).to_table();
+
+        assert!(table.contains("    Material |   900   920 |     0     0 |   900   920"));
+        assert!(table.contains("Phase: 4/24"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_json() -> Result<()> {
+        let move_generator =
+            MoveGenerator::new(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1")?);
+        let json: serde_json::Value =
+            serde_json::from_str(&trace_evaluation(&move_generator).to_json()?)?;
+
+        assert_eq!(json["terms"][0]["name"], "Material");
+        assert_eq!(json["terms"][0]["white"]["middle_game"], 900);
+        assert_eq!(json["phase"], 4);
+
+        Ok(())
+    }
+}
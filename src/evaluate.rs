@@ -1,19 +1,129 @@
-use crate::{move_generation::MoveGenerator, piece::Color};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 
-pub fn evaluate(move_generator: &MoveGenerator) -> i32 {
-    let mut eval = 0;
-    let board = &move_generator.board;
+use serde::{Deserialize, Serialize};
 
-    for square in 0..64 {
-        if let Some(piece) = board.squares[square] {
-            if board.colors[square].unwrap() == Color::White {
-                eval += piece.piece_value() + piece.position_value(square, Color::White)
-            } else {
-                eval -= piece.piece_value() + piece.position_value(square, Color::Black)
-            }
+use crate::{
+    bitboard::{adjacent_files, file_mask, pawn_attacks, rank_mask, ranks_ahead},
+    board::{Board, POCKET_PIECES},
+    endgame::{evaluate_endgame, scale_factor, NORMAL_SCALE},
+    eval_params::EvalParams,
+    move_generation::MoveGenerator,
+    pawn_structure::{evaluate_pawn_structure, pawn_bitboards},
+    piece::{Color, Piece},
+    square::Square,
+    variant::{adjacent_squares, checks_given, Variant, HILL_SQUARES},
+};
+
+// Indexed by how many squares the king is away from the nearest hill square
+const HILL_DISTANCE_BONUS: [i32; 4] = [0, 150, 60, 20];
+// Indexed by the number of checks given, the third one wins
+const CHECKS_GIVEN_BONUS: [i32; 4] = [0, 120, 350, 0];
+// Atomic, for each piece next to the king that could be captured to blow the king up with it
+const EXPLOSIVE_NEIGHBOUR_PENALTY: i32 = 20;
+
+// Phase contributed by each piece, indexed by Piece. A full set of pieces adds up to MAX_PHASE.
+// Source: https://www.chessprogramming.org/Tapered_Eval
+const PHASE_WEIGHTS: [i32; 6] = [
+    0, // Pawn
+    1, // Knight
+    1, // Bishop
+    2, // Rook
+    4, // Queen
+    0, // King
+];
+pub const MAX_PHASE: i32 = 24;
+
+// A pair of middle game and end game values, blended together by game phase at the very end
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    pub middle_game: i32,
+    pub end_game: i32,
+}
+
+impl Score {
+    pub const fn new(middle_game: i32, end_game: i32) -> Self {
+        Self {
+            middle_game,
+            end_game,
         }
     }
 
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.middle_game * phase + self.end_game * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self::new(self.middle_game + other.middle_game, self.end_game + other.end_game)
+    }
+}
+
+impl Sub for Score {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.middle_game - other.middle_game, self.end_game - other.end_game)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Score {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.middle_game, -self.end_game)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Self;
+    fn mul(self, factor: i32) -> Self {
+        Self::new(self.middle_game * factor, self.end_game * factor)
+    }
+}
+
+impl Div<i32> for Score {
+    type Output = Self;
+    fn div(self, divisor: i32) -> Self {
+        Self::new(self.middle_game / divisor, self.end_game / divisor)
+    }
+}
+
+pub fn evaluate(move_generator: &MoveGenerator) -> i32 {
+    let board = &move_generator.board;
+    if let Some(accumulator) = &board.accumulator {
+        // The network only knows standard chess, so pieces in hand and the variant's own goals
+        // are added on top
+        let variant_score = evaluate_pockets(board, &board.eval_params) + evaluate_variant(board);
+        let eval = accumulator.evaluate() + variant_score.taper(game_phase(board));
+        return if board.to_move == Color::White {
+            eval
+        } else {
+            -eval
+        };
+    }
+
+    // Accumulated from white's point of view
+    let mut score = board.piece_scores.total();
+    let params = &board.eval_params;
+    score += evaluate_pockets(board, params);
+    score += evaluate_variant(board);
+    score += evaluate_pawn_structure(board, params);
+    score += evaluate_positional_terms(move_generator, params).total();
+
+    let eval = taper_with_endgames(board, score);
+
     if move_generator.board.to_move == Color::White {
         eval
     } else {
@@ -21,11 +131,410 @@ pub fn evaluate(move_generator: &MoveGenerator) -> i32 {
     }
 }
 
+// The classical evaluation from white's point of view with any set of weights. Everything is
+// computed from scratch, which is too slow for search but what tuning needs.
+pub fn evaluate_with_params(move_generator: &MoveGenerator, params: &EvalParams) -> i32 {
+    let board = &move_generator.board;
+
+    let mut score = PieceScores::from_board_with_params(board, params).total();
+    score += evaluate_pockets(board, params);
+    score += evaluate_variant(board);
+    score += evaluate_pawn_structure(board, params);
+    score += evaluate_positional_terms(move_generator, params).total();
+
+    taper_with_endgames(board, score)
+}
+
+// Blends the middle game and end game scores from white's point of view. Drawish endings have
+// their end game score scaled down, and a few endings have a specialised evaluation instead.
+pub fn taper_with_endgames(board: &Board, score: Score) -> i32 {
+    // Few pieces on the board is no ending when captured pieces come back, and the endgame
+    // knowledge is about mating anyway
+    if !board.variant.is_standard() {
+        return score.taper(game_phase(board));
+    }
+    if let Some(eval) = evaluate_endgame(board) {
+        return eval;
+    }
+
+    let strong_side = if score.end_game >= 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let end_game = score.end_game * scale_factor(board, strong_side) / NORMAL_SCALE;
+
+    Score::new(score.middle_game, end_game).taper(game_phase(board))
+}
+
+// Material in the crazyhouse pockets from white's point of view
+pub fn evaluate_pockets(board: &Board, params: &EvalParams) -> Score {
+    let [white, black] = pocket_material_by_side(board, params);
+    white - black
+}
+
+pub fn pocket_material_by_side(board: &Board, params: &EvalParams) -> [Score; 2] {
+    [Color::White, Color::Black].map(|color| {
+        POCKET_PIECES
+            .iter()
+            .fold(Score::default(), |score, &piece| {
+                score + params.material(piece) * board.pockets.count(color, piece) as i32
+            })
+    })
+}
+
+// Progress towards winning by the rules of the variant from white's point of view: the king
+// closing in on the hill in King of the Hill, the checks given so far in Three-check, how
+// exposed the king is to explosions in atomic. Reaching the goal ends the game, so the last
+// step is left to the search.
+pub fn evaluate_variant(board: &Board) -> Score {
+    let [white, black] = variant_progress_by_side(board);
+    white - black
+}
+
+pub fn variant_progress_by_side(board: &Board) -> [Score; 2] {
+    let progress = |color: Color| match board.variant {
+        Variant::KingOfTheHill => {
+            let Some(king_square) = board.king_square(color) else {
+                return 0;
+            };
+            let distance = HILL_SQUARES
+                .iter()
+                .map(|hill| {
+                    let hill = hill.as_index();
+                    (hill % 8)
+                        .abs_diff(king_square % 8)
+                        .max((hill / 8).abs_diff(king_square / 8))
+                })
+                .min()
+                .unwrap_or(0);
+            HILL_DISTANCE_BONUS[distance]
+        }
+        Variant::ThreeCheck => CHECKS_GIVEN_BONUS[checks_given(board, color) as usize],
+        // Friend or foe, any piece but the other king can be captured next to the king. The
+        // kings themselves can't capture, so they never explode each other.
+        Variant::Atomic => {
+            let Some(king_square) = board.king_square(color) else {
+                return 0;
+            };
+            let neighbours = adjacent_squares(king_square)
+                .filter(|&square| board.squares[square].is_some_and(|piece| piece != Piece::King))
+                .count() as i32;
+            -EXPLOSIVE_NEIGHBOUR_PENALTY * neighbours
+        }
+        Variant::Standard | Variant::Crazyhouse => 0,
+    };
+
+    [Color::White, Color::Black].map(|color| {
+        let bonus = progress(color);
+        Score::new(bonus, bonus)
+    })
+}
+
+// MAX_PHASE with all pieces on the board, 0 with only kings and pawns left
+pub fn game_phase(board: &Board) -> i32 {
+    board.piece_scores.game_phase()
+}
+
+// Material, piece-square table values and piece counts of each side, indexed by Color. Board keeps
+// these up to date as pieces come and go, so evaluation never has to scan the board for them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PieceScores {
+    pub material: [Score; 2],
+    pub position: [Score; 2],
+    // Indexed by Color, then Piece
+    pub piece_counts: [[u8; 6]; 2],
+}
+
+impl PieceScores {
+    // The slow way, used to set up a board and to double check the running totals
+    pub fn from_board(board: &Board) -> Self {
+        Self::from_board_with_params(board, &board.eval_params)
+    }
+
+    pub fn from_board_with_params(board: &Board, params: &EvalParams) -> Self {
+        let mut piece_scores = Self::default();
+
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+                piece_scores.add_piece_with_params(piece, color, square, params);
+            }
+        }
+
+        piece_scores
+    }
+
+    pub fn add_piece_with_params(
+        &mut self,
+        piece: Piece,
+        color: Color,
+        square: usize,
+        params: &EvalParams,
+    ) {
+        let side = color as usize;
+        self.material[side] += params.material(piece);
+        self.position[side] += params.position(piece, square, color);
+        self.piece_counts[side][piece as usize] += 1;
+    }
+
+    pub fn remove_piece_with_params(
+        &mut self,
+        piece: Piece,
+        color: Color,
+        square: usize,
+        params: &EvalParams,
+    ) {
+        let side = color as usize;
+        self.material[side] -= params.material(piece);
+        self.position[side] -= params.position(piece, square, color);
+        self.piece_counts[side][piece as usize] -= 1;
+    }
+
+    // Material and piece-square table values from white's point of view
+    pub fn total(&self) -> Score {
+        self.material[0] - self.material[1] + self.position[0] - self.position[1]
+    }
+
+    // Promotions can push the raw count over the maximum, so it is capped
+    pub fn game_phase(&self) -> i32 {
+        let phase: i32 = self
+            .piece_counts
+            .iter()
+            .flat_map(|counts| counts.iter().zip(PHASE_WEIGHTS))
+            .map(|(&count, weight)| count as i32 * weight)
+            .sum();
+
+        std::cmp::min(phase, MAX_PHASE)
+    }
+}
+
+// Piece activity terms for each side, indexed by Color
+#[derive(Debug, Default)]
+pub struct PositionalTerms {
+    pub mobility: [Score; 2],
+    pub king_safety: [Score; 2],
+    pub pieces: [Score; 2],
+}
+
+impl PositionalTerms {
+    // From white's point of view
+    pub fn total(&self) -> Score {
+        let white = Color::White as usize;
+        let black = Color::Black as usize;
+
+        self.mobility[white] - self.mobility[black] + self.king_safety[white]
+            - self.king_safety[black]
+            + self.pieces[white]
+            - self.pieces[black]
+    }
+}
+
+pub fn evaluate_positional_terms(
+    move_generator: &MoveGenerator,
+    params: &EvalParams,
+) -> PositionalTerms {
+    let board = &move_generator.board;
+    let pawns = pawn_bitboards(board);
+    let pawns_attacks = [
+        pawn_attacks(Color::White, pawns[Color::White as usize]),
+        pawn_attacks(Color::Black, pawns[Color::Black as usize]),
+    ];
+
+    let mut occupancy = [0u64; 2];
+    let mut king_squares = [None; 2];
+    for square in 0..64 {
+        if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+            occupancy[color as usize] |= 1 << square;
+            if piece == Piece::King {
+                king_squares[color as usize] = Some(square);
+            }
+        }
+    }
+    let king_zones = king_squares.map(|king_square| match king_square {
+        Some(square) => move_generator.attacks_from(square) | (1 << square),
+        None => 0,
+    });
+
+    let mut terms = PositionalTerms::default();
+    // Indexed by the attacking side
+    let mut king_attackers = [0; 2];
+    let mut king_attack_weight = [0; 2];
+    let mut bishops = [0; 2];
+
+    for square in 0..64 {
+        let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) else {
+            continue;
+        };
+        if piece == Piece::Pawn || piece == Piece::King {
+            continue;
+        }
+
+        let side = color as usize;
+        let enemy = color.opposite_color() as usize;
+        let file = square % 8;
+        let rank = square / 8;
+        let relative_rank = if color == Color::White {
+            rank
+        } else {
+            7 - rank
+        };
+
+        let attacks = move_generator.attacks_from(square);
+        let mobility_area = !occupancy[side] & !pawns_attacks[enemy];
+        let mobility = (attacks & mobility_area).count_ones() as i32;
+        terms.mobility[side] += params.mobility[piece as usize] * mobility;
+
+        let king_zone_attacks = attacks & king_zones[enemy];
+        if king_zone_attacks != 0 {
+            king_attackers[side] += 1;
+            king_attack_weight[side] +=
+                params.king_attack_weight[piece as usize] * king_zone_attacks.count_ones() as i32;
+        }
+
+        match piece {
+            Piece::Knight => {
+                let is_defended_by_pawn = pawns_attacks[side] & (1 << square) != 0;
+                let can_be_chased_by_pawn =
+                    pawns[enemy] & adjacent_files(file) & ranks_ahead(color, rank) != 0;
+                if (3..=5).contains(&relative_rank) && is_defended_by_pawn && !can_be_chased_by_pawn
+                {
+                    terms.pieces[side] += params.knight_outpost;
+                }
+            }
+            Piece::Bishop => {
+                bishops[side] += 1;
+                if is_trapped_bishop(board, square, color) {
+                    terms.pieces[side] += params.trapped_bishop;
+                }
+            }
+            Piece::Rook => {
+                if (pawns[side] | pawns[enemy]) & file_mask(file) == 0 {
+                    terms.pieces[side] += params.rook_open_file;
+                } else if pawns[side] & file_mask(file) == 0 {
+                    terms.pieces[side] += params.rook_semi_open_file;
+                }
+                if relative_rank == 6 {
+                    terms.pieces[side] += params.rook_on_seventh;
+                }
+                if is_trapped_rook(board, king_squares[side], square, color, mobility) {
+                    terms.pieces[side] += params.trapped_rook;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for color in [Color::White, Color::Black] {
+        let side = color as usize;
+        let enemy = color.opposite_color() as usize;
+
+        if bishops[side] >= 2 {
+            terms.pieces[side] += params.bishop_pair;
+        }
+
+        if let Some(king_square) = king_squares[side] {
+            terms.king_safety[side] += king_shelter(king_square, color, pawns, params);
+
+            let attackers = std::cmp::min(king_attackers[enemy], 7);
+            let danger = king_attack_weight[enemy] * params.king_attackers_scale[attackers] / 100;
+            terms.king_safety[side] -= Score::new(danger, 0);
+        }
+    }
+
+    terms
+}
+
+fn king_shelter(king_square: usize, color: Color, pawns: [u64; 2], params: &EvalParams) -> Score {
+    let side = color as usize;
+    let file = king_square % 8;
+    let rank = king_square / 8;
+    let king_files = file_mask(file) | adjacent_files(file);
+    let mut shelter = Score::default();
+
+    let shield_ranks = match color {
+        Color::White if rank <= 1 => rank_mask(rank + 1) | rank_mask(rank + 2),
+        Color::Black if rank >= 6 => rank_mask(rank - 1) | rank_mask(rank - 2),
+        // A king that has wandered up the board has no shield to speak of
+        _ => 0,
+    };
+    let shield_pawns = (pawns[side] & king_files & shield_ranks).count_ones() as i32;
+    shelter += params.king_pawn_shield * shield_pawns;
+
+    for file in file.saturating_sub(1)..=std::cmp::min(file + 1, 7) {
+        if (pawns[0] | pawns[1]) & file_mask(file) == 0 {
+            shelter += params.king_open_file;
+        } else if pawns[side] & file_mask(file) == 0 {
+            shelter += params.king_semi_open_file;
+        }
+    }
+
+    shelter
+}
+
+fn is_trapped_bishop(board: &Board, square: usize, color: Color) -> bool {
+    let trapping_pawn_square = match (color, Square::from_index(square)) {
+        (Color::White, Square::A7) => Square::B6,
+        (Color::White, Square::H7) => Square::G6,
+        (Color::Black, Square::A2) => Square::B3,
+        (Color::Black, Square::H2) => Square::G3,
+        _ => return false,
+    };
+
+    board.is_piece_at_square(trapping_pawn_square.as_index(), Piece::Pawn, color.opposite_color())
+}
+
+// A rook is trapped when it sits in a corner between the edge and its own king on the back rank
+// with nowhere to go, like after Kf1 with the rook still on h1. A rook that can still castle
+// isn't, which matters in Chess960 where the king may start right next to it.
+fn is_trapped_rook(
+    board: &Board,
+    king_square: Option<usize>,
+    square: usize,
+    color: Color,
+    mobility: i32,
+) -> bool {
+    let Some(king_square) = king_square else {
+        return false;
+    };
+    let back_rank = if color == Color::White { 0 } else { 7 };
+    if square / 8 != back_rank || king_square / 8 != back_rank || mobility > 3 {
+        return false;
+    }
+
+    let king_file = king_square % 8;
+    let rook_file = square % 8;
+    let kingside = rook_file > king_file;
+    let state = &board.board_state;
+    let can_castle = match (color, kingside) {
+        (Color::White, true) => state.white_kingside_castling_priviledge,
+        (Color::White, false) => state.white_queenside_castling_priviledge,
+        (Color::Black, true) => state.black_kingside_castling_priviledge,
+        (Color::Black, false) => state.black_queenside_castling_priviledge,
+    };
+    if can_castle && state.castling_squares.rook(color, kingside) == square {
+        return false;
+    }
+
+    (king_file >= 5 && kingside) || (king_file <= 2 && !kingside)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{board::Board, move_generation::MoveGenerator};
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::MoveGenerator,
+        piece::{Color, Piece},
+        square::Square,
+        variant::Variant,
+    };
+    use anyhow::Result;
 
-    use super::evaluate;
+    use super::{
+        evaluate, evaluate_pockets, evaluate_positional_terms, evaluate_variant, game_phase, Score,
+        MAX_PHASE,
+    };
+    use crate::eval_params::DEFAULT_EVAL_PARAMS;
 
     #[test]
     fn test_starting_position_eval() {
@@ -35,4 +544,219 @@ mod tests {
         let eval = evaluate(&move_generator);
         assert!(eval == 0);
     }
+
+    #[test]
+    fn test_starting_position_is_middle_game() {
+        assert_eq!(game_phase(&Board::starting_position()), MAX_PHASE);
+    }
+
+    #[test]
+    fn test_king_and_pawn_ending_is_end_game() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1")?;
+
+        assert_eq!(game_phase(&board), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_taper_interpolates_between_phases() {
+        let score = Score::new(100, 200);
+
+        assert_eq!(score.taper(MAX_PHASE), 100);
+        assert_eq!(score.taper(0), 200);
+        assert_eq!(score.taper(MAX_PHASE / 2), 150);
+    }
+
+    #[test]
+    fn test_end_game_king_prefers_the_centre() -> Result<()> {
+        let centralised_king: Board = BoardBuilder::new()
+            .piece(Square::E4, Piece::King, Color::White)
+            .piece(Square::E8, Piece::King, Color::Black)
+            .piece(Square::A2, Piece::Pawn, Color::White)
+            // Otherwise the KPK bitbase knows the exact result
+            .piece(Square::H7, Piece::Pawn, Color::Black)
+            .try_into()?;
+        let cornered_king: Board = BoardBuilder::new()
+            .piece(Square::H1, Piece::King, Color::White)
+            .piece(Square::E8, Piece::King, Color::Black)
+            .piece(Square::A2, Piece::Pawn, Color::White)
+            .piece(Square::H7, Piece::Pawn, Color::Black)
+            .try_into()?;
+
+        let centralised_eval = evaluate(&MoveGenerator::new(centralised_king));
+        let cornered_eval = evaluate(&MoveGenerator::new(cornered_king));
+
+        assert!(centralised_eval > cornered_eval);
+
+        Ok(())
+    }
+
+    fn positional_terms(fen: &str) -> Result<super::PositionalTerms> {
+        let move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen)?);
+        Ok(evaluate_positional_terms(&move_generator, &DEFAULT_EVAL_PARAMS))
+    }
+
+    #[test]
+    fn test_starting_position_positional_terms_are_balanced() -> Result<()> {
+        let terms = positional_terms("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")?;
+
+        assert_eq!(terms.total(), Score::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_centralised_knight_is_more_mobile() -> Result<()> {
+        let centralised = positional_terms("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1")?;
+        let cornered = positional_terms("4k3/8/8/8/8/8/8/N3K3 w - - 0 1")?;
+
+        assert!(centralised.mobility[0].middle_game > cornered.mobility[0].middle_game);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bishop_pair() -> Result<()> {
+        let terms = positional_terms("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1")?;
+        let single_bishop_terms = positional_terms("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1")?;
+
+        assert_eq!(
+            terms.pieces[0] - single_bishop_terms.pieces[0],
+            DEFAULT_EVAL_PARAMS.bishop_pair
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rook_on_open_and_semi_open_files() -> Result<()> {
+        // The a-file is open, black's d-pawn leaves the d-file semi-open for white
+        let terms = positional_terms("4k3/3p4/8/8/8/8/8/R2RK3 w - - 0 1")?;
+
+        assert_eq!(
+            terms.pieces[0],
+            DEFAULT_EVAL_PARAMS.rook_open_file + DEFAULT_EVAL_PARAMS.rook_semi_open_file
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rook_on_seventh() -> Result<()> {
+        let terms = positional_terms("4k3/1R6/8/8/8/8/8/4K3 w - - 0 1")?;
+
+        assert_eq!(
+            terms.pieces[0],
+            DEFAULT_EVAL_PARAMS.rook_open_file + DEFAULT_EVAL_PARAMS.rook_on_seventh
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_knight_outpost() -> Result<()> {
+        // The e5 knight is defended by d4 and no black pawn can ever attack it
+        let outpost = positional_terms("4k3/p7/8/4N3/3P4/8/8/4K3 w - - 0 1")?;
+        // Black's f7 pawn can chase it away
+        let no_outpost = positional_terms("4k3/5p2/8/4N3/3P4/8/8/4K3 w - - 0 1")?;
+
+        assert_eq!(outpost.pieces[0], DEFAULT_EVAL_PARAMS.knight_outpost);
+        assert_eq!(no_outpost.pieces[0], Score::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trapped_bishop() -> Result<()> {
+        let terms = positional_terms("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1")?;
+
+        assert_eq!(terms.pieces[0], DEFAULT_EVAL_PARAMS.trapped_bishop);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trapped_rook() -> Result<()> {
+        let terms = positional_terms("4k3/8/8/8/8/8/6PP/5K1R w - - 0 1")?;
+
+        assert_eq!(terms.pieces[0], DEFAULT_EVAL_PARAMS.trapped_rook);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rook_that_can_castle_is_not_trapped() -> Result<()> {
+        // Chess960 start position with the king on g1 and the rook on h1
+        let fen = "rbbqnnkr/pppppppp/8/8/8/8/PPPPPPPP/RBBQNNKR w HAha - 0 1";
+        let terms = positional_terms(fen)?;
+
+        assert_eq!(terms.pieces[0], DEFAULT_EVAL_PARAMS.bishop_pair);
+
+        let without_castling = positional_terms(&fen.replace("HAha", "-"))?;
+        assert_eq!(
+            without_castling.pieces[0],
+            DEFAULT_EVAL_PARAMS.bishop_pair + DEFAULT_EVAL_PARAMS.trapped_rook
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pawn_shield_and_open_files_near_king() -> Result<()> {
+        let sheltered = positional_terms("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1")?;
+        let exposed = positional_terms("4k3/8/8/8/8/8/8/6K1 w - - 0 1")?;
+
+        assert_eq!(sheltered.king_safety[0], DEFAULT_EVAL_PARAMS.king_pawn_shield * 3);
+        assert_eq!(exposed.king_safety[0], DEFAULT_EVAL_PARAMS.king_open_file * 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_king_attackers_reduce_king_safety() -> Result<()> {
+        let attacked = positional_terms("6k1/5ppp/8/8/8/8/1Q6/4KR2 b - - 0 1")?;
+        let single_attacker = positional_terms("6k1/5ppp/8/8/8/8/1Q6/4K3 b - - 0 1")?;
+
+        assert!(attacked.king_safety[1].middle_game < single_attacker.king_safety[1].middle_game);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pieces_in_hand_count_as_material() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/pp6/8/8/8/8/6PP/4K3[QNp] w - - 0 1")?;
+        let material = |piece| DEFAULT_EVAL_PARAMS.material(piece);
+        assert_eq!(
+            evaluate_pockets(&board, &DEFAULT_EVAL_PARAMS),
+            material(Piece::Queen) + material(Piece::Knight) - material(Piece::Pawn)
+        );
+
+        // A bare king ending on the board is nothing special with pieces in hand
+        let without_pockets = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3[] w - - 0 1")?;
+        let with_pockets = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3[Q] w - - 0 1")?;
+        assert_eq!(evaluate(&MoveGenerator::new(without_pockets)), 0);
+        assert!(evaluate(&MoveGenerator::new(with_pockets)) > 800);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_variant_progress() -> Result<()> {
+        // One step from the hill against three
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/4K3/8/8 w - - 0 1")?;
+        assert_eq!(evaluate_variant(&board), Score::default());
+        board.variant = Variant::KingOfTheHill;
+        assert_eq!(evaluate_variant(&board), Score::new(130, 130));
+
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/4K3/8/8 w - - 0 1 +2+1")?;
+        assert_eq!(evaluate_variant(&board), Score::new(230, 230));
+
+        // Two pieces next to the white king could be taken to blow it up
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/3Pn3/4K3 w - - 0 1")?;
+        board.variant = Variant::Atomic;
+        assert_eq!(evaluate_variant(&board), Score::new(-40, -40));
+
+        Ok(())
+    }
 }
@@ -5,6 +5,7 @@ use crate::{
     board::Board,
     board_builder::BoardBuilder,
     move_generation::{Move, MoveGenerator},
+    pgn::{GameResult, PgnGame},
     piece::Color,
     search::{find_best_move, COUNTER},
 };
@@ -13,12 +14,17 @@ enum GameState {
     Active,
     Checkmate,
     Stalemate,
+    // Lost by a rule of the variant, like the opponent's king reaching the hill
+    VariantLoss,
 }
 
 pub struct Game {
     player_color: Option<Color>,
     board: Board,
     engine_search_depth: u32,
+    // Kept so the game can be written out as PGN once it is over
+    starting_board: Board,
+    moves: Vec<Move>,
 }
 
 impl Game {
@@ -31,8 +37,10 @@ impl Game {
 
         Ok(Self {
             player_color,
+            starting_board: board.clone(),
             board,
             engine_search_depth,
+            moves: Vec::new(),
         })
     }
 
@@ -40,16 +48,8 @@ impl Game {
     pub fn start_game(&mut self) -> Result<()> {
         loop {
             let mut move_generator = MoveGenerator::new(self.board.clone());
-            match self.check_game_state(&mut move_generator) {
-                GameState::Active => {}
-                GameState::Checkmate => {
-                    println!("Checkmate!");
-                    return Ok(());
-                }
-                GameState::Stalemate => {
-                    println!("Stalemate!");
-                    return Ok(());
-                }
+            if self.announce_game_over(&mut move_generator) {
+                return self.print_pgn();
             }
 
             if self
@@ -58,8 +58,16 @@ impl Game {
             {
                 println!("{}", self.board);
                 let input = self.get_uci_move_input();
-                match Move::try_from_uci(&input, &mut move_generator) {
-                    Ok(mv) => self.board.move_piece(&mv),
+                // Either notation will do, as long as the move is legal
+                let mv =
+                    Move::try_from_san(&input, &mut move_generator).or_else(|error| {
+                        match input.len() {
+                            4 | 5 => Move::try_from_uci(&input, &mut move_generator),
+                            _ => Err(error),
+                        }
+                    });
+                match mv {
+                    Ok(mv) => self.play_move(mv),
                     Err(error) => println!("{}", error),
                 }
             } else {
@@ -69,16 +77,8 @@ impl Game {
                     println!("{}", self.board);
                 }
 
-                match self.check_game_state(&mut move_generator) {
-                    GameState::Active => {}
-                    GameState::Checkmate => {
-                        println!("Checkmate!");
-                        return Ok(());
-                    }
-                    GameState::Stalemate => {
-                        println!("Stalemate!");
-                        return Ok(());
-                    }
+                if self.announce_game_over(&mut move_generator) {
+                    return self.print_pgn();
                 }
 
                 println!("Talia is thinking ...");
@@ -87,6 +87,7 @@ impl Game {
                     &mut move_generator.generate_moves(),
                     &mut move_generator,
                     self.engine_search_depth,
+                    0,
                 );
                 let end_time = std::time::Instant::now();
                 let elapsed_time = end_time.duration_since(start_time).as_millis();
@@ -97,14 +98,14 @@ impl Game {
                     self.engine_search_depth
                 );
 
-                println!("Best move: {:?}", best_move);
+                println!("Best move: {}", best_move.to_san(&self.board));
 
                 // Display the eval without perspective.
                 // Positive eval: white has advantage, negative eval: black has advantage
                 if move_generator.board.to_move == Color::Black {
                     best_eval *= -1
                 }
-                self.board.move_piece(&best_move);
+                self.play_move(best_move);
                 println!("Eval: {best_eval}")
             }
         }
@@ -124,11 +125,45 @@ impl Game {
         }
     }
 
+    fn play_move(&mut self, mv: Move) {
+        self.board.move_piece(&mv);
+        self.moves.push(mv);
+    }
+
+    pub fn to_pgn(&self) -> Result<String> {
+        let result = match self.check_game_state(&mut MoveGenerator::new(self.board.clone())) {
+            GameState::Active => GameResult::Unknown,
+            GameState::Stalemate => GameResult::Draw,
+            GameState::Checkmate | GameState::VariantLoss => match self.board.to_move {
+                Color::White => GameResult::BlackWins,
+                Color::Black => GameResult::WhiteWins,
+            },
+        };
+        let mut game = PgnGame::from_moves(&self.starting_board, &self.moves, result)?;
+        game.set_tag("Event", "Talia CLI game");
+        let (white, black) = match self.player_color {
+            Some(Color::White) => ("Human", "Talia"),
+            Some(Color::Black) => ("Talia", "Human"),
+            None => ("Talia", "Talia"),
+        };
+        game.set_tag("White", white);
+        game.set_tag("Black", black);
+
+        game.to_pgn()
+    }
+
+    fn print_pgn(&self) -> Result<()> {
+        println!("{}", self.to_pgn()?);
+        Ok(())
+    }
+
     fn check_game_state(&self, move_generator: &mut MoveGenerator) -> GameState {
         let moves = move_generator.generate_moves();
         match moves.is_empty() {
             true => {
-                if move_generator.is_in_check(move_generator.board.to_move) {
+                if move_generator.board.variant.is_lost(&move_generator.board) {
+                    GameState::VariantLoss
+                } else if move_generator.is_in_check(move_generator.board.to_move) {
                     GameState::Checkmate
                 } else {
                     GameState::Stalemate
@@ -138,6 +173,20 @@ impl Game {
         }
     }
 
+    // Prints how the game ended, if it did. The winner is always the side that is not to move.
+    fn announce_game_over(&self, move_generator: &mut MoveGenerator) -> bool {
+        let winner = self.board.to_move.opposite_color();
+        match self.check_game_state(move_generator) {
+            GameState::Active => return false,
+            GameState::Checkmate => println!("Checkmate! {winner:?} wins"),
+            GameState::Stalemate => println!("Stalemate!"),
+            GameState::VariantLoss => {
+                println!("{winner:?} wins by {}", self.board.variant.pgn_name())
+            }
+        }
+        true
+    }
+
     fn get_uci_move_input(&self) -> String {
         let mut input = String::new();
         std::io::stdin()
@@ -147,3 +196,28 @@ impl Game {
         input.trim().to_owned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        move_generation::{Flag, Move},
+        piece::Color,
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::Game;
+
+    #[test]
+    fn test_game_to_pgn() -> Result<()> {
+        let mut game =
+            Game::try_from_fen("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 30", Some(Color::Black), 4)?;
+        game.play_move(Move::from_square(Square::A1, Square::A8, Flag::None));
+        let pgn = game.to_pgn()?;
+
+        assert!(pgn.contains("[White \"Talia\"]\n[Black \"Human\"]\n[Result \"1-0\"]\n"));
+        assert!(pgn.contains("[FEN \"6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 30\"]\n"));
+        assert!(pgn.ends_with("\n30. Ra8# 1-0\n"));
+        Ok(())
+    }
+}
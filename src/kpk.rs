@@ -0,0 +1,358 @@
+// King and pawn against king bitbase, generated by retrograde analysis the first time it is needed
+// Source: https://www.chessprogramming.org/KPK
+//
+// Positions are stored from the point of view of the side with the pawn ("white"), with the pawn
+// mirrored onto files a-d. Every position is first classified from what is known right away
+// (illegal positions, safe promotions, stalemates, the pawn being captured), after which the
+// unknown positions are resolved by looking at their successors until nothing changes any more.
+// Whatever is still unknown at that point is a draw.
+use std::sync::OnceLock;
+
+use crate::{
+    board::Board,
+    endgame::distance,
+    piece::{Color, Piece},
+};
+
+// Side to move * king square * king square * pawn on files a-d and ranks 2-7
+const POSITION_COUNT: usize = 2 * 64 * 64 * 24;
+
+static KPK_BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+// Generating takes a moment, so the engine does it at startup instead of in the middle of a search
+pub fn init() {
+    KPK_BITBASE.get_or_init(generate);
+}
+
+// Whether the side with the pawn wins with best play. Squares are board indexes, with a1 = 0.
+pub fn probe(
+    strong_side: Color,
+    strong_king: usize,
+    pawn: usize,
+    weak_king: usize,
+    side_to_move: Color,
+) -> bool {
+    // Flip the board so the pawn moves up it
+    let (mut strong_king, mut pawn, mut weak_king) = match strong_side {
+        Color::White => (strong_king, pawn, weak_king),
+        Color::Black => (strong_king ^ 56, pawn ^ 56, weak_king ^ 56),
+    };
+    // Mirror the pawn onto the queen side
+    if pawn % 8 >= 4 {
+        strong_king ^= 7;
+        pawn ^= 7;
+        weak_king ^= 7;
+    }
+    let strong_to_move = side_to_move == strong_side;
+
+    let index = index(strong_to_move, strong_king, weak_king, pawn);
+    let bitbase = KPK_BITBASE.get_or_init(generate);
+    bitbase[index / 64] & (1 << (index % 64)) != 0
+}
+
+// Some(true) if the board is a won KPK ending, Some(false) if it is a drawn one, or None if it
+// is not KPK at all
+pub fn probe_board(board: &Board) -> Option<bool> {
+    if !board.variant.is_standard() {
+        return None;
+    }
+    let counts = &board.piece_scores.piece_counts;
+    let strong_side = [Color::White, Color::Black].into_iter().find(|&color| {
+        let is_lone_pawn = counts[color as usize][..Piece::King as usize] == [1, 0, 0, 0, 0];
+        let is_bare_king =
+            counts[color.opposite_color() as usize][..Piece::King as usize] == [0, 0, 0, 0, 0];
+        is_lone_pawn && is_bare_king
+    })?;
+
+    let find = |piece: Piece, color: Color| {
+        (0..64).find(|&square| board.is_piece_at_square(square, piece, color))
+    };
+    let strong_king = find(Piece::King, strong_side)?;
+    let pawn = find(Piece::Pawn, strong_side)?;
+    // Only unchecked boards can have a pawn on the first or last rank, and the bitbase has no
+    // room for one there
+    if !(8..56).contains(&pawn) {
+        return None;
+    }
+    let weak_king = find(Piece::King, strong_side.opposite_color())?;
+
+    Some(probe(strong_side, strong_king, pawn, weak_king, board.to_move))
+}
+
+fn index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    let pawn_index = (pawn % 8) + 4 * (pawn / 8 - 1);
+    (white_to_move as usize) | (black_king << 1) | (white_king << 7) | (pawn_index << 13)
+}
+
+fn generate() -> Vec<u64> {
+    let mut outcomes = vec![Outcome::Invalid; POSITION_COUNT];
+    for_each_position(|index, white_to_move, white_king, black_king, pawn| {
+        outcomes[index] = initial_outcome(white_to_move, white_king, black_king, pawn);
+    });
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for_each_position(|index, white_to_move, white_king, black_king, pawn| {
+            if outcomes[index] != Outcome::Unknown {
+                return;
+            }
+            let outcome = if white_to_move {
+                white_outcome(&outcomes, white_king, black_king, pawn)
+            } else {
+                black_outcome(&outcomes, white_king, black_king, pawn)
+            };
+            if outcome != Outcome::Unknown {
+                outcomes[index] = outcome;
+                changed = true;
+            }
+        });
+    }
+
+    let mut bitbase = vec![0u64; POSITION_COUNT / 64];
+    for (index, outcome) in outcomes.iter().enumerate() {
+        if *outcome == Outcome::Win {
+            bitbase[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    bitbase
+}
+
+fn for_each_position(mut f: impl FnMut(usize, bool, usize, usize, usize)) {
+    for rank in 1..7 {
+        for file in 0..4 {
+            let pawn = rank * 8 + file;
+            for white_king in 0..64 {
+                for black_king in 0..64 {
+                    for white_to_move in [false, true] {
+                        let index = index(white_to_move, white_king, black_king, pawn);
+                        f(index, white_to_move, white_king, black_king, pawn);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn initial_outcome(
+    white_to_move: bool,
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> Outcome {
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        // The side that just moved cannot have left the black king in check
+        || (white_to_move && pawn_attacks(pawn) & (1 << black_king) != 0)
+    {
+        return Outcome::Invalid;
+    }
+
+    if white_to_move && pawn / 8 == 6 {
+        let promotion_square = pawn + 8;
+        let is_free = promotion_square != white_king && promotion_square != black_king;
+        let is_safe = distance(black_king, promotion_square) > 1
+            || distance(white_king, promotion_square) == 1;
+        if is_free && is_safe {
+            return Outcome::Win;
+        }
+    }
+
+    if !white_to_move {
+        let can_take_pawn = distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1;
+        if can_take_pawn
+            || black_king_moves(white_king, black_king, pawn)
+                .next()
+                .is_none()
+        {
+            return Outcome::Draw;
+        }
+    }
+
+    Outcome::Unknown
+}
+
+// Win as soon as one move wins, draw once every move draws
+fn white_outcome(
+    outcomes: &[Outcome],
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> Outcome {
+    let mut all_draw = true;
+
+    let king_moves = king_moves(white_king)
+        .filter(|&square| square != pawn && distance(square, black_king) > 1)
+        .map(|square| outcomes[index(false, square, black_king, pawn)]);
+    let pawn_moves = pawn_pushes(white_king, black_king, pawn)
+        .map(|square| outcomes[index(false, white_king, black_king, square)]);
+
+    for outcome in king_moves.chain(pawn_moves) {
+        match outcome {
+            Outcome::Win => return Outcome::Win,
+            Outcome::Draw | Outcome::Invalid => {}
+            Outcome::Unknown => all_draw = false,
+        }
+    }
+
+    // Promoting from the seventh rank without winning means the new queen gets taken
+    if all_draw {
+        Outcome::Draw
+    } else {
+        Outcome::Unknown
+    }
+}
+
+// Draw as soon as one move draws, win once every move loses
+fn black_outcome(
+    outcomes: &[Outcome],
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> Outcome {
+    let mut all_win = true;
+
+    for square in black_king_moves(white_king, black_king, pawn) {
+        match outcomes[index(true, white_king, square, pawn)] {
+            Outcome::Draw => return Outcome::Draw,
+            Outcome::Win | Outcome::Invalid => {}
+            Outcome::Unknown => all_win = false,
+        }
+    }
+
+    if all_win {
+        Outcome::Win
+    } else {
+        Outcome::Unknown
+    }
+}
+
+fn king_moves(square: usize) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |&target| distance(square, target) == 1)
+}
+
+// Squares the black king can step to, a capture of the pawn included when it is undefended
+fn black_king_moves(
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> impl Iterator<Item = usize> {
+    king_moves(black_king).filter(move |&square| {
+        distance(square, white_king) > 1 && pawn_attacks(pawn) & (1 << square) == 0
+    })
+}
+
+// Pushes that stay on the board as a pawn, promotions are settled in initial_outcome
+fn pawn_pushes(white_king: usize, black_king: usize, pawn: usize) -> impl Iterator<Item = usize> {
+    let is_free = move |square: usize| square != white_king && square != black_king;
+    let single_push = pawn + 8;
+    let can_push = pawn / 8 < 6 && is_free(single_push);
+    let can_double_push = can_push && pawn / 8 == 1 && is_free(pawn + 16);
+
+    [
+        can_push.then_some(single_push),
+        can_double_push.then_some(pawn + 16),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn pawn_attacks(pawn: usize) -> u64 {
+    let file = pawn % 8;
+    let mut attacks = 0;
+    if file > 0 {
+        attacks |= 1 << (pawn + 7);
+    }
+    if file < 7 {
+        attacks |= 1 << (pawn + 9);
+    }
+    attacks
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board_builder::BoardBuilder, piece::Color};
+    use anyhow::Result;
+
+    use super::{probe, probe_board};
+
+    fn kpk_result(fen: &str) -> Result<Option<bool>> {
+        Ok(probe_board(&BoardBuilder::try_from_fen(fen)?))
+    }
+
+    #[test]
+    fn test_king_in_front_of_pawn_wins() -> Result<()> {
+        // White king on the sixth rank in front of its pawn wins no matter who moves
+        assert_eq!(kpk_result("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1")?, Some(true));
+        assert_eq!(kpk_result("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")?, Some(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_opposition_decides() -> Result<()> {
+        // With the pawn behind the king on the fifth rank, the side to move loses the opposition
+        assert_eq!(kpk_result("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1")?, Some(false));
+        assert_eq!(kpk_result("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1")?, Some(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rook_pawn_with_king_in_corner_is_drawn() -> Result<()> {
+        assert_eq!(kpk_result("7k/8/6K1/7P/8/8/8/8 w - - 0 1")?, Some(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pawn_outruns_king() -> Result<()> {
+        // The black king only gets into the square of the pawn when it moves first
+        assert_eq!(kpk_result("8/8/8/8/P7/4k3/8/K7 w - - 0 1")?, Some(true));
+        assert_eq!(kpk_result("8/8/8/8/P7/4k3/8/K7 b - - 0 1")?, Some(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_black_pawn_is_flipped() -> Result<()> {
+        assert_eq!(kpk_result("8/8/8/4p3/4k3/8/8/4K3 b - - 0 1")?, Some(true));
+        assert_eq!(kpk_result("8/8/8/4p3/4k3/8/8/4K3 w - - 0 1")?, Some(false));
+        assert_eq!(
+            probe(Color::Black, 28, 36, 4, Color::Black),
+            probe(Color::White, 36, 28, 60, Color::White)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_not_kpk() -> Result<()> {
+        assert_eq!(kpk_result("4k3/8/8/4K3/4P3/8/8/7R w - - 0 1")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pawn_on_back_rank_is_not_probed() -> Result<()> {
+        for fen in [
+            "4k3/8/8/8/8/8/8/3PK3 w - - 0 1",
+            "3Pk3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            let board = BoardBuilder::try_from_fen_unchecked(fen)?;
+            assert_eq!(probe_board(&board), None);
+        }
+
+        Ok(())
+    }
+}
@@ -1,17 +1,38 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
+pub mod bitboard;
 pub mod board;
 pub mod board_builder;
+pub mod book;
+pub mod book_builder;
 pub mod bot;
+pub mod endgame;
+pub mod epd;
+pub mod eval_params;
+pub mod eval_trace;
 pub mod evaluate;
 pub mod game_manager;
+pub mod kpk;
 pub mod move_generation;
+pub mod nnue;
+pub mod pawn_structure;
+pub mod pgn;
 pub mod piece;
 pub mod piece_square_table;
+pub mod polyglot_keys;
 pub mod search;
 pub mod square;
+pub mod syzygy;
+pub mod tablebase;
+pub mod tune;
+pub mod variant;
+pub mod zobrist;
+use crate::book_builder::BookBuildOptions;
 use crate::bot::Bot;
+use crate::epd::EpdRunOptions;
 use crate::game_manager::Game;
 use crate::piece::Color;
 
@@ -20,20 +41,128 @@ use crate::piece::Color;
 struct Args {
     #[arg(long, default_value_t = false)]
     cli: bool,
+    /// Evaluate with this network instead of the classical evaluation
+    #[arg(long)]
+    eval_file: Option<String>,
+    /// JSON file with evaluation weights, as written by `talia tune`
+    #[arg(long)]
+    eval_params: Option<String>,
+    /// Polyglot opening book to play the first moves from
+    #[arg(long)]
+    book: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Tune the evaluation weights on a dataset of positions labelled with game results
+    Tune {
+        dataset: String,
+        #[arg(long, default_value = "tuned_params.json")]
+        output: String,
+        #[arg(long, default_value_t = 100)]
+        iterations: u32,
+    },
+    /// Work with Polyglot opening books
+    Book {
+        #[command(subcommand)]
+        command: BookCommand,
+    },
+    /// Run a test suite of EPD positions and check the moves against their bm and am
+    Epd {
+        suite: String,
+        #[arg(long, default_value_t = 4)]
+        depth: u32,
+        /// Keep deepening until this many milliseconds have passed
+        #[arg(long)]
+        movetime: Option<u64>,
+        /// Also write the results as JSON to this file
+        #[arg(long)]
+        json: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BookCommand {
+    /// Build a book from the opening moves of the games in PGN files
+    Build {
+        #[arg(required = true)]
+        pgn: Vec<String>,
+        #[arg(long, default_value = "book.bin")]
+        output: String,
+        /// Only count moves played in the first this many plies of a game
+        #[arg(long, default_value_t = 20)]
+        max_ply: u32,
+        /// Leave out moves played in fewer games than this
+        #[arg(long, default_value_t = 3)]
+        min_games: u32,
+        /// Leave out moves that scored less than this fraction of the points
+        #[arg(long, default_value_t = 0.0)]
+        min_score: f64,
+    },
 }
 
 fn main() -> Result<()> {
     println!("Talia Chess Engine: v1.1.1");
     let args = Args::parse();
+    if let Some(eval_params) = &args.eval_params {
+        eval_params::load_eval_params(eval_params)?;
+    }
+    if let Some(eval_file) = &args.eval_file {
+        nnue::load_network(eval_file)?;
+    }
+    if let Some(book) = &args.book {
+        book::load_book(book)?;
+        book::OWN_BOOK.store(true, Ordering::Relaxed);
+    }
+    kpk::init();
 
-    if args.cli {
-        let search_depth = 6;
-        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let mut game = Game::try_from_fen(fen, Some(Color::White), search_depth)?;
-        game.start_game()?;
-    } else {
-        let mut bot = Bot::new();
-        bot.start_uci()?;
+    match &args.command {
+        Some(Command::Tune {
+            dataset,
+            output,
+            iterations,
+        }) => tune::run_tuning(dataset, output, *iterations)?,
+        Some(Command::Book {
+            command:
+                BookCommand::Build {
+                    pgn,
+                    output,
+                    max_ply,
+                    min_games,
+                    min_score,
+                },
+        }) => {
+            let options = BookBuildOptions {
+                max_ply: *max_ply,
+                min_games: *min_games,
+                min_score: *min_score,
+            };
+            book_builder::run_book_build(pgn, output, options)?;
+        }
+        Some(Command::Epd {
+            suite,
+            depth,
+            movetime,
+            json,
+        }) => {
+            let options = EpdRunOptions {
+                depth: *depth,
+                move_time: movetime.map(Duration::from_millis),
+            };
+            epd::run_epd(suite, options, json.as_deref())?;
+        }
+        None if args.cli => {
+            let search_depth = 6;
+            let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+            let mut game = Game::try_from_fen(fen, Some(Color::White), search_depth)?;
+            game.start_game()?;
+        }
+        None => {
+            let mut bot = Bot::new();
+            bot.start_uci()?;
+        }
     }
 
     Ok(())
@@ -1,9 +1,11 @@
 use anyhow::{anyhow, bail, Result};
 use core::fmt;
+use std::ops::RangeInclusive;
 
-use crate::board::Board;
+use crate::board::{Board, CastlingSquares, POCKET_PIECES};
 use crate::piece::{Color, Piece};
 use crate::square::Square;
+use crate::variant::{adjacent_squares, Variant};
 
 #[derive(Eq, PartialEq, Clone)]
 pub struct Move {
@@ -33,6 +35,17 @@ impl Move {
         algebraic_notation: &str,
         move_generator: &mut MoveGenerator,
     ) -> Result<Self> {
+        // Drops are written as P@e4, with the piece in upper case for either side
+        if let Some((piece, square)) = algebraic_notation.split_once('@') {
+            let piece = Self::drop_piece(piece)?;
+            let target_square = Square::from_algebraic_notation(square)?.as_index();
+            return move_generator
+                .generate_moves()
+                .into_iter()
+                .find(|mv| mv.flag == Flag::Drop(piece) && mv.target_square == target_square)
+                .ok_or(anyhow!("Not a legal move"));
+        }
+
         let promotion_piece = match algebraic_notation.chars().nth(4) {
             Some('q') => Some(Piece::Queen),
             Some('r') => Some(Piece::Rook),
@@ -71,16 +84,177 @@ impl Move {
                 .ok_or(anyhow!("Not a legal move")),
         }
     }
+
+    // Finds the legal move written in standard algebraic notation, such as Nbd7, exd6 or
+    // e8=Q+. Castling with zeros, promotions without the = sign, en passant marks (exd6e.p.),
+    // long algebraic notation (Ng1-f3) and annotations (e4!?) are accepted too.
+    pub fn try_from_san(san: &str, move_generator: &mut MoveGenerator) -> Result<Self> {
+        let trimmed = san
+            .trim_end_matches(['+', '#', '!', '?'])
+            .trim_end_matches("e.p.")
+            .trim_end_matches("ep");
+        let legal_moves = move_generator.generate_moves();
+
+        let castle = match trimmed {
+            "O-O" | "0-0" => Some(Flag::KingsideCastle),
+            "O-O-O" | "0-0-0" => Some(Flag::QueensideCastle),
+            _ => None,
+        };
+        if let Some(castle) = castle {
+            return legal_moves
+                .into_iter()
+                .find(|mv| mv.flag == castle)
+                .ok_or_else(|| anyhow!("illegal move: {san}"));
+        }
+
+        if let Some((piece, square)) = trimmed.split_once('@') {
+            // Pawn drops may leave out the P
+            let piece = match piece {
+                "" => Piece::Pawn,
+                piece => Self::drop_piece(piece)?,
+            };
+            let target = Square::from_algebraic_notation(square)?.as_index();
+            return legal_moves
+                .into_iter()
+                .find(|mv| mv.flag == Flag::Drop(piece) && mv.target_square == target)
+                .ok_or_else(|| anyhow!("illegal move: {san}"));
+        }
+
+        let mut rest: String = trimmed.chars().filter(|&c| c != 'x' && c != '-').collect();
+        let promotion = match rest.chars().last() {
+            Some(letter) if "NBRQnbrq".contains(letter) => {
+                rest.pop();
+                rest = rest.trim_end_matches('=').to_string();
+                match letter.to_ascii_uppercase() {
+                    'N' => Some(Piece::Knight),
+                    'B' => Some(Piece::Bishop),
+                    'R' => Some(Piece::Rook),
+                    _ => Some(Piece::Queen),
+                }
+            }
+            _ => None,
+        };
+        let piece = match rest.chars().next() {
+            Some('N') => Piece::Knight,
+            Some('B') => Piece::Bishop,
+            Some('R') => Piece::Rook,
+            Some('Q') => Piece::Queen,
+            Some('K') => Piece::King,
+            _ => Piece::Pawn,
+        };
+        if piece != Piece::Pawn {
+            rest.remove(0);
+        }
+        if rest.len() < 2 || !rest.is_ascii() {
+            bail!("not a move in algebraic notation: {san}");
+        }
+        let (disambiguation, target) = rest.split_at(rest.len() - 2);
+        let target = Square::from_algebraic_notation(target)?.as_index();
+
+        let mut candidates = legal_moves.into_iter().filter(|mv| {
+            let start = format!("{:?}", Square::from_index(mv.starting_square)).to_lowercase();
+            move_generator.board.squares[mv.starting_square] == Some(piece)
+                && mv.target_square == target
+                && mv.promotion_piece() == promotion
+                && disambiguation.chars().all(|c| start.contains(c))
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => bail!("ambiguous move: {san}"),
+            (None, _) => bail!("illegal move: {san}"),
+        }
+    }
+
+    // Writes a legal move in standard algebraic notation, with only as much of the starting
+    // square as it takes to tell it apart from the other moves to the same square
+    pub fn to_san(&self, board: &Board) -> String {
+        let mut move_generator = MoveGenerator::new(board.clone());
+        let legal_moves = move_generator.generate_moves();
+        let piece = board.squares[self.starting_square].unwrap_or(Piece::Pawn);
+        let is_capture = matches!(
+            self.flag,
+            Flag::Capture(_) | Flag::CaptureWithPromotion(..) | Flag::EnPassantCapture
+        );
+        let start = format!("{:?}", Square::from_index(self.starting_square)).to_lowercase();
+        let target = format!("{:?}", Square::from_index(self.target_square)).to_lowercase();
+
+        let mut san = String::new();
+        match self.flag {
+            Flag::KingsideCastle => san.push_str("O-O"),
+            Flag::QueensideCastle => san.push_str("O-O-O"),
+            Flag::Drop(_) => san.push_str(&self.to_string()),
+            _ => {
+                if piece == Piece::Pawn {
+                    if is_capture {
+                        san.push_str(&start[..1]);
+                    }
+                } else {
+                    san.push(piece.to_symbol(Color::White));
+                    let rivals: Vec<usize> = legal_moves
+                        .iter()
+                        .filter(|mv| {
+                            mv.target_square == self.target_square
+                                && mv.starting_square != self.starting_square
+                                && board.squares[mv.starting_square] == Some(piece)
+                        })
+                        .map(|mv| mv.starting_square)
+                        .collect();
+                    let same_file = rivals.iter().any(|s| s % 8 == self.starting_square % 8);
+                    let same_rank = rivals.iter().any(|s| s / 8 == self.starting_square / 8);
+                    match (rivals.is_empty(), same_file, same_rank) {
+                        (true, _, _) => {}
+                        (false, false, _) => san.push_str(&start[..1]),
+                        (false, true, false) => san.push_str(&start[1..]),
+                        (false, true, true) => san.push_str(&start),
+                    }
+                }
+                if is_capture {
+                    san.push('x');
+                }
+                san.push_str(&target);
+                if let Some(promotion) = self.promotion_piece() {
+                    san.push('=');
+                    san.push(promotion.to_symbol(Color::White));
+                }
+            }
+        }
+
+        move_generator.board.move_piece(self);
+        if move_generator.is_in_check(move_generator.board.to_move) {
+            match move_generator.generate_moves().is_empty() {
+                true => san.push('#'),
+                false => san.push('+'),
+            }
+        }
+
+        san
+    }
+
+    fn drop_piece(letter: &str) -> Result<Piece> {
+        match letter {
+            "P" | "p" => Ok(Piece::Pawn),
+            "N" | "n" => Ok(Piece::Knight),
+            "B" | "b" => Ok(Piece::Bishop),
+            "R" | "r" => Ok(Piece::Rook),
+            "Q" | "q" => Ok(Piece::Queen),
+            _ => bail!("Not a piece that can be dropped: {letter}"),
+        }
+    }
+
+    pub fn promotion_piece(&self) -> Option<Piece> {
+        match self.flag {
+            Flag::PromoteTo(piece) | Flag::CaptureWithPromotion(_, piece) => Some(piece),
+            _ => None,
+        }
+    }
 }
 
+// Coordinates are enough to tell moves apart in test failures and logs, the flag only adds
+// something for special moves
 impl fmt::Debug for Move {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "starting_square: {:?}, target_square: {:?}",
-            Square::from_index(self.starting_square),
-            Square::from_index(self.target_square),
-        )?;
+        write!(f, "{self}")?;
         if self.flag != Flag::None {
             write!(f, ", {:?}", self.flag)?;
         }
@@ -90,6 +264,11 @@ impl fmt::Debug for Move {
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Flag::Drop(piece) = self.flag {
+            let square = format!("{:?}", Square::from_index(self.target_square)).to_lowercase();
+            return write!(f, "{}@{square}", piece.to_symbol(Color::White));
+        }
+
         let mut output = format!(
             "{:?}{:?}",
             Square::from_index(self.starting_square),
@@ -120,6 +299,8 @@ pub enum Flag {
     Capture(Piece),
     // captured piece, promotion piece
     CaptureWithPromotion(Piece, Piece),
+    // Crazyhouse only, starting and target square are both where the piece is dropped
+    Drop(Piece),
 }
 
 pub struct MoveGenerator {
@@ -145,6 +326,11 @@ impl MoveGenerator {
 
     pub fn generate_moves(&mut self) -> Vec<Move> {
         let mut legal_moves = Vec::new();
+        // The game is already over
+        if self.board.variant.is_lost(&self.board) {
+            return legal_moves;
+        }
+
         let pseudo_legal_moves = self.generate_pseudo_legal_moves();
         let to_move = self.board.to_move;
 
@@ -159,10 +345,12 @@ impl MoveGenerator {
             self.board.move_piece(&mv);
 
             let in_check_after_move = self.is_in_check(to_move);
+            // In atomic, capturing next to your own king blows it up as well
+            let king_exploded = self.board.king_square(to_move).is_none();
 
             self.board.unmake_move(&mv).unwrap();
 
-            if !in_check_after_move {
+            if !in_check_after_move && !king_exploded {
                 legal_moves.push(mv);
             }
         }
@@ -192,10 +380,31 @@ impl MoveGenerator {
                 Piece::King => self.generate_king_moves(&mut moves, square),
             }
         }
+        if self.board.variant.has_drops() {
+            self.generate_drops(&mut moves);
+        }
 
         moves
     }
 
+    // Any piece in the pocket can go on any empty square, except for pawns on the first and
+    // last rank
+    fn generate_drops(&mut self, moves: &mut Vec<Move>) {
+        let to_move = self.board.to_move;
+        for piece in POCKET_PIECES {
+            if self.board.pockets.count(to_move, piece) == 0 {
+                continue;
+            }
+            let squares = match piece {
+                Piece::Pawn => 8..56,
+                _ => 0..64,
+            };
+            for square in squares.filter(|&square| self.board.is_square_empty(square)) {
+                moves.push(Move::new(square, square, Flag::Drop(piece)));
+            }
+        }
+    }
+
     fn generate_sliding_moves(&mut self, moves: &mut Vec<Move>, start_square: usize) {
         let piece = self.board.squares[start_square]
             .expect("should not be generating sliding moves from an empty square");
@@ -368,6 +577,8 @@ impl MoveGenerator {
                 moves.push(Move::new(start_square, target_square, Flag::None));
             } else if self.board.colors[target_square]
                 .is_some_and(|color| color != self.board.colors[start_square].unwrap())
+                // A king capturing in atomic would blow itself up
+                && self.board.variant != Variant::Atomic
             {
                 let captured_piece = self.board.squares[target_square]
                     .expect("piece should not be None if color exists");
@@ -375,65 +586,55 @@ impl MoveGenerator {
             }
         }
 
-        // TODO: Refactor this
-        match self.board.to_move {
-            Color::White => {
-                let kingside_castling_path_clear = self.board.squares[Square::F1.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::G1.as_index()].is_none();
-                if self.board.board_state.white_kingside_castling_priviledge
-                    && kingside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::G1.as_index(),
-                        Flag::KingsideCastle,
-                    ));
-                }
-
-                let queenside_castling_path_clear = self.board.squares[Square::D1.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::C1.as_index()].is_none()
-                    && self.board.squares[Square::B1.as_index()].is_none();
-                if self.board.board_state.white_queenside_castling_priviledge
-                    && queenside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::C1.as_index(),
-                        Flag::QueensideCastle,
-                    ))
-                }
+        for kingside in [true, false] {
+            if let Some(mv) = self.castling_move(start_square, kingside) {
+                moves.push(mv);
             }
-            Color::Black => {
-                let kingside_castling_path_clear = self.board.squares[Square::F8.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::G8.as_index()].is_none();
-                if self.board.board_state.black_kingside_castling_priviledge
-                    && kingside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::G8.as_index(),
-                        Flag::KingsideCastle,
-                    ));
-                }
+        }
+    }
 
-                let queenside_castling_path_clear = self.board.squares[Square::D8.as_index()]
-                    .is_none()
-                    && self.board.squares[Square::C8.as_index()].is_none()
-                    && self.board.squares[Square::B8.as_index()].is_none();
-                if self.board.board_state.black_queenside_castling_priviledge
-                    && queenside_castling_path_clear
-                {
-                    moves.push(Move::new(
-                        start_square,
-                        Square::C8.as_index(),
-                        Flag::QueensideCastle,
-                    ))
-                }
-            }
+    // Castling needs the right, and every square between the king, the rook and where they
+    // end up to be empty apart from the king and rook themselves. Whether the king passes
+    // through check is left to is_castling_path_clear.
+    fn castling_move(&self, king_square: usize, kingside: bool) -> Option<Move> {
+        let color = self.board.to_move;
+        let state = &self.board.board_state;
+        let can_castle = match (color, kingside) {
+            (Color::White, true) => state.white_kingside_castling_priviledge,
+            (Color::White, false) => state.white_queenside_castling_priviledge,
+            (Color::Black, true) => state.black_kingside_castling_priviledge,
+            (Color::Black, false) => state.black_queenside_castling_priviledge,
+        };
+        let rook_square = state.castling_squares.rook(color, kingside);
+        if !can_castle
+            || king_square != state.castling_squares.king[color as usize]
+            || !self
+                .board
+                .is_piece_at_square(rook_square, Piece::Rook, color)
+        {
+            return None;
         }
+
+        let (king_target, rook_target) = CastlingSquares::destinations(color, kingside);
+        let squares = [king_square, king_target, rook_square, rook_target];
+        let (first, last) = (*squares.iter().min()?, *squares.iter().max()?);
+        if (first..=last).any(|square| {
+            square != king_square && square != rook_square && !self.board.is_square_empty(square)
+        }) {
+            return None;
+        }
+
+        let flag = match kingside {
+            true => Flag::KingsideCastle,
+            false => Flag::QueensideCastle,
+        };
+        // UCI writes Chess960 castling as the king taking its own rook, which also keeps it
+        // apart from a king move to the same square
+        let target_square = match self.board.chess960 {
+            true => rook_square,
+            false => king_target,
+        };
+        Some(Move::new(king_square, target_square, flag))
     }
 
     fn precompute_move_data() -> [[usize; 8]; 64] {
@@ -502,6 +703,59 @@ impl MoveGenerator {
         }
     }
 
+    // Squares attacked by the piece on the given square as a bitboard, regardless of whose turn
+    // it is. Squares occupied by friendly pieces are included, since those pieces are defended.
+    pub fn attacks_from(&self, square: usize) -> u64 {
+        let (Some(piece), Some(color)) = (self.board.squares[square], self.board.colors[square])
+        else {
+            return 0;
+        };
+
+        let offsets: &[isize] = match piece {
+            Piece::Pawn if color == Color::White => &[7, 9],
+            Piece::Pawn => &[-7, -9],
+            Piece::Knight => &[-17, -15, -10, -6, 6, 10, 15, 17],
+            Piece::King => &self.direction_offsets,
+            Piece::Bishop | Piece::Rook | Piece::Queen => {
+                return self.sliding_attacks_from(square, piece);
+            }
+        };
+
+        let mut attacks = 0;
+        for offset in offsets {
+            let target_square = square as isize + offset;
+            if !(0..64).contains(&target_square)
+                || Self::is_pacman_move(square, target_square as usize)
+            {
+                continue;
+            }
+            attacks |= 1 << target_square;
+        }
+
+        attacks
+    }
+
+    fn sliding_attacks_from(&self, square: usize, piece: Piece) -> u64 {
+        let start_direction_index = if piece == Piece::Bishop { 4 } else { 0 };
+        let end_direction_index = if piece == Piece::Rook { 4 } else { 8 };
+        let mut attacks = 0;
+
+        for direction_index in start_direction_index..end_direction_index {
+            for n in 0..self.num_squares_to_edge[square][direction_index] {
+                let target_square = (square as isize
+                    + self.direction_offsets[direction_index] * (n as isize + 1))
+                    as usize;
+                attacks |= 1 << target_square;
+
+                if self.board.squares[target_square].is_some() {
+                    break;
+                }
+            }
+        }
+
+        attacks
+    }
+
     pub fn is_pacman_move(start: usize, target: usize) -> bool {
         let starting_rank = start as isize / 8;
         let starting_file = start as isize % 8;
@@ -514,6 +768,21 @@ impl MoveGenerator {
     }
 
     pub fn is_in_check(&mut self, color_to_check: Color) -> bool {
+        // In atomic there is no check without both kings, and none while they touch, since
+        // capturing one king would blow up the other
+        if self.board.variant == Variant::Atomic {
+            let king_squares = (
+                self.board.king_square(color_to_check),
+                self.board.king_square(color_to_check.opposite_color()),
+            );
+            match king_squares {
+                (Some(king_square), Some(enemy_king_square))
+                    if !adjacent_squares(king_square).any(|square| square == enemy_king_square) => {
+                }
+                _ => return false,
+            }
+        }
+
         let king_square = (0..64)
             .find(|&square| {
                 self.board.colors[square].is_some()
@@ -718,43 +987,47 @@ impl MoveGenerator {
         attack_map
     }
 
+    // The king may not castle out of, through or into check
     fn is_castling_path_clear(&mut self, mv: &Move) -> bool {
-        // TODO: Fix this outright war crime
-        if mv.flag == Flag::KingsideCastle {
-            let attacked_map = self.calculate_opponent_attack_map();
-
-            if self.board.to_move == Color::White {
-                if attacked_map[Square::E1.as_index()]
-                    || attacked_map[Square::F1.as_index()]
-                    || attacked_map[Square::G1.as_index()]
-                {
-                    return false;
-                }
-            } else if attacked_map[Square::E8.as_index()]
-                || attacked_map[Square::F8.as_index()]
-                || attacked_map[Square::G8.as_index()]
-            {
-                return false;
-            }
-        } else if mv.flag == Flag::QueensideCastle {
-            let attacked_squares = self.calculate_opponent_attack_map();
-
-            if self.board.to_move == Color::White {
-                if attacked_squares[Square::E1.as_index()]
-                    || attacked_squares[Square::D1.as_index()]
-                    || attacked_squares[Square::C1.as_index()]
-                {
-                    return false;
-                }
-            } else if attacked_squares[Square::E8.as_index()]
-                || attacked_squares[Square::D8.as_index()]
-                || attacked_squares[Square::C8.as_index()]
-            {
-                return false;
-            }
+        let kingside = match mv.flag {
+            Flag::KingsideCastle => true,
+            Flag::QueensideCastle => false,
+            _ => return true,
+        };
+        let (king_target, _) = CastlingSquares::destinations(self.board.to_move, kingside);
+        let path = mv.starting_square.min(king_target)..=mv.starting_square.max(king_target);
+        if self.board.variant == Variant::Atomic {
+            return self.is_atomic_castling_path_clear(mv.starting_square, path, king_target);
+        }
+        let attacked_squares = self.calculate_opponent_attack_map();
+
+        !path.into_iter().any(|square| attacked_squares[square])
+    }
+
+    // Next to the enemy king the king can't be checked in atomic, so it may castle while in
+    // line with an enemy rook, and then it doesn't shield the squares behind it. Where the
+    // king lands is left to the check after the move, once the rook has moved in front of it.
+    fn is_atomic_castling_path_clear(
+        &mut self,
+        king_square: usize,
+        path: RangeInclusive<usize>,
+        king_target: usize,
+    ) -> bool {
+        let Some(enemy_king_square) = self.board.king_square(self.board.to_move.opposite_color())
+        else {
+            return true;
+        };
+        let king = self.board.remove_piece(king_square);
+        let attacked_squares = self.calculate_opponent_attack_map();
+        if let Some((king, color)) = king {
+            self.board.put_piece(king_square, king, color);
         }
 
-        true
+        !path.into_iter().any(|square| {
+            (square != king_target || square == king_square)
+                && attacked_squares[square]
+                && !adjacent_squares(square).any(|square| square == enemy_king_square)
+        })
     }
 
     #[allow(unused)]
@@ -774,7 +1047,7 @@ impl MoveGenerator {
     }
 
     #[cfg(test)]
-    fn perft_test(&mut self, depth: u32) -> u32 {
+    pub(crate) fn perft_test(&mut self, depth: u32) -> u32 {
         if depth == 0 {
             return 1;
         }
@@ -808,6 +1081,7 @@ mod tests {
         Piece::{self, *},
     };
     use crate::square::Square::{self, *};
+    use crate::variant::Variant;
     use anyhow::Result;
 
     #[test]
@@ -816,7 +1090,7 @@ mod tests {
         let uci_output = format!("{mv}");
 
         dbg!(&uci_output);
-        assert!(String::from("e4e5") == uci_output);
+        assert!(uci_output == "e4e5");
 
         Ok(())
     }
@@ -827,7 +1101,98 @@ mod tests {
         let uci_output = format!("{mv}");
 
         dbg!(&uci_output);
-        assert!(String::from("e7e8q") == uci_output);
+        assert!(uci_output == "e7e8q");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_debug_output() {
+        let mv = Move::from_square(E7, E8, Flag::PromoteTo(Queen));
+        assert_eq!(format!("{mv:?}"), "e7e8q, PromoteTo(Queen)");
+        assert_eq!(format!("{:?}", Move::from_square(G1, F3, Flag::None)), "g1f3");
+    }
+
+    #[test]
+    fn test_move_from_san() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        assert_eq!(
+            Move::try_from_san("Nf3", &mut move_generator)?,
+            Move::from_square(G1, F3, Flag::None)
+        );
+        assert_eq!(
+            Move::try_from_san("Ng1-f3!?", &mut move_generator)?,
+            Move::from_square(G1, F3, Flag::None)
+        );
+        assert!(Move::try_from_san("Ke2", &mut move_generator).is_err());
+        assert!(Move::try_from_san("Zz9", &mut move_generator).is_err());
+
+        // Both rooks can reach d1
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+        assert!(Move::try_from_san("Rd1", &mut move_generator).is_err());
+        assert_eq!(
+            Move::try_from_san("Rad1", &mut move_generator)?,
+            Move::from_square(A1, D1, Flag::None)
+        );
+
+        // The pawn can promote with or without a capture
+        let board = BoardBuilder::try_from_fen("1n2k3/P7/8/8/8/8/8/4K2R w K - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board);
+        for san in ["O-O+", "0-0"] {
+            assert_eq!(
+                Move::try_from_san(san, &mut move_generator)?,
+                Move::from_square(E1, G1, Flag::KingsideCastle)
+            );
+        }
+        for san in ["axb8=Q", "axb8Q", "ab8=q"] {
+            assert_eq!(
+                Move::try_from_san(san, &mut move_generator)?,
+                Move::from_square(A7, B8, Flag::CaptureWithPromotion(Knight, Queen))
+            );
+        }
+        assert_eq!(
+            Move::try_from_san("a8=N", &mut move_generator)?,
+            Move::from_square(A7, A8, Flag::PromoteTo(Knight))
+        );
+        assert!(Move::try_from_san("a8", &mut move_generator).is_err());
+
+        let board = BoardBuilder::try_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2")?;
+        let mut move_generator = MoveGenerator::new(board);
+        for san in ["exd6e.p.", "exd6", "ed6"] {
+            assert_eq!(
+                Move::try_from_san(san, &mut move_generator)?,
+                Move::from_square(E5, D6, Flag::EnPassantCapture)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_to_san() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("1n2k3/P7/8/8/R7/8/4K3/R6R w - - 0 1")?;
+        let san = |start, target, flag| Move::from_square(start, target, flag).to_san(&board);
+
+        // The rooks on the first rank can both reach d1, and the ones on the a-file a2
+        assert_eq!(san(A1, D1, Flag::None), "Rad1");
+        assert_eq!(san(A1, A2, Flag::None), "R1a2");
+        assert_eq!(san(A4, A2, Flag::None), "R4a2");
+        assert_eq!(san(H1, H8, Flag::None), "Rh8+");
+        // The knight on b8 blocks the new rook
+        assert_eq!(san(A7, A8, Flag::PromoteTo(Rook)), "a8=R");
+        assert_eq!(san(A7, B8, Flag::CaptureWithPromotion(Knight, Queen)), "axb8=Q+");
+
+        let board = BoardBuilder::try_from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R b KQkq - 0 1")?;
+        assert_eq!(
+            Move::from_square(E8, C8, Flag::QueensideCastle).to_san(&board),
+            "O-O-O"
+        );
+        let board = BoardBuilder::try_from_fen("6k1/5ppp/8/3pP3/8/8/8/R3K3 w Q d6 0 2")?;
+        let san = |start, target, flag| Move::from_square(start, target, flag).to_san(&board);
+        assert_eq!(san(E5, D6, Flag::EnPassantCapture), "exd6");
+        assert_eq!(san(A1, A8, Flag::None), "Ra8#");
+        assert_eq!(san(E1, E2, Flag::None), "Ke2");
 
         Ok(())
     }
@@ -842,7 +1207,7 @@ mod tests {
         let uci_output = format!("{mv}");
 
         dbg!(&uci_output);
-        assert!(String::from("e7f8q") == uci_output);
+        assert!(uci_output == "e7f8q");
 
         Ok(())
     }
@@ -1091,6 +1456,193 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_move_generation_chess960_perft() -> Result<()> {
+        // Node counts for depths 1 to 3, from the Chess960 perft results on chessprogramming.org
+        let positions = [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10471],
+            ),
+            (
+                "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+                [22, 593, 13440],
+            ),
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                [28, 1120, 31058],
+            ),
+        ];
+
+        for (fen, expected) in positions {
+            let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen)?);
+            for (depth, nodes) in (1..).zip(expected) {
+                assert_eq!(move_generator.perft_test(depth), nodes, "{fen} at depth {depth}");
+            }
+        }
+
+        // The standard position castles the same way in Chess960 mode
+        let mut move_generator = MoveGenerator::new(Board::chess960_position(518)?);
+        assert_eq!(move_generator.perft_test(3), 8902);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chess960_castling_moves() -> Result<()> {
+        // King on b1 with rooks on a1 and g1: castling queenside moves the king to c1
+        // and leaves the king taking its own rook in UCI notation
+        let board = BoardBuilder::try_from_fen("1k6/8/8/8/8/8/8/RK4R1 w AG - 0 1")?;
+        assert!(board.chess960);
+        let mut move_generator = MoveGenerator::new(board.clone());
+        let moves = move_generator.generate_moves();
+
+        let queenside = Move::from_square(B1, A1, Flag::QueensideCastle);
+        let kingside = Move::from_square(B1, G1, Flag::KingsideCastle);
+        assert!(moves.contains(&queenside));
+        assert!(moves.contains(&kingside));
+        assert_eq!(queenside.to_string(), "b1a1");
+        assert_eq!(Move::try_from_uci("b1g1", &mut move_generator)?, kingside);
+        assert_eq!(kingside.to_san(&board), "O-O");
+
+        let mut board = board;
+        board.move_piece(&queenside);
+        assert_eq!(board.to_fen(), "1k6/8/8/8/8/8/8/2KR2R1 b - - 1 1");
+        board.unmake_move(&queenside)?;
+        board.move_piece(&kingside);
+        assert_eq!(board.to_fen(), "1k6/8/8/8/8/8/8/R4RK1 b - - 1 1");
+
+        // An attacked square between the king and g1 rules out castling kingside only
+        let board = BoardBuilder::try_from_fen("1k3r2/8/8/8/8/8/8/RK4R1 w AG - 0 1")?;
+        let moves = MoveGenerator::new(board).generate_moves();
+        assert!(moves.contains(&queenside));
+        assert!(!moves.contains(&kingside));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_crazyhouse_perft() -> Result<()> {
+        // Node counts from the crazyhouse perft suite of python-chess
+        let positions: [(&str, &[u32]); 4] = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+                &[20, 400, 8902],
+            ),
+            ("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75353]),
+            (
+                "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+                &[42, 1347, 58057],
+            ),
+            // Only differs from standard chess after Bxb7, where the promoted queen goes to
+            // the pocket as a pawn: 3 king moves, then 22 moves and 44 pawn drops for black
+            // after Ka3 or Kb3 and 18 moves and 45 drops after Kxb2
+            ("4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1", &[20, 360, 5445]),
+        ];
+
+        for (fen, expected) in positions {
+            let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen)?);
+            for (depth, &nodes) in (1..).zip(expected) {
+                assert_eq!(move_generator.perft_test(depth), nodes, "{fen} at depth {depth}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_generation_atomic_perft() -> Result<()> {
+        // Node counts from the atomic perft suite of python-chess. The kings touch in the last
+        // three positions, which the board builder wouldn't allow in standard chess.
+        let positions: [(&str, &[u32]); 6] = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[20, 400, 8902, 197326],
+            ),
+            (
+                "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+                &[28, 833, 23353],
+            ),
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", &[18, 180, 4364, 61401]),
+            ("r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", &[25, 282, 6753, 98729]),
+            ("Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", &[21, 465, 10631, 241478]),
+            ("1R4kr/4K3/8/8/8/8/8/8 b k - 0 1", &[4, 77, 1021, 17915]),
+        ];
+
+        for (fen, expected) in positions {
+            let mut board = BoardBuilder::try_from_fen_unchecked(fen)?;
+            board.variant = Variant::Atomic;
+            let mut move_generator = MoveGenerator::new(board);
+            for (depth, &nodes) in (1..).zip(expected) {
+                assert_eq!(move_generator.perft_test(depth), nodes, "{fen} at depth {depth}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_explosions() -> Result<()> {
+        let fen = "r3k2r/6p1/8/3pn3/4N3/8/1B6/4K3 w kq - 0 1";
+        let mut board = BoardBuilder::try_from_fen(fen)?;
+        board.variant = Variant::Atomic;
+
+        // The capturing knight, the captured pawn and the knight next to them all go, but the
+        // pawns don't
+        let capture = Move::from_square(E4, D5, Flag::Capture(Pawn));
+        board.move_piece(&capture);
+        assert_eq!(board.to_fen(), "r3k2r/6p1/8/8/8/8/1B6/4K3 b kq - 0 1");
+        board.unmake_move(&capture)?;
+        assert_eq!(board.to_fen(), fen);
+
+        // Blowing up the rook takes the castling right with it
+        let capture = Move::from_square(B2, G7, Flag::Capture(Pawn));
+        board.move_piece(&capture);
+        assert_eq!(board.to_fen(), "r3k3/8/8/3pn3/4N3/8/8/4K3 b q - 0 1");
+        board.unmake_move(&capture)?;
+        assert_eq!(board.to_fen(), fen);
+
+        // The king can't take the checking pawn, and neither can the knight since the king
+        // would go up with it
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/3pp3/4K1N1 w - - 0 1")?;
+        board.variant = Variant::Atomic;
+        assert_eq!(
+            MoveGenerator::new(board).generate_moves(),
+            [Move::from_square(E1, F2, Flag::None)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crazyhouse_drops() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3[Pn] w - - 0 1")?;
+        let mut move_generator = MoveGenerator::new(board.clone());
+        let moves = move_generator.generate_moves();
+
+        // No pawns on the first or last rank, and black's knight stays in black's pocket
+        let pawn_drops = moves.iter().filter(|mv| mv.flag == Flag::Drop(Pawn));
+        assert_eq!(pawn_drops.count(), 48);
+        assert!(!moves.contains(&Move::from_square(A1, A1, Flag::Drop(Pawn))));
+        assert!(moves.iter().all(|mv| mv.flag != Flag::Drop(Knight)));
+
+        let drop = Move::from_square(D7, D7, Flag::Drop(Pawn));
+        assert_eq!(drop.to_string(), "P@d7");
+        assert_eq!(Move::try_from_uci("P@d7", &mut move_generator)?, drop);
+        assert_eq!(Move::try_from_san("@d7+", &mut move_generator)?, drop);
+        assert_eq!(drop.to_san(&board), "P@d7+");
+
+        Ok(())
+    }
+
     #[test]
     fn test_num_squares_to_edge() {
         let move_generator = MoveGenerator::default();
@@ -2514,6 +3066,57 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_attacks_from_knight_in_corner() -> Result<()> {
+        let board = BoardBuilder::new()
+            .piece(A1, Knight, White)
+            .skip_validation()
+            .try_into()?;
+        let move_generator = MoveGenerator::new(board);
+
+        assert_eq!(
+            move_generator.attacks_from(A1.as_index()),
+            (1 << B3.as_index()) | (1 << C2.as_index())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attacks_from_rook_stops_at_blockers() -> Result<()> {
+        let board = BoardBuilder::new()
+            .piece(A1, Rook, White)
+            .piece(A3, Pawn, White)
+            .piece(C1, Knight, Black)
+            .skip_validation()
+            .try_into()?;
+        let move_generator = MoveGenerator::new(board);
+
+        assert_eq!(
+            move_generator.attacks_from(A1.as_index()),
+            (1 << A2.as_index())
+                | (1 << A3.as_index())
+                | (1 << B1.as_index())
+                | (1 << C1.as_index())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attacks_from_black_pawn() -> Result<()> {
+        let board = BoardBuilder::new()
+            .piece(H5, Pawn, Black)
+            .to_move(White)
+            .skip_validation()
+            .try_into()?;
+        let move_generator = MoveGenerator::new(board);
+
+        assert_eq!(move_generator.attacks_from(H5.as_index()), 1 << G4.as_index());
+
+        Ok(())
+    }
+
     #[test]
     fn test_is_kingside_castling_path_clear_true_white() -> Result<()> {
         let board = BoardBuilder::from_starting_position()
@@ -0,0 +1,455 @@
+// A small efficiently updatable neural network (NNUE) evaluation
+// Source: https://www.chessprogramming.org/NNUE
+//
+// The architecture is 768 -> N -> 1. Each of the 768 inputs is one piece of one color on one
+// square, indexed the same way as the zobrist piece keys (color * 384 + piece * 64 + square).
+// The hidden layer is the accumulator: the hidden biases plus the weight columns of every piece
+// on the board, updated as pieces are put on and taken off instead of being recomputed. It is
+// clipped to [0, QA] before the output layer. The network evaluates from white's point of view.
+//
+// Weights file format, all numbers little endian:
+//   magic                 8 bytes  "TALIANN1"
+//   hidden size N         u32      must be a positive multiple of 16
+//   feature weights       i16      768 * N, one N sized column per input feature
+//   hidden biases         i16      N
+//   output weights        i16      N
+//   output bias           i32
+// Feature weights and hidden biases are quantised by QA, output weights by QB and the output
+// bias by QA * QB. The output is scaled by SCALE to get centipawns.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::board::Board;
+use crate::piece::{Color, Piece};
+
+pub const INPUT_SIZE: usize = 768;
+pub const QA: i32 = 255;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+const MAGIC: &[u8; 8] = b"TALIANN1";
+// The SIMD code handles 16 i16s at a time
+const HIDDEN_SIZE_MULTIPLE: usize = 16;
+
+// The network used by search, if one has been loaded
+static NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+// Switches between the network and the classical evaluation while a network is loaded
+pub static USE_NETWORK: AtomicBool = AtomicBool::new(true);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Network {
+    hidden_size: usize,
+    feature_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn new(
+        feature_weights: Vec<i16>,
+        hidden_biases: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i32,
+    ) -> Result<Self> {
+        let hidden_size = hidden_biases.len();
+        if hidden_size == 0 || !hidden_size.is_multiple_of(HIDDEN_SIZE_MULTIPLE) {
+            bail!("hidden layer size must be a positive multiple of {HIDDEN_SIZE_MULTIPLE}");
+        }
+        if feature_weights.len() != INPUT_SIZE * hidden_size {
+            bail!("expected {} feature weights", INPUT_SIZE * hidden_size);
+        }
+        if output_weights.len() != hidden_size {
+            bail!("expected {hidden_size} output weights");
+        }
+
+        Ok(Self {
+            hidden_size,
+            feature_weights,
+            hidden_biases,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).map_err(|e| anyhow!("could not read network file {path}: {e}"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            bail!("not a talia network file");
+        };
+        let (hidden_size, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(anyhow!("network file is missing the hidden layer size"))?;
+        let hidden_size = u32::from_le_bytes(*hidden_size) as usize;
+
+        let i16_count = (INPUT_SIZE + 2) * hidden_size;
+        if rest.len() != i16_count * 2 + 4 {
+            bail!("network file has the wrong size for a hidden layer of {hidden_size}");
+        }
+        let (weights, output_bias) = rest.split_at(i16_count * 2);
+        let mut weights: Vec<i16> = weights
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        let output_bias = i32::from_le_bytes(output_bias.try_into()?);
+
+        let output_weights = weights.split_off((INPUT_SIZE + 1) * hidden_size);
+        let hidden_biases = weights.split_off(INPUT_SIZE * hidden_size);
+
+        Self::new(weights, hidden_biases, output_weights, output_bias)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((self.hidden_size as u32).to_le_bytes());
+        for weight in self
+            .feature_weights
+            .iter()
+            .chain(&self.hidden_biases)
+            .chain(&self.output_weights)
+        {
+            bytes.extend(weight.to_le_bytes());
+        }
+        bytes.extend(self.output_bias.to_le_bytes());
+
+        bytes
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn feature_weights(&self, piece: Piece, color: Color, square: usize) -> &[i16] {
+        let start = feature_index(piece, color, square) * self.hidden_size;
+        &self.feature_weights[start..start + self.hidden_size]
+    }
+}
+
+pub fn feature_index(piece: Piece, color: Color, square: usize) -> usize {
+    color as usize * 384 + piece as usize * 64 + square
+}
+
+pub fn load_network(path: &str) -> Result<()> {
+    let network = Network::load(path)?;
+    *NETWORK.write().unwrap() = Some(Arc::new(network));
+    Ok(())
+}
+
+pub fn clear_network() {
+    *NETWORK.write().unwrap() = None;
+}
+
+// The network search should evaluate with, or None for the classical evaluation
+pub fn active_network() -> Option<Arc<Network>> {
+    if !USE_NETWORK.load(Ordering::Relaxed) {
+        return None;
+    }
+    NETWORK.read().unwrap().clone()
+}
+
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    values: Vec<i16>,
+}
+
+impl PartialEq for Accumulator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.network, &other.network) && self.values == other.values
+    }
+}
+
+impl Eq for Accumulator {}
+
+impl Accumulator {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let mut accumulator = Self {
+            values: network.hidden_biases.clone(),
+            network,
+        };
+
+        for square in 0..64 {
+            if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+                accumulator.add_piece(piece, color, square);
+            }
+        }
+
+        accumulator
+    }
+
+    pub fn add_piece(&mut self, piece: Piece, color: Color, square: usize) {
+        let weights = self.network.feature_weights(piece, color, square);
+        add_weights(&mut self.values, weights);
+    }
+
+    pub fn remove_piece(&mut self, piece: Piece, color: Color, square: usize) {
+        let weights = self.network.feature_weights(piece, color, square);
+        subtract_weights(&mut self.values, weights);
+    }
+
+    // From white's point of view, in centipawns
+    pub fn evaluate(&self) -> i32 {
+        let output = clipped_dot_product(&self.values, &self.network.output_weights)
+            + self.network.output_bias;
+        // Scaling a big output could overflow an i32
+        (output as i64 * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+
+    pub fn values(&self) -> &[i16] {
+        &self.values
+    }
+
+    pub fn network(&self) -> Arc<Network> {
+        self.network.clone()
+    }
+}
+
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: AVX2 support was just checked
+        unsafe { avx2::add_weights(values, weights) };
+        return;
+    }
+    scalar::add_weights(values, weights);
+}
+
+fn subtract_weights(values: &mut [i16], weights: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: AVX2 support was just checked
+        unsafe { avx2::subtract_weights(values, weights) };
+        return;
+    }
+    scalar::subtract_weights(values, weights);
+}
+
+fn clipped_dot_product(values: &[i16], weights: &[i16]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: AVX2 support was just checked
+        return unsafe { avx2::clipped_dot_product(values, weights) };
+    }
+    scalar::clipped_dot_product(values, weights)
+}
+
+// Wraps on overflow just like the SIMD instructions, so both give the same answers
+mod scalar {
+    use super::QA;
+
+    pub fn add_weights(values: &mut [i16], weights: &[i16]) {
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    pub fn subtract_weights(values: &mut [i16], weights: &[i16]) {
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+
+    pub fn clipped_dot_product(values: &[i16], weights: &[i16]) -> i32 {
+        values
+            .iter()
+            .zip(weights)
+            .fold(0i32, |sum, (&value, &weight)| {
+                let clipped = (value as i32).clamp(0, QA);
+                sum.wrapping_add(clipped * weight as i32)
+            })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::{HIDDEN_SIZE_MULTIPLE, QA};
+
+    // Safety: the caller must make sure the CPU supports AVX2. Slices are a multiple of 16 long.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add_weights(values: &mut [i16], weights: &[i16]) {
+        for (values, weights) in values
+            .chunks_exact_mut(HIDDEN_SIZE_MULTIPLE)
+            .zip(weights.chunks_exact(HIDDEN_SIZE_MULTIPLE))
+        {
+            let sum = _mm256_add_epi16(load(values), load(weights));
+            _mm256_storeu_si256(values.as_mut_ptr() as *mut __m256i, sum);
+        }
+    }
+
+    // Safety: the caller must make sure the CPU supports AVX2. Slices are a multiple of 16 long.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn subtract_weights(values: &mut [i16], weights: &[i16]) {
+        for (values, weights) in values
+            .chunks_exact_mut(HIDDEN_SIZE_MULTIPLE)
+            .zip(weights.chunks_exact(HIDDEN_SIZE_MULTIPLE))
+        {
+            let difference = _mm256_sub_epi16(load(values), load(weights));
+            _mm256_storeu_si256(values.as_mut_ptr() as *mut __m256i, difference);
+        }
+    }
+
+    // Safety: the caller must make sure the CPU supports AVX2. Slices are a multiple of 16 long.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn clipped_dot_product(values: &[i16], weights: &[i16]) -> i32 {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(QA as i16);
+        let mut sum = _mm256_setzero_si256();
+
+        for (values, weights) in values
+            .chunks_exact(HIDDEN_SIZE_MULTIPLE)
+            .zip(weights.chunks_exact(HIDDEN_SIZE_MULTIPLE))
+        {
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(load(values), zero), max);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, load(weights)));
+        }
+
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        lanes
+            .iter()
+            .fold(0, |total, &lane| total.wrapping_add(lane))
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn load(values: &[i16]) -> __m256i {
+        _mm256_loadu_si256(values.as_ptr() as *const __m256i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        evaluate::evaluate,
+        move_generation::MoveGenerator,
+        piece::{Color, Piece},
+    };
+    use anyhow::Result;
+
+    use super::{scalar, Accumulator, Network, INPUT_SIZE};
+
+    // A network with small pseudo random weights, good enough to check the plumbing
+    pub fn random_network(hidden_size: usize) -> Network {
+        let mut state: u64 = 0x2F6B_91D4_C3A8_5E07;
+        let mut next_weight = |range: i64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            ((state % (2 * range as u64 + 1)) as i64 - range) as i16
+        };
+
+        let feature_weights = (0..INPUT_SIZE * hidden_size)
+            .map(|_| next_weight(100))
+            .collect();
+        let hidden_biases = (0..hidden_size).map(|_| next_weight(100)).collect();
+        let output_weights = (0..hidden_size).map(|_| next_weight(64)).collect();
+
+        Network::new(feature_weights, hidden_biases, output_weights, 1000).unwrap()
+    }
+
+    #[test]
+    fn test_network_round_trips_through_bytes() -> Result<()> {
+        let network = random_network(32);
+
+        assert_eq!(Network::from_bytes(&network.to_bytes())?, network);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_rejects_bad_files() {
+        let mut bytes = random_network(16).to_bytes();
+
+        assert!(Network::from_bytes(b"NOTANETWORK").is_err());
+        bytes.pop();
+        assert!(Network::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_network_rejects_unsupported_hidden_size() {
+        let network = Network::new(vec![0; INPUT_SIZE * 10], vec![0; 10], vec![0; 10], 0);
+
+        assert!(network.is_err());
+    }
+
+    #[test]
+    fn test_network_load_from_file() -> Result<()> {
+        let network = random_network(16);
+        let path = std::env::temp_dir().join("talia_test_network.nnue");
+        std::fs::write(&path, network.to_bytes())?;
+
+        assert_eq!(Network::load(path.to_str().unwrap())?, network);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accumulator_add_and_remove_piece() {
+        let network = Arc::new(random_network(16));
+        let mut accumulator = Accumulator::new(network.clone(), &Board::default());
+        let empty_accumulator = accumulator.clone();
+
+        accumulator.add_piece(Piece::Knight, Color::White, 21);
+        assert_ne!(accumulator, empty_accumulator);
+
+        accumulator.remove_piece(Piece::Knight, Color::White, 21);
+        assert_eq!(accumulator, empty_accumulator);
+    }
+
+    #[test]
+    fn test_accumulator_is_updated_through_moves() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )?;
+        board.attach_network(Some(Arc::new(random_network(16))));
+        let mut move_generator = MoveGenerator::new(board);
+
+        // Every move and unmake double checks the accumulator in debug builds, but compare
+        // against a fresh one here as well
+        for mv in move_generator.generate_moves() {
+            move_generator.board.move_piece(&mv);
+            let board = &move_generator.board;
+            let accumulator = board.accumulator.as_ref().unwrap();
+            let fresh_accumulator = Accumulator::new(accumulator.network.clone(), board);
+            assert_eq!(accumulator, &fresh_accumulator);
+            move_generator.board.unmake_move(&mv)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let network = Arc::new(random_network(64));
+        let accumulator = Accumulator::new(network.clone(), &Board::starting_position());
+
+        assert_eq!(
+            super::clipped_dot_product(accumulator.values(), &network.output_weights),
+            scalar::clipped_dot_product(accumulator.values(), &network.output_weights)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_uses_attached_network() -> Result<()> {
+        let network = Arc::new(random_network(16));
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1")?;
+        board.attach_network(Some(network.clone()));
+        let network_eval = Accumulator::new(network, &board).evaluate();
+
+        // Black to move, so evaluate answers from black's point of view
+        assert_eq!(evaluate(&MoveGenerator::new(board)), -network_eval);
+
+        Ok(())
+    }
+}
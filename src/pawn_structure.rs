@@ -0,0 +1,343 @@
+use std::cell::RefCell;
+
+use crate::{
+    bitboard::{adjacent_files, file_mask, rank_mask, ranks_ahead, squares},
+    board::Board,
+    eval_params::EvalParams,
+    evaluate::Score,
+    piece::{Color, Piece},
+};
+
+// Pawn structure changes rarely during search, so its evaluation is cached by pawn key.
+// Must be a power of two so the key can be masked into an index.
+const PAWN_HASH_TABLE_SIZE: usize = 1 << 13;
+
+// Everything in an entry depends on the pawns and the weights alone. Passed pawns are stored
+// rather than scored, because how much they are worth also depends on the pieces blocking them.
+#[derive(Clone, Copy, Default)]
+struct PawnHashEntry {
+    key: u64,
+    weights: PawnWeights,
+    // Indexed by Color
+    scores: [Score; 2],
+    passed_pawns: [u64; 2],
+}
+
+// The weights an entry was scored with. Every board carries its own weights, so an entry is only
+// a hit when they match as well as the pawn key.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct PawnWeights {
+    isolated_pawn: Score,
+    doubled_pawn: Score,
+    backward_pawn: Score,
+    connected_pawn: Score,
+    pawn_island: Score,
+}
+
+impl PawnWeights {
+    fn from_params(params: &EvalParams) -> Self {
+        PawnWeights {
+            isolated_pawn: params.isolated_pawn,
+            doubled_pawn: params.doubled_pawn,
+            backward_pawn: params.backward_pawn,
+            connected_pawn: params.connected_pawn,
+            pawn_island: params.pawn_island,
+        }
+    }
+}
+
+thread_local! {
+    // An entry with a zero key is exactly right for a board without pawns, so the empty table
+    // needs no special casing
+    static PAWN_HASH_TABLE: RefCell<Vec<PawnHashEntry>> =
+        RefCell::new(vec![PawnHashEntry::default(); PAWN_HASH_TABLE_SIZE]);
+}
+
+// Returns the pawn structure score from white's point of view
+pub fn evaluate_pawn_structure(board: &Board, params: &EvalParams) -> Score {
+    let [white, black] = pawn_structure_by_side(board, params);
+    white - black
+}
+
+// The pawn structure score of each side, indexed by Color
+pub fn pawn_structure_by_side(board: &Board, params: &EvalParams) -> [Score; 2] {
+    let key = board.board_state.pawn_key;
+    let weights = PawnWeights::from_params(params);
+    let index = key as usize & (PAWN_HASH_TABLE_SIZE - 1);
+
+    let entry = PAWN_HASH_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        if table[index].key != key || table[index].weights != weights {
+            table[index] = analyse_pawns(pawn_bitboards(board), key, weights);
+        }
+        table[index]
+    });
+
+    let passed_pawn_scores = score_passed_pawns(board, entry.passed_pawns, params);
+    [
+        entry.scores[0] + passed_pawn_scores[0],
+        entry.scores[1] + passed_pawn_scores[1],
+    ]
+}
+
+pub fn pawn_bitboards(board: &Board) -> [u64; 2] {
+    let mut pawns = [0; 2];
+
+    for square in 0..64 {
+        if let (Some(Piece::Pawn), Some(color)) = (board.squares[square], board.colors[square]) {
+            pawns[color as usize] |= 1 << square;
+        }
+    }
+
+    pawns
+}
+
+fn analyse_pawns(pawns: [u64; 2], key: u64, weights: PawnWeights) -> PawnHashEntry {
+    let mut scores = [Score::default(); 2];
+    let mut passed_pawns = [0; 2];
+
+    for color in [Color::White, Color::Black] {
+        let own_pawns = pawns[color as usize];
+        let enemy_pawns = pawns[color.opposite_color() as usize];
+        let mut side_score = Score::default();
+
+        for square in squares(own_pawns) {
+            let file = square % 8;
+            let rank = square / 8;
+            let file_mask = file_mask(file);
+            let adjacent_files_mask = adjacent_files(file);
+            let ahead_mask = ranks_ahead(color, rank);
+            let own_rank_mask = rank_mask(rank);
+
+            let is_isolated = own_pawns & adjacent_files_mask == 0;
+            if is_isolated {
+                side_score += weights.isolated_pawn;
+            } else if is_backward(color, square, own_pawns, enemy_pawns) {
+                side_score += weights.backward_pawn;
+            }
+
+            let supporting_rank_mask = match color {
+                Color::White if rank > 0 => rank_mask(rank - 1),
+                Color::Black if rank < 7 => rank_mask(rank + 1),
+                _ => 0,
+            };
+            let is_supported = own_pawns & adjacent_files_mask & supporting_rank_mask != 0;
+            let is_phalanx = own_pawns & adjacent_files_mask & own_rank_mask != 0;
+            if is_supported || is_phalanx {
+                side_score += weights.connected_pawn;
+            }
+
+            // Only the front pawn of a doubled pair can be passed
+            let is_front_pawn = own_pawns & file_mask & ahead_mask == 0;
+            let is_unopposed = enemy_pawns & (file_mask | adjacent_files_mask) & ahead_mask == 0;
+            if is_front_pawn && is_unopposed {
+                passed_pawns[color as usize] |= 1 << square;
+            }
+        }
+
+        let mut occupied_files: u8 = 0;
+        for file in 0..8 {
+            let pawns_on_file = (own_pawns & file_mask(file)).count_ones() as i32;
+            if pawns_on_file > 1 {
+                side_score += weights.doubled_pawn * (pawns_on_file - 1);
+            }
+            if pawns_on_file > 0 {
+                occupied_files |= 1 << file;
+            }
+        }
+        // Every island starts on a file whose left neighbour has no pawns
+        let islands = (occupied_files & !(occupied_files << 1)).count_ones() as i32;
+        side_score += weights.pawn_island * islands;
+
+        scores[color as usize] = side_score;
+    }
+
+    PawnHashEntry {
+        key,
+        weights,
+        scores,
+        passed_pawns,
+    }
+}
+
+// A pawn is backward when no friendly pawn beside or behind it can ever defend it, and it
+// cannot safely step forward because an enemy pawn controls the square in front of it
+fn is_backward(color: Color, square: usize, own_pawns: u64, enemy_pawns: u64) -> bool {
+    let file = square % 8;
+    let rank = square / 8;
+    let adjacent_files_mask = adjacent_files(file);
+
+    let can_be_supported = own_pawns & adjacent_files_mask & !ranks_ahead(color, rank) != 0;
+    if can_be_supported {
+        return false;
+    }
+
+    // Enemy pawns two ranks ahead on an adjacent file attack the stop square
+    let attacker_rank = match color {
+        Color::White if rank < 6 => rank + 2,
+        Color::Black if rank > 1 => rank - 2,
+        _ => return false,
+    };
+    enemy_pawns & adjacent_files_mask & rank_mask(attacker_rank) != 0
+}
+
+fn score_passed_pawns(board: &Board, passed_pawns: [u64; 2], params: &EvalParams) -> [Score; 2] {
+    let mut scores = [Score::default(); 2];
+
+    for color in [Color::White, Color::Black] {
+        for square in squares(passed_pawns[color as usize]) {
+            // Only unchecked boards can have a pawn on the last rank, with nothing in front of it
+            let (relative_rank, stop_square) = match color {
+                Color::White => (square / 8, Some(square + 8).filter(|&stop| stop < 64)),
+                Color::Black => (7 - square / 8, square.checked_sub(8)),
+            };
+
+            let mut bonus = params.passed_pawn[relative_rank];
+            // An enemy piece in front of the pawn has to be dislodged before it can run
+            if stop_square.is_some_and(|stop| board.colors[stop] == Some(color.opposite_color())) {
+                bonus = bonus / 2;
+            }
+
+            scores[color as usize] += bonus;
+        }
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board, board_builder::BoardBuilder, eval_params::DEFAULT_EVAL_PARAMS,
+        evaluate::Score,
+    };
+    use anyhow::Result;
+
+    use super::{analyse_pawns, evaluate_pawn_structure, pawn_bitboards, PawnWeights};
+
+    fn default_weights() -> PawnWeights {
+        PawnWeights::from_params(&DEFAULT_EVAL_PARAMS)
+    }
+
+    fn pawn_structure_score(fen: &str) -> Result<Score> {
+        let board = BoardBuilder::try_from_fen(fen)?;
+        let [white, black] = analyse_pawns(pawn_bitboards(&board), 0, default_weights()).scores;
+        Ok(white - black)
+    }
+
+    #[test]
+    fn test_passed_pawns_on_the_last_rank() -> Result<()> {
+        let board = BoardBuilder::try_from_fen_unchecked("3Pk3/8/8/8/8/8/8/3pK3 w - - 0 1")?;
+
+        assert_eq!(
+            evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS),
+            Score::default()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symmetrical_pawns_score_zero() {
+        let board = Board::starting_position();
+
+        assert_eq!(
+            evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn test_isolated_pawn() -> Result<()> {
+        // White: a2, c2 isolated on two islands. Black: b7, c7 connected on one island.
+        let score = pawn_structure_score("4k3/1pp5/8/8/8/8/P1P5/4K3 w - - 0 1")?;
+        let params = DEFAULT_EVAL_PARAMS;
+        let expected = params.isolated_pawn * 2 + params.pawn_island * 2
+            - params.connected_pawn * 2
+            - params.pawn_island;
+
+        assert_eq!(score, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_doubled_pawns() -> Result<()> {
+        let score = pawn_structure_score("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1")?;
+        let params = DEFAULT_EVAL_PARAMS;
+        let expected = params.isolated_pawn * 2 + params.doubled_pawn + params.pawn_island;
+
+        assert_eq!(score, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backward_pawn() -> Result<()> {
+        // d3 cannot be defended by e4 and d4 is controlled by black's c5 pawn
+        let score = pawn_structure_score("4k3/8/8/2p5/4P3/3P4/8/4K3 w - - 0 1")?;
+        let params = DEFAULT_EVAL_PARAMS;
+        let expected = params.backward_pawn + params.connected_pawn + params.pawn_island
+            - params.isolated_pawn
+            - params.pawn_island;
+
+        assert_eq!(score, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_passed_pawn_detection() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/p7/8/3P4/8/8/7P/4K3 w - - 0 1")?;
+        let entry = analyse_pawns(pawn_bitboards(&board), 0, default_weights());
+
+        // d5 and h2 are passed for white, a7 is passed for black
+        assert_eq!(entry.passed_pawns[0], (1 << 35) | (1 << 15));
+        assert_eq!(entry.passed_pawns[1], 1 << 48);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocked_passed_pawn_is_worth_less() -> Result<()> {
+        let free_pawn = BoardBuilder::try_from_fen("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1")?;
+        let blocked_pawn = BoardBuilder::try_from_fen("4k3/8/3n4/3P4/8/8/8/4K3 w - - 0 1")?;
+
+        let free_score = evaluate_pawn_structure(&free_pawn, &DEFAULT_EVAL_PARAMS);
+        let blocked_score = evaluate_pawn_structure(&blocked_pawn, &DEFAULT_EVAL_PARAMS);
+
+        assert!(free_score.end_game > blocked_score.end_game);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_score_matches_fresh_analysis() -> Result<()> {
+        let board = BoardBuilder::try_from_fen(
+            "r1bqkb1r/pp3ppp/2n1pn2/2pp4/3P4/2PBPN2/PP3PPP/RNBQK2R w KQkq - 0 6",
+        )?;
+        let fresh_score = evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS);
+        let cached_score = evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS);
+
+        assert_eq!(fresh_score, cached_score);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_score_follows_the_weights() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1")?;
+        let default_score = evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS);
+
+        // Same pawns, so the same hash slot, but the entry from the default weights must not be
+        // reused
+        let mut params = DEFAULT_EVAL_PARAMS;
+        params.doubled_pawn = params.doubled_pawn * 2;
+        let doubled_score = evaluate_pawn_structure(&board, &params);
+
+        assert_eq!(doubled_score, default_score + DEFAULT_EVAL_PARAMS.doubled_pawn);
+        assert_eq!(evaluate_pawn_structure(&board, &DEFAULT_EVAL_PARAMS), default_score);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,640 @@
+// Reading and writing games in PGN. Everything in the movetext is kept: comments, annotation
+// glyphs and variations hang off the move they belong to, so a game can be written back out in
+// export format without losing anything.
+// Source: https://www.chessprogramming.org/Portable_Game_Notation
+//
+// Real world files are rarely tidy, so the reader also copes with move numbers glued to moves
+// (1.e4), suffix annotations (e4!?), castling with zeros, games without a result or a blank
+// line between them, lines escaped with % and byte order marks.
+use std::{fmt, iter::Peekable};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    move_generation::{Move, MoveGenerator},
+    piece::Color,
+    variant::Variant,
+};
+
+// Export format keeps lines at most this long
+const MAX_LINE_LENGTH: usize = 80;
+// The tags every exported game has, in the order they are written, with their unknown values
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+// Suffix annotations and the glyphs they stand for
+const SUFFIX_ANNOTATIONS: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" | "½-½" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+
+    // Half points scored by the color, None if the game did not finish
+    pub fn half_points(self, color: Color) -> Option<u32> {
+        match (self, color) {
+            (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black) => {
+                Some(2)
+            }
+            (GameResult::Draw, _) => Some(1),
+            (GameResult::Unknown, _) => None,
+            _ => Some(0),
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        };
+        write!(f, "{result}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    // As written in the file, in standard algebraic notation
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comments_before: Vec<String>,
+    pub comments_after: Vec<String>,
+    // Lines played instead of this move
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(san: &str) -> Self {
+        Self {
+            san: san.to_string(),
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments_after: Vec::new(),
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    // The main line
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    fn new() -> Self {
+        Self {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        }
+    }
+
+    // A game played from the given position, such as one Talia just played
+    pub fn from_moves(starting_board: &Board, moves: &[Move], result: GameResult) -> Result<Self> {
+        let mut game = PgnGame::new();
+        if *starting_board != Board::starting_position() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &starting_board.to_fen());
+        }
+        if !starting_board.variant.is_standard() {
+            game.set_tag("Variant", starting_board.variant.pgn_name());
+        } else if starting_board.chess960 {
+            game.set_tag("Variant", "Chess960");
+        }
+        game.result = result;
+
+        let mut board = starting_board.clone();
+        for mv in moves {
+            let mut move_generator = MoveGenerator::new(board.clone());
+            if !move_generator.generate_moves().contains(mv) {
+                bail!("illegal move {mv} in position {}", board.to_fen());
+            }
+            game.moves.push(PgnMove::new(&mv.to_san(&board)));
+            board.move_piece(mv);
+        }
+
+        Ok(game)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn starting_board(&self) -> Result<Board> {
+        let mut board = match self.tag("FEN") {
+            Some(fen) => BoardBuilder::try_from_fen(fen)?,
+            None => Board::starting_position(),
+        };
+        // The FEN can't tell Chess960 or King of the Hill apart from standard chess
+        match self.tag("Variant") {
+            Some(name) if name.eq_ignore_ascii_case("chess960") => board.chess960 = true,
+            Some(name) => match Variant::from_pgn_name(name) {
+                // Standard is what the FEN said already
+                Some(Variant::Standard) => {}
+                Some(variant) => board.variant = variant,
+                None => bail!("unsupported variant: {name}"),
+            },
+            None => {}
+        }
+        Ok(board)
+    }
+
+    // Plays the main line, checking that it and every variation are legal. Returns the final
+    // position and the moves of the main line.
+    pub fn replay(&self) -> Result<(Board, Vec<Move>)> {
+        let mut board = self.starting_board()?;
+        let moves = replay_line(&mut board, &self.moves)?;
+        Ok((board, moves))
+    }
+
+    // Export format: the seven tag roster first, moves in standard algebraic notation, glyphs
+    // as $n and no line longer than 80 characters
+    pub fn to_pgn(&self) -> Result<String> {
+        let mut pgn = String::new();
+        for (name, unknown) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.to_string(),
+                _ => self.tag(name).unwrap_or(unknown).to_string(),
+            };
+            pgn.push_str(&format_tag(name, &value));
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(tag, _)| tag == name) {
+                pgn.push_str(&format_tag(name, value));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        write_line(&mut tokens, &mut self.starting_board()?, &self.moves)?;
+        tokens.push(self.result.to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+
+        Ok(pgn)
+    }
+}
+
+fn format_tag(name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{name} \"{value}\"]\n")
+}
+
+// 12. Nf3 or 12... Nf6, depending on who is to move
+fn numbered(board: &Board, san: &str) -> String {
+    match board.to_move {
+        Color::White => format!("{}. {san}", board.full_move_number),
+        Color::Black => format!("{}... {san}", board.full_move_number),
+    }
+}
+
+fn play(board: &Board, pgn_move: &PgnMove) -> Result<Move> {
+    Move::try_from_san(&pgn_move.san, &mut MoveGenerator::new(board.clone()))
+        .with_context(|| format!("at move {}", numbered(board, &pgn_move.san)))
+}
+
+fn replay_line(board: &mut Board, line: &[PgnMove]) -> Result<Vec<Move>> {
+    let mut moves = Vec::new();
+    for pgn_move in line {
+        for variation in &pgn_move.variations {
+            replay_line(&mut board.clone(), variation)?;
+        }
+        let mv = play(board, pgn_move)?;
+        board.move_piece(&mv);
+        moves.push(mv);
+    }
+    Ok(moves)
+}
+
+fn write_line(tokens: &mut Vec<String>, board: &mut Board, line: &[PgnMove]) -> Result<()> {
+    // Black's moves only get a number at the start of a line or after something interrupts it
+    let mut needs_number = true;
+    for pgn_move in line {
+        for comment in &pgn_move.comments_before {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+
+        // The move is written again so the notation is always the standard one
+        let mv = play(board, pgn_move)?;
+        let san = mv.to_san(board);
+        if board.to_move == Color::White || needs_number {
+            tokens.push(numbered(board, &san));
+        } else {
+            tokens.push(san);
+        }
+        needs_number = false;
+
+        for nag in &pgn_move.nags {
+            tokens.push(format!("${nag}"));
+        }
+        for comment in &pgn_move.comments_after {
+            tokens.push(format!("{{{comment}}}"));
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            let mut variation_tokens = Vec::new();
+            write_line(&mut variation_tokens, &mut board.clone(), variation)?;
+            if let Some(first) = variation_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.extend(variation_tokens);
+            needs_number = true;
+        }
+
+        board.move_piece(&mv);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    OpenVariation,
+    CloseVariation,
+    Nag(u8),
+    Result(GameResult),
+    San(String),
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>> {
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+    let mut games = Vec::new();
+
+    while tokens.peek().is_some() {
+        let mut game = PgnGame::new();
+        while let Some(Token::Tag(name, value)) =
+            tokens.next_if(|token| matches!(token, Token::Tag(..)))
+        {
+            game.tags.push((name, value));
+        }
+
+        game.moves = parse_line(&mut tokens, false)?;
+        // The result at the end of the movetext wins over the tag, which is only used when the
+        // movetext has none
+        game.result = match tokens.next_if(|token| matches!(token, Token::Result(_))) {
+            Some(Token::Result(result)) => result,
+            _ => game
+                .tag("Result")
+                .and_then(GameResult::from_token)
+                .unwrap_or(GameResult::Unknown),
+        };
+        games.push(game);
+    }
+
+    Ok(games)
+}
+
+// Reads moves until the end of the variation, the result or the tags of the next game
+fn parse_line(tokens: &mut Tokens, in_variation: bool) -> Result<Vec<PgnMove>> {
+    let mut line: Vec<PgnMove> = Vec::new();
+    let mut comments_before = Vec::new();
+
+    loop {
+        let token = match tokens.peek() {
+            None | Some(Token::Tag(..)) | Some(Token::Result(_)) if in_variation => {
+                bail!("variation is never closed")
+            }
+            None | Some(Token::Tag(..)) | Some(Token::Result(_)) => break,
+            Some(_) => tokens.next().unwrap(),
+        };
+
+        match token {
+            Token::San(san) => {
+                let mut pgn_move = PgnMove::new(&san);
+                pgn_move.comments_before = std::mem::take(&mut comments_before);
+                line.push(pgn_move);
+            }
+            Token::Comment(comment) => match line.last_mut() {
+                Some(last) => last.comments_after.push(comment),
+                None => comments_before.push(comment),
+            },
+            Token::Nag(nag) => line
+                .last_mut()
+                .ok_or_else(|| anyhow!("annotation glyph before any move"))?
+                .nags
+                .push(nag),
+            Token::OpenVariation => {
+                let variation = parse_line(tokens, true)?;
+                line.last_mut()
+                    .ok_or_else(|| anyhow!("variation before any move"))?
+                    .variations
+                    .push(variation);
+            }
+            Token::CloseVariation if in_variation => break,
+            Token::CloseVariation => bail!("unmatched closing parenthesis"),
+            Token::Tag(..) | Token::Result(_) => unreachable!("peeked above"),
+        }
+    }
+
+    Ok(line)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    let mut at_line_start = true;
+
+    while let Some(c) = chars.next() {
+        let line_start = std::mem::replace(&mut at_line_start, c == '\n');
+        match c {
+            // Escaped lines are meant for other programs
+            '%' if line_start => while chars.next_if(|&c| c != '\n').is_some() {},
+            '{' => {
+                // Comments do not nest, so they run until the first closing brace
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => bail!("unterminated comment"),
+                    }
+                }
+                let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+                tokens.push(Token::Comment(comment));
+            }
+            ';' => {
+                let mut comment = String::new();
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    comment.push(c);
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::OpenVariation),
+            ')' => tokens.push(Token::CloseVariation),
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                let nag = digits
+                    .parse()
+                    .map_err(|_| anyhow!("invalid annotation glyph: ${digits}"))?;
+                tokens.push(Token::Nag(nag));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}();[$".contains(*c))
+                {
+                    word.push(c);
+                }
+                tokens.extend(read_word(&word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn read_tag(chars: &mut Peekable<impl Iterator<Item = char>>) -> Result<Token> {
+    // Format: [Event "F/S Return Match"]
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"' && *c != ']') {
+        name.push(c);
+    }
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if name.is_empty() || chars.next() != Some('"') {
+        bail!("tag value is not quoted: [{name}");
+    }
+
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('\\') => value.extend(chars.next()),
+            Some('"') => break,
+            Some(c) => value.push(c),
+            None => bail!("tag is never closed: [{name}"),
+        }
+    }
+    while chars.next_if(|&c| c != ']').is_some() {}
+    chars.next();
+
+    Ok(Token::Tag(name, value))
+}
+
+// A word of movetext: a result, a move with its number and suffix annotation, or just a number
+fn read_word(word: &str) -> Vec<Token> {
+    if let Some(result) = GameResult::from_token(word) {
+        return vec![Token::Result(result)];
+    }
+    // Some files write en passant captures as exd6 e.p.
+    if word == "e.p." {
+        return Vec::new();
+    }
+
+    // A move number is digits followed by dots, which keeps castling with zeros (4.0-0) apart
+    let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = match digits.starts_with('.') || digits.is_empty() {
+        true => digits.trim_start_matches('.'),
+        false => word,
+    };
+    if san.is_empty() {
+        return Vec::new();
+    }
+
+    let (san, annotation) = san.split_at(san.find(['!', '?']).unwrap_or(san.len()));
+    let mut tokens = vec![Token::San(san.to_string())];
+    if let Some((_, nag)) = SUFFIX_ANNOTATIONS
+        .iter()
+        .find(|(suffix, _)| *suffix == annotation)
+    {
+        tokens.push(Token::Nag(*nag));
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move},
+        piece::Color,
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{parse_pgn, GameResult, PgnGame};
+
+    const GAME: &str = r#"[Event "Casual game"]
+[White "Talia"]
+[Black "Someone \"quoted\""]
+[Result "1-0"]
+
+{Opening comment} 1. e4 {best by test} e5 2. Nf3 $1 (2. f4 exf4 (2... d5) 3. Nf3) Nc6 ; the usual
+3.Bb5 a6!? 1-0
+"#;
+
+    #[test]
+    fn test_parse_pgn() -> Result<()> {
+        let games = parse_pgn(GAME)?;
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+
+        assert_eq!(game.tag("White"), Some("Talia"));
+        assert_eq!(game.tag("Black"), Some("Someone \"quoted\""));
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(GameResult::WhiteWins.half_points(Color::Black), Some(0));
+        let sans: Vec<&str> = game.moves.iter().map(|mv| mv.san.as_str()).collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+
+        assert_eq!(game.moves[0].comments_before, ["Opening comment"]);
+        assert_eq!(game.moves[0].comments_after, ["best by test"]);
+        assert_eq!(game.moves[2].nags, [1]);
+        assert_eq!(game.moves[3].comments_after, ["the usual"]);
+        assert_eq!(game.moves[5].nags, [5]);
+
+        // The King's Gambit is played instead of Nf3, and d5 instead of exf4 inside it
+        let variation = &game.moves[2].variations[0];
+        assert_eq!(variation.len(), 3);
+        assert_eq!(variation[1].variations[0][0].san, "d5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pgn_quirks() -> Result<()> {
+        let pgn = "\u{feff}[Event \"a\"]\n% not for us\n1.e4 e5 2.Nf3 Nf6 3.Bc4 Bc5 4.0-0 0-0\n\
+                   [Event \"b\"]\n[Result \"1/2-1/2\"]\n1. d4 c6 2. d5 e5 3. dxe6 e.p.";
+        let games = parse_pgn(pgn)?;
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("a"));
+        assert_eq!(games[0].moves.len(), 8);
+        assert_eq!(games[0].moves[6].san, "0-0");
+        assert_eq!(games[0].result, GameResult::Unknown);
+        // No result in the movetext, so the tag decides
+        assert_eq!(games[1].result, GameResult::Draw);
+        assert_eq!(games[1].replay()?.1.len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pgn_errors() {
+        assert!(parse_pgn("1. e4 {never closed").is_err());
+        assert!(parse_pgn("1. e4 e5)").is_err());
+        assert!(parse_pgn("1. e4 (1. d4").is_err());
+        assert!(parse_pgn("[Event Unquoted]").is_err());
+        assert!(parse_pgn("(1. d4) 1. e4").is_err());
+    }
+
+    #[test]
+    fn test_replay_checks_legality() -> Result<()> {
+        let (board, moves) = parse_pgn(GAME)?[0].replay()?;
+        assert_eq!(moves.len(), 6);
+        assert_eq!(
+            board.to_fen(),
+            "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4"
+        );
+
+        let error = parse_pgn("1. e4 e5 2. Ke3 *")?[0].replay().unwrap_err();
+        assert_eq!(format!("{error:#}"), "at move 2. Ke3: illegal move: Ke3");
+
+        // Variations are checked too
+        assert!(parse_pgn("1. e4 (1. e5) e5 *")?[0].replay().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_pgn() -> Result<()> {
+        let pgn = parse_pgn(GAME)?[0].to_pgn()?;
+
+        assert_eq!(
+            pgn,
+            r#"[Event "Casual game"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "Talia"]
+[Black "Someone \"quoted\""]
+[Result "1-0"]
+
+{Opening comment} 1. e4 {best by test} 1... e5 2. Nf3 $1 (2. f4 exf4 (2... d5)
+3. Nf3) 2... Nc6 {the usual} 3. Bb5 a6 $5 1-0
+"#
+        );
+        // Reading what was written gives the same game back
+        assert_eq!(parse_pgn(&pgn)?[0].to_pgn()?, pgn);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_moves() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 30")?;
+        let moves = [Move::from_square(Square::A1, Square::A8, Flag::None)];
+        let game = PgnGame::from_moves(&board, &moves, GameResult::WhiteWins)?;
+
+        assert_eq!(game.tag("FEN"), Some("6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 30"));
+        assert!(game.to_pgn()?.ends_with("\n30. Ra8# 1-0\n"));
+
+        let illegal = [Move::from_square(Square::A1, Square::B2, Flag::None)];
+        assert!(PgnGame::from_moves(&board, &illegal, GameResult::Unknown).is_err());
+        Ok(())
+    }
+}
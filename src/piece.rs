@@ -12,7 +12,7 @@ pub enum Piece {
     King,
 }
 
-const PIECE_VALUE_TABLE: [i32; 6] = [
+pub const PIECE_VALUE_TABLE: [i32; 6] = [
     100, // Pawn
     300, // Knight
     325, // Bishop
@@ -21,6 +21,16 @@ const PIECE_VALUE_TABLE: [i32; 6] = [
     0,   // King (not included in material count)
 ];
 
+// Pawns and rooks gain value as the board empties, the minor pieces lose a little
+pub const END_GAME_PIECE_VALUE_TABLE: [i32; 6] = [
+    120, // Pawn
+    280, // Knight
+    310, // Bishop
+    530, // Rook
+    920, // Queen
+    0,   // King (not included in material count)
+];
+
 impl Piece {
     pub fn to_symbol(&self, color: Color) -> char {
         match (self, color) {
@@ -43,12 +53,26 @@ impl Piece {
         matches!(self, Piece::Queen | Piece::Rook | Piece::Bishop)
     }
 
+    // Middle game value, used wherever the game phase is not known (e.g. move ordering)
     pub fn piece_value(&self) -> i32 {
         PIECE_VALUE_TABLE[*self as usize]
     }
 
+    pub fn end_game_piece_value(&self) -> i32 {
+        END_GAME_PIECE_VALUE_TABLE[*self as usize]
+    }
+
+    // Middle game piece square table bonus for a piece of this color on the square
     pub fn position_value(&self, square: usize, color: Color) -> i32 {
-        let index = match color {
+        MIDDLE_GAME_PIECE_SQUARE_TABLES[*self as usize][Self::square_table_index(square, color)]
+    }
+
+    pub fn end_game_position_value(&self, square: usize, color: Color) -> i32 {
+        END_GAME_PIECE_SQUARE_TABLES[*self as usize][Self::square_table_index(square, color)]
+    }
+
+    pub fn square_table_index(square: usize, color: Color) -> usize {
+        match color {
             Color::White => {
                 let rank = square / 8;
                 let file = square % 8;
@@ -57,9 +81,7 @@ impl Piece {
                 rank * 8 + file
             }
             Color::Black => square,
-        };
-
-        PIECE_SQUARE_TABLES[*self as usize][index]
+        }
     }
 }
 
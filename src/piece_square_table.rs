@@ -1,7 +1,7 @@
 // Source: https://www.chessprogramming.org/Simplified_Evaluation_Function
 
 #[rustfmt::skip]
-pub const PAWN_SQUARE_TABLE: [i32; 64] = [
+pub const PAWN_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
      0,  0,  0,  0,  0,  0,  0,  0,
      50, 50, 50, 50, 50, 50, 50, 50,
      10, 10, 20, 30, 30, 20, 10, 10,
@@ -13,7 +13,7 @@ pub const PAWN_SQUARE_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const KNIGHT_SQUARE_TABLE: [i32; 64] = [
+pub const KNIGHT_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
     -50,-40,-30,-30,-30,-30,-40,-50,
     -40,-20,  0,  0,  0,  0,-20,-40,
     -30,  0, 10, 15, 15, 10,  0,-30,
@@ -25,7 +25,7 @@ pub const KNIGHT_SQUARE_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const BISHOP_SQUARE_TABLE: [i32; 64] = [
+pub const BISHOP_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
     -20,-10,-10,-10,-10,-10,-10,-20,
     -10,  0,  0,  0,  0,  0,  0,-10,
     -10,  0,  5, 10, 10,  5,  0,-10,
@@ -37,7 +37,7 @@ pub const BISHOP_SQUARE_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const ROOK_SQUARE_TABLE: [i32; 64] = [
+pub const ROOK_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
       0,  0,  0,  0,  0,  0,  0,  0,
       5, 10, 10, 10, 10, 10, 10,  5,
      -5,  0,  0,  0,  0,  0,  0, -5,
@@ -49,7 +49,7 @@ pub const ROOK_SQUARE_TABLE: [i32; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const QUEEN_SQUARE_TABLE: [i32; 64] = [
+pub const QUEEN_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
     -20,-10,-10, -5, -5,-10,-10,-20,
     -10,  0,  0,  0,  0,  0,  0,-10,
     -10,  0,  5,  5,  5,  5,  0,-10,
@@ -72,11 +72,97 @@ pub const KING_MIDDLE_GAME_SQUARE_TABLE: [i32; 64] = [
      20, 30, 10,  0,  0, 10, 30, 20
 ];
 
-pub const PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
-    PAWN_SQUARE_TABLE,
-    KNIGHT_SQUARE_TABLE,
-    BISHOP_SQUARE_TABLE,
-    ROOK_SQUARE_TABLE,
-    QUEEN_SQUARE_TABLE,
+// The end game tables below are adapted from the middle game ones: pawns are worth more the
+// closer they are to promoting, pieces care less about development, and the king comes out to
+// fight once the queens are off.
+
+#[rustfmt::skip]
+pub const PAWN_END_GAME_SQUARE_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     80, 80, 80, 80, 80, 80, 80, 80,
+     50, 50, 50, 50, 50, 50, 50, 50,
+     30, 30, 30, 30, 30, 30, 30, 30,
+     15, 15, 15, 15, 15, 15, 15, 15,
+      5,  5,  5,  5,  5,  5,  5,  5,
+      0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0
+];
+
+#[rustfmt::skip]
+pub const KNIGHT_END_GAME_SQUARE_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+pub const BISHOP_END_GAME_SQUARE_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0, 10, 15, 15, 10,  0,-10,
+    -10,  0, 10, 15, 15, 10,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+pub const ROOK_END_GAME_SQUARE_TABLE: [i32; 64] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+     10, 10, 10, 10, 10, 10, 10, 10,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0
+];
+
+#[rustfmt::skip]
+pub const QUEEN_END_GAME_SQUARE_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+     -5,  0, 10, 15, 15, 10,  0, -5,
+     -5,  0, 10, 15, 15, 10,  0, -5,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20
+];
+
+// Source: https://www.chessprogramming.org/Simplified_Evaluation_Function
+#[rustfmt::skip]
+pub const KING_END_GAME_SQUARE_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50
+];
+
+pub const MIDDLE_GAME_PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
+    PAWN_MIDDLE_GAME_SQUARE_TABLE,
+    KNIGHT_MIDDLE_GAME_SQUARE_TABLE,
+    BISHOP_MIDDLE_GAME_SQUARE_TABLE,
+    ROOK_MIDDLE_GAME_SQUARE_TABLE,
+    QUEEN_MIDDLE_GAME_SQUARE_TABLE,
     KING_MIDDLE_GAME_SQUARE_TABLE,
 ];
+
+pub const END_GAME_PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
+    PAWN_END_GAME_SQUARE_TABLE,
+    KNIGHT_END_GAME_SQUARE_TABLE,
+    BISHOP_END_GAME_SQUARE_TABLE,
+    ROOK_END_GAME_SQUARE_TABLE,
+    QUEEN_END_GAME_SQUARE_TABLE,
+    KING_END_GAME_SQUARE_TABLE,
+];
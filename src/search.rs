@@ -1,84 +1,50 @@
 use anyhow::{bail, Result};
-use reqwest::{self, blocking::Client};
-use serde::Deserialize;
 use std::sync::atomic::{AtomicI32, Ordering};
 
 use crate::{
+    book,
     evaluate::evaluate,
+    kpk,
     move_generation::{Flag, Move, MoveGenerator},
+    nnue::active_network,
+    syzygy::{self, Wdl},
+    tablebase::{active_provider, Category, TablebaseMove},
 };
 
 const INF: i32 = i32::MAX;
+// What a tablebase win is worth, more than any evaluation but less than a mate the search sees
+pub const TB_WIN: i32 = 20_000;
 pub static COUNTER: AtomicI32 = AtomicI32::new(0);
 
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-pub struct TablebaseResponse {
-    pub dtz: Option<i32>,
-    pub precise_dtz: Option<i32>,
-    pub dtm: Option<i32>,
-    pub checkmate: bool,
-    pub stalemate: bool,
-    pub insufficient_material: bool,
-    pub category: Category,
-    pub moves: Vec<TablebaseMove>,
-}
-
-#[allow(unused)]
-#[derive(Debug, Deserialize)]
-pub struct TablebaseMove {
-    uci: String,
-    san: String,
-    dtz: Option<i32>,
-    precise_dtz: Option<i32>,
-    dtm: Option<i32>,
-    zeroing: bool,
-    checkmate: bool,
-    stalemate: bool,
-    insufficient_material: bool,
-    category: Category,
-}
-
-#[derive(Debug, Deserialize, Eq, PartialEq)]
-pub enum Category {
-    #[serde(rename = "win")]
-    Win,
-    #[serde(rename = "loss")]
-    Loss,
-    #[serde(rename = "draw")]
-    Draw,
-}
-
-impl TablebaseResponse {
-    fn get_best_move(&self) -> &TablebaseMove {
-        let mut best_move = &self.moves[0];
-
-        for mv in &self.moves {
-            match best_move.category {
-                // The category is from the opponent's perspective. So a loss is good
-                Category::Win => {
-                    if mv.category == Category::Draw || mv.category == Category::Loss {
-                        best_move = mv
-                    }
-                }
-                Category::Loss => {
-                    if mv.category == Category::Loss && mv.dtm > best_move.dtm {
-                        best_move = mv
-                    }
-                }
-                Category::Draw => {
-                    if mv.category == Category::Loss {
-                        best_move = mv
-                    }
-                }
-            }
-        }
-
-        best_move
+// draw_score is what a draw is worth to the side to move. It flips sign every ply, so with a
+// positive contempt the root side avoids draws and its opponent is assumed to seek them.
+pub fn search(
+    move_generator: &mut MoveGenerator,
+    depth: u32,
+    mut alpha: i32,
+    beta: i32,
+    draw_score: i32,
+) -> i32 {
+    // Reaching the hill or giving the third check wins on the spot
+    if move_generator.board.variant.is_lost(&move_generator.board) {
+        return -INF;
+    }
+    if move_generator.board.is_repetition() || move_generator.board.is_insufficient_material() {
+        return draw_score;
+    }
+    // No need to search a king and pawn ending the bitbase knows is drawn
+    if kpk::probe_board(&move_generator.board) == Some(false) {
+        return draw_score;
+    }
+    if let Some(wdl) = syzygy::probe_search(move_generator, depth) {
+        return match wdl {
+            Wdl::Win => TB_WIN,
+            Wdl::Loss => -TB_WIN,
+            // The fifty move rule turns cursed wins and blessed losses into draws
+            Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => draw_score,
+        };
     }
-}
 
-pub fn search(move_generator: &mut MoveGenerator, depth: u32, mut alpha: i32, beta: i32) -> i32 {
     if depth == 0 {
         COUNTER.fetch_add(1, Ordering::Relaxed);
         return search_all_captures(move_generator, alpha, beta);
@@ -91,14 +57,20 @@ pub fn search(move_generator: &mut MoveGenerator, depth: u32, mut alpha: i32, be
             // remaining is worse than low depth remaining
             return -INF;
         } else {
-            return 0;
+            return draw_score;
         }
     }
 
+    // Checkmate takes precedence over the fifty move rule, so only check it once we know
+    // there are legal moves
+    if move_generator.board.is_fifty_move_rule_draw() {
+        return draw_score;
+    }
+
     moves.sort_unstable_by_key(|mv| guess_move_score(move_generator, mv));
     for mv in moves.iter() {
         move_generator.board.move_piece(mv);
-        let eval = -search(move_generator, depth - 1, -beta, -alpha);
+        let eval = -search(move_generator, depth - 1, -beta, -alpha, -draw_score);
         move_generator.board.unmake_move(mv).unwrap();
 
         if eval >= beta {
@@ -114,6 +86,9 @@ pub fn search(move_generator: &mut MoveGenerator, depth: u32, mut alpha: i32, be
 
 // TODO: Modify move generation to make this more efficient
 fn search_all_captures(move_generator: &mut MoveGenerator, alpha: i32, beta: i32) -> i32 {
+    if move_generator.board.variant.is_lost(&move_generator.board) {
+        return -INF;
+    }
     let eval = evaluate(move_generator);
     if eval >= beta {
         return beta;
@@ -146,35 +121,78 @@ fn search_all_captures(move_generator: &mut MoveGenerator, alpha: i32, beta: i32
     alpha
 }
 
-pub fn query_tablebase(move_generator: &mut MoveGenerator) -> Result<(Move, i32)> {
-    let base_tb_server_url = "http://tablebase.lichess.ovh/standard";
-    // Make FEN URL friendly
-    let params = [("fen", move_generator.board.to_fen().replace(' ', "_"))];
-    let client = Client::new();
-    let response = client.get(base_tb_server_url).query(&params).send()?;
-
-    let tb_response: TablebaseResponse = if response.status().is_success() {
-        response.json()?
-    } else {
-        bail!("Call to tablebase failed");
+// Asks the configured tablebase for the best root move, None if it does not cover the position
+pub fn query_tablebase(move_generator: &mut MoveGenerator) -> Result<Option<(Move, i32)>> {
+    let fen = move_generator.board.to_fen();
+    let Some(response) = active_provider().probe(&fen)? else {
+        return Ok(None);
+    };
+    let Some(best_move) = response.get_best_move() else {
+        return Ok(None);
     };
 
-    let best_move = tb_response.get_best_move();
-    let eval = match best_move.category {
-        Category::Win => -INF,
-        Category::Draw => 0,
-        Category::Loss => INF,
+    Ok(Some((
+        Move::try_from_uci(&best_move.uci, move_generator)?,
+        tablebase_score(best_move),
+    )))
+}
+
+// What a tablebase move is worth to the side playing it. A mate on the board scores INF, the
+// flat score the search gives every mate, and a mate in n plies scores INF - n so the score
+// still tells how far away it is. The search never sees these, since a tablebase move is played
+// straight from the root. A win without a known distance to mate scores like a tablebase win in
+// the search, a little less the further away the next zeroing move is.
+fn tablebase_score(mv: &TablebaseMove) -> i32 {
+    let win_score = || match (mv.checkmate, mv.dtm, mv.dtz) {
+        (true, _, _) => INF,
+        (false, Some(dtm), _) => INF - dtm.abs(),
+        (false, None, dtz) => TB_WIN - dtz.unwrap_or(0).abs(),
     };
 
-    Ok((Move::try_from_uci(&best_move.uci, move_generator)?, eval))
+    // The category is from the opponent's perspective
+    match mv.category {
+        Category::Loss | Category::SyzygyLoss | Category::MaybeLoss => win_score(),
+        Category::Win | Category::SyzygyWin | Category::MaybeWin => -win_score(),
+        // The fifty move rule turns cursed wins and blessed losses into draws
+        Category::BlessedLoss | Category::Draw | Category::Unknown | Category::CursedWin => 0,
+    }
 }
 
+// contempt is how much Talia dislikes a draw, in centipawns from the perspective of the side to
+// move at the root. Negative values make Talia steer towards draws.
 pub fn find_best_move(
     moves: &mut [Move],
     move_generator: &mut MoveGenerator,
     depth: u32,
+    contempt: i32,
+) -> (Move, i32) {
+    // Book moves come without a score
+    if let Some(book_move) = book::probe_book(move_generator) {
+        if moves.contains(&book_move) {
+            COUNTER.store(0, Ordering::Relaxed);
+            return (book_move, 0);
+        }
+    }
+
+    search_root_moves(moves, move_generator, depth, contempt)
+}
+
+// Like find_best_move, but always searches instead of playing a move from the opening book
+pub fn search_root_moves(
+    moves: &mut [Move],
+    move_generator: &mut MoveGenerator,
+    depth: u32,
+    contempt: i32,
 ) -> (Move, i32) {
     COUNTER.store(0, Ordering::Relaxed);
+    move_generator.board.attach_network(active_network());
+
+    // With local tablebases only the moves that keep the best result are searched
+    let local_tablebase_moves = syzygy::filter_root_moves(move_generator, moves);
+    let moves = match local_tablebase_moves {
+        Some(count) => &mut moves[..count],
+        None => moves,
+    };
 
     let pieces_left = move_generator
         .board
@@ -182,17 +200,24 @@ pub fn find_best_move(
         .iter()
         .filter(|sq| sq.is_some())
         .count();
-    if pieces_left <= 7 {
-        // TODO: Add logging for when query fails
+    // Tablebases only know standard chess
+    if local_tablebase_moves.is_none()
+        && pieces_left <= 7
+        && move_generator.board.variant.is_standard()
+    {
         match query_tablebase(move_generator) {
-            Ok(tb_result) => return tb_result,
-            Err(err) => println!("{err}"),
+            // The tablebase knows nothing about root move restrictions, so only trust it
+            // if the move it suggests is one we are allowed to play
+            Ok(Some(tb_result)) if moves.contains(&tb_result.0) => return tb_result,
+            Ok(_) => {}
+            // A failed lookup just means searching the position like any other
+            Err(err) => println!("info string {err:#}"),
         }
     }
     moves.sort_unstable_by_key(|mv| guess_move_score(move_generator, mv));
 
     let mut best_move = moves
-        .get(0)
+        .first()
         .expect("moves vector must have at least one move");
 
     let mut best_eval = -INF;
@@ -204,7 +229,8 @@ pub fn find_best_move(
 
         for mv in moves.iter() {
             move_generator.board.move_piece(mv);
-            let eval = -search(move_generator, curr_depth, -beta, -alpha);
+            // After a root move it is the opponent's turn, and a draw is worth the contempt to them
+            let eval = -search(move_generator, curr_depth, -beta, -alpha, contempt);
             move_generator.board.unmake_move(mv).unwrap();
             // If we see mate at the current depth, stop the search, since
             // the current move is guarenteed to be the fastest mate
@@ -223,12 +249,33 @@ pub fn find_best_move(
     (best_move.clone(), best_eval)
 }
 
+// Restricts the root moves to the ones listed by the UCI 'go searchmoves' parameter.
+// Moves that are not legal in the current position are ignored.
+pub fn filter_root_moves(moves: &[Move], search_moves: &[Move]) -> Result<Vec<Move>> {
+    let filtered_moves: Vec<Move> = moves
+        .iter()
+        .filter(|mv| search_moves.contains(mv))
+        .cloned()
+        .collect();
+
+    if filtered_moves.is_empty() {
+        bail!("none of the search moves are legal in the current position");
+    }
+
+    Ok(filtered_moves)
+}
+
 pub fn guess_move_score(move_generator: &MoveGenerator, mv: &Move) -> i32 {
     let mut score_guess: i32 = 0;
 
+    let capture_piece_multiplier = 10;
+    // A dropped piece only has the square it lands on
+    if let Flag::Drop(piece) = mv.flag {
+        let piece_color = move_generator.board.to_move;
+        return -piece.position_value(mv.target_square, piece_color);
+    }
     let starting_piece = move_generator.board.squares[mv.starting_square].unwrap();
     let piece_color = move_generator.board.colors[mv.starting_square].unwrap();
-    let capture_piece_multiplier = 10;
 
     match mv.flag {
         Flag::PromoteTo(piece) => score_guess += piece.piece_value(),
@@ -259,12 +306,14 @@ mod tests {
         board_builder::BoardBuilder,
         move_generation::{Flag, Move, MoveGenerator},
         piece::{Color, Piece},
-        search::INF,
+        search::{INF, TB_WIN},
         square::Square,
+        tablebase::{Category, TablebaseMove},
+        variant::Variant,
     };
     use anyhow::Result;
 
-    use super::find_best_move;
+    use super::{filter_root_moves, find_best_move, search, tablebase_score};
 
     #[test]
     fn test_find_best_move_mate_in_one() -> Result<()> {
@@ -277,7 +326,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2);
+        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2, 0);
         let mating_move = Move::from_square(Square::A8, Square::A1, Flag::None);
 
         assert!(best_move == mating_move);
@@ -293,7 +342,7 @@ mod tests {
         let board: Board = BoardBuilder::try_from_fen("k6r/2p3pp/4p3/4P3/7q/8/5r2/3K4 b - - 1 41")?;
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6, 0);
         let expected_best_move = Move::from_square(Square::H4, Square::H1, Flag::None);
 
         assert!(best_move == expected_best_move);
@@ -307,7 +356,7 @@ mod tests {
             BoardBuilder::try_from_fen("k6r/2p2ppp/4P3/4P3/8/1r6/4KP1P/2q5 b - - 0 36")?;
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 6, 0);
         // The only mate in two move
         let expected_best_move = Move::from_square(Square::H8, Square::D8, Flag::None);
 
@@ -328,7 +377,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 2);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 2, 0);
         let capture_move = Move::from_square(Square::E1, Square::E5, Flag::Capture(Piece::Queen));
 
         assert!(best_move == capture_move);
@@ -348,7 +397,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3, 0);
         let capture_move = Move::from_square(Square::A1, Square::E1, Flag::None);
 
         assert!(best_move == capture_move);
@@ -368,7 +417,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3, 0);
         let forking_move = Move::from_square(Square::D1, Square::E3, Flag::None);
 
         assert!(best_move == forking_move);
@@ -387,7 +436,7 @@ mod tests {
 
         let mut move_generator = MoveGenerator::new(board);
         let mut moves = move_generator.generate_moves();
-        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3);
+        let (best_move, _) = find_best_move(&mut moves, &mut move_generator, 3, 0);
 
         assert!(
             best_move == Move::from_square(Square::A7, Square::A8, Flag::PromoteTo(Piece::Queen))
@@ -395,4 +444,212 @@ mod tests {
         println!("{best_move}");
         Ok(())
     }
+
+    #[test]
+    fn test_filter_root_moves() -> Result<()> {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let moves = move_generator.generate_moves();
+        let search_moves = [
+            Move::from_square(Square::G1, Square::F3, Flag::None),
+            Move::from_square(Square::E2, Square::E4, Flag::PawnDoublePush),
+        ];
+
+        let filtered_moves = filter_root_moves(&moves, &search_moves)?;
+
+        assert!(filtered_moves == search_moves);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_root_moves_none_legal() {
+        let mut move_generator = MoveGenerator::new(Board::starting_position());
+        let moves = move_generator.generate_moves();
+        let search_moves = [Move::from_square(Square::E2, Square::E5, Flag::None)];
+
+        let filtered_moves = filter_root_moves(&moves, &search_moves);
+
+        assert_eq!(
+            filtered_moves.err().unwrap().to_string(),
+            "none of the search moves are legal in the current position"
+        );
+    }
+
+    #[test]
+    fn test_find_best_move_with_search_moves() -> Result<()> {
+        // Same position as the mate in one, but the mating move is not allowed
+        let board: Board = BoardBuilder::new()
+            .piece(Square::H1, Piece::King, Color::White)
+            .piece(Square::H3, Piece::King, Color::Black)
+            .piece(Square::A8, Piece::Rook, Color::Black)
+            .to_move(Color::Black)
+            .try_into()?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let moves = move_generator.generate_moves();
+        let search_moves = [
+            Move::from_square(Square::A8, Square::A2, Flag::None),
+            Move::from_square(Square::A8, Square::B8, Flag::None),
+        ];
+        let mut moves = filter_root_moves(&moves, &search_moves)?;
+        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2, 0);
+
+        assert!(search_moves.contains(&best_move));
+        assert!(eval != INF);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_scores_repetition_as_draw() -> Result<()> {
+        // Both knights go out and back, repeating the starting position
+        let board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(Square::G1, Square::F3, Flag::None))
+            .make_move(Move::from_square(Square::G8, Square::F6, Flag::None))
+            .make_move(Move::from_square(Square::F3, Square::G1, Flag::None))
+            .make_move(Move::from_square(Square::F6, Square::G8, Flag::None))
+            .try_into()?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let eval = search(&mut move_generator, 3, -INF, INF, -25);
+
+        assert_eq!(eval, -25);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_scores_insufficient_material_as_draw() -> Result<()> {
+        let board: Board = BoardBuilder::new()
+            .piece(Square::E1, Piece::King, Color::White)
+            .piece(Square::E8, Piece::King, Color::Black)
+            .piece(Square::C3, Piece::Knight, Color::White)
+            .to_move(Color::White)
+            .try_into()?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let eval = search(&mut move_generator, 3, -INF, INF, 10);
+
+        assert_eq!(eval, 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_scores_drawn_king_and_pawn_as_draw() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1")?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let eval = search(&mut move_generator, 3, -INF, INF, 10);
+
+        assert_eq!(eval, 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_scores_fifty_move_rule_as_draw() -> Result<()> {
+        let board: Board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/Q3K3 w - - 100 80")?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let eval = search(&mut move_generator, 3, -INF, INF, 0);
+
+        assert_eq!(eval, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_prefers_mate_over_fifty_move_rule() -> Result<()> {
+        // Qa8 and Rh8 are both mate on the 100th half move, so they still count
+        let board: Board = BoardBuilder::try_from_fen("4k3/7R/4K3/8/8/8/8/Q7 w - - 99 80")?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let eval = search(&mut move_generator, 2, -INF, INF, 0);
+
+        assert_eq!(eval, INF);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_best_move_reaches_the_hill() -> Result<()> {
+        // e4 is covered by the queen, d4 isn't
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/3K4/8/7q w - - 0 1")?;
+        board.variant = Variant::KingOfTheHill;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2, 0);
+
+        assert_eq!(best_move, Move::from_square(Square::D3, Square::D4, Flag::None));
+        assert_eq!(eval, INF);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_best_move_gives_third_check() -> Result<()> {
+        let board = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/q7/4K2R w - - 0 1 +2+0")?;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2, 0);
+
+        assert_eq!(best_move, Move::from_square(Square::H1, Square::H8, Flag::None));
+        assert_eq!(eval, INF);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_best_move_blows_up_the_king() -> Result<()> {
+        // Taking the pawn next to the king wins on the spot in atomic
+        let mut board = BoardBuilder::try_from_fen("4k3/3p4/8/8/8/8/8/3RK3 w - - 0 1")?;
+        board.variant = Variant::Atomic;
+
+        let mut move_generator = MoveGenerator::new(board);
+        let mut moves = move_generator.generate_moves();
+        let (best_move, eval) = find_best_move(&mut moves, &mut move_generator, 2, 0);
+
+        assert_eq!(
+            best_move,
+            Move::from_square(Square::D1, Square::D7, Flag::Capture(Piece::Pawn))
+        );
+        assert_eq!(eval, INF);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tablebase_score() {
+        let mut mv = TablebaseMove {
+            uci: "a1a2".to_string(),
+            san: None,
+            dtz: Some(-4),
+            precise_dtz: Some(-4),
+            dtm: Some(-7),
+            zeroing: false,
+            checkmate: false,
+            stalemate: false,
+            insufficient_material: false,
+            category: Category::Loss,
+        };
+        assert_eq!(tablebase_score(&mv), INF - 7);
+
+        mv.dtm = None;
+        mv.category = Category::SyzygyLoss;
+        assert_eq!(tablebase_score(&mv), TB_WIN - 4);
+
+        mv.category = Category::MaybeWin;
+        mv.dtz = Some(4);
+        assert_eq!(tablebase_score(&mv), -(TB_WIN - 4));
+
+        mv.category = Category::BlessedLoss;
+        assert_eq!(tablebase_score(&mv), 0);
+
+        mv.category = Category::Loss;
+        mv.checkmate = true;
+        assert_eq!(tablebase_score(&mv), INF);
+    }
 }
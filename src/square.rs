@@ -1,5 +1,6 @@
 use anyhow::{bail, Result};
 
+#[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
 pub struct Sq(u8);
 
@@ -0,0 +1,1596 @@
+// Probing Syzygy endgame tablebases from local .rtbw (win/draw/loss) and .rtbz (distance to
+// zeroing) files
+// Source: https://www.chessprogramming.org/Syzygy_Bases, following the layout of the probing
+// code in Stockfish (src/syzygy/tbprobe.cpp)
+//
+// A table is stored per material signature, with white as the stronger side, so KQvKR also
+// answers for KRvKQ with the colours swapped. Positions are turned into an index by placing the
+// pieces group by group while using the symmetries of the board, and the values are stored
+// compressed with recursive pairing and a canonical Huffman code, split into blocks that a sparse
+// index points into.
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    board::Board,
+    move_generation::{Flag, Move, MoveGenerator},
+    piece::{Color, Piece},
+};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+const MAX_PIECES: usize = 7;
+// Ranks root moves, see root_rank
+const MAX_DTZ: i32 = 1 << 18;
+
+// Flags of a compressed table
+const STM_FLAG: u8 = 1;
+const MAPPED_FLAG: u8 = 2;
+const WIN_PLIES_FLAG: u8 = 4;
+const LOSS_PLIES_FLAG: u8 = 8;
+const WIDE_FLAG: u8 = 16;
+const SINGLE_VALUE_FLAG: u8 = 128;
+
+// The tablebases found in SyzygyPath, if any
+static TABLEBASES: RwLock<Option<Arc<Tablebases>>> = RwLock::new(None);
+// Pieces in the largest table found, so the search can skip the lock for bigger positions
+static LARGEST_TABLE: AtomicUsize = AtomicUsize::new(0);
+// Positions with TBProbeLimit pieces are only probed with at least this much depth left
+pub static TB_PROBE_DEPTH: AtomicU32 = AtomicU32::new(1);
+// Positions with more pieces than this are never probed
+pub static TB_PROBE_LIMIT: AtomicUsize = AtomicUsize::new(MAX_PIECES);
+
+// From the point of view of the side to move. Cursed wins and blessed losses are wins and losses
+// that the fifty move rule turns into draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32)).unwrap()
+    }
+}
+
+// Looks for tables in a list of directories separated like PATH is on this platform. Returns
+// how many win/draw/loss tables were found.
+pub fn load_tablebases(paths: &str) -> Result<usize> {
+    let tablebases = Tablebases::open(paths)?;
+    let table_count = tablebases.table_count;
+    LARGEST_TABLE.store(tablebases.largest_table, Ordering::Relaxed);
+    *TABLEBASES.write().unwrap() = Some(Arc::new(tablebases));
+
+    Ok(table_count)
+}
+
+pub fn clear_tablebases() {
+    LARGEST_TABLE.store(0, Ordering::Relaxed);
+    *TABLEBASES.write().unwrap() = None;
+}
+
+pub fn active_tablebases() -> Option<Arc<Tablebases>> {
+    TABLEBASES.read().unwrap().clone()
+}
+
+// Probes the win/draw/loss tables from inside the search, if the position is small enough and
+// the fifty move counter was just reset, since the tables know nothing about it
+pub fn probe_search(move_generator: &mut MoveGenerator, depth: u32) -> Option<Wdl> {
+    let pieces = piece_count(&move_generator.board);
+    let cardinality = probe_cardinality();
+    if pieces > cardinality
+        || (pieces == cardinality && depth < TB_PROBE_DEPTH.load(Ordering::Relaxed))
+        || move_generator.board.board_state.half_move_clock != 0
+        || has_castling_rights(&move_generator.board)
+        || !move_generator.board.variant.is_standard()
+    {
+        return None;
+    }
+
+    active_tablebases()?.probe_wdl(move_generator)
+}
+
+// Puts the root moves that keep the best result according to the tablebases first, and returns
+// how many there are. None if the position is not covered.
+pub fn filter_root_moves(move_generator: &mut MoveGenerator, moves: &mut [Move]) -> Option<usize> {
+    if piece_count(&move_generator.board) > probe_cardinality()
+        || has_castling_rights(&move_generator.board)
+        || !move_generator.board.variant.is_standard()
+    {
+        return None;
+    }
+
+    active_tablebases()?.filter_root_moves(move_generator, moves)
+}
+
+fn probe_cardinality() -> usize {
+    std::cmp::min(
+        TB_PROBE_LIMIT.load(Ordering::Relaxed),
+        LARGEST_TABLE.load(Ordering::Relaxed),
+    )
+}
+
+fn piece_count(board: &Board) -> usize {
+    board
+        .piece_scores
+        .piece_counts
+        .iter()
+        .flatten()
+        .map(|&count| count as usize)
+        .sum()
+}
+
+fn has_castling_rights(board: &Board) -> bool {
+    let state = &board.board_state;
+    state.white_kingside_castling_priviledge
+        || state.white_queenside_castling_priviledge
+        || state.black_kingside_castling_priviledge
+        || state.black_queenside_castling_priviledge
+}
+
+pub struct Tablebases {
+    // Every table is listed under the material of both colour assignments
+    tables: HashMap<String, Arc<TableEntry>>,
+    table_count: usize,
+    largest_table: usize,
+}
+
+impl Tablebases {
+    pub fn open(paths: &str) -> Result<Self> {
+        let mut wdl_files: Vec<(String, PathBuf)> = Vec::new();
+        let mut dtz_files: HashMap<String, PathBuf> = HashMap::new();
+
+        for directory in std::env::split_paths(paths) {
+            let entries = fs::read_dir(&directory).map_err(|e| {
+                anyhow!("could not read tablebase directory {}: {e}", directory.display())
+            })?;
+            for entry in entries {
+                let path = entry?.path();
+                let (Some(name), Some(extension)) = (
+                    path.file_stem().and_then(|stem| stem.to_str()),
+                    path.extension().and_then(|extension| extension.to_str()),
+                ) else {
+                    continue;
+                };
+                match extension {
+                    "rtbw" => wdl_files.push((name.to_owned(), path.clone())),
+                    "rtbz" => {
+                        dtz_files.entry(name.to_owned()).or_insert(path.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut tablebases = Tablebases {
+            tables: HashMap::new(),
+            table_count: 0,
+            largest_table: 0,
+        };
+        for (name, wdl_path) in wdl_files {
+            let Some(material) = Material::from_name(&name) else {
+                continue;
+            };
+            if tablebases.tables.contains_key(&material.key()) {
+                continue;
+            }
+
+            tablebases.table_count += 1;
+            tablebases.largest_table =
+                std::cmp::max(tablebases.largest_table, material.piece_count);
+            let entry = Arc::new(TableEntry {
+                dtz_path: dtz_files.get(&name).cloned(),
+                wdl_path,
+                material,
+                wdl: OnceLock::new(),
+                dtz: OnceLock::new(),
+            });
+            tablebases
+                .tables
+                .insert(entry.material.key(), entry.clone());
+            tablebases
+                .tables
+                .insert(entry.material.swapped_key(), entry);
+        }
+
+        Ok(tablebases)
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.table_count
+    }
+
+    pub fn largest_table(&self) -> usize {
+        self.largest_table
+    }
+
+    pub fn probe_wdl(&self, move_generator: &mut MoveGenerator) -> Option<Wdl> {
+        self.search_wdl(move_generator, false).map(|(wdl, _)| wdl)
+    }
+
+    // Plies until the next capture or pawn move with best play, positive when the side to move
+    // wins and negative when it loses. Cursed wins and blessed losses are 100 plies further away.
+    pub fn probe_dtz(&self, move_generator: &mut MoveGenerator) -> Option<i32> {
+        let (wdl, zeroing_is_best) = self.search_wdl(move_generator, true)?;
+        // Draws are not stored
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // The table holds a don't care value when the best move zeroes the counter anyway
+        if zeroing_is_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        if let Some(dtz) = self.probe_dtz_table(&move_generator.board, wdl)? {
+            let cursed_plies = match wdl {
+                Wdl::CursedWin | Wdl::BlessedLoss => 100,
+                _ => 0,
+            };
+            return Some((dtz + cursed_plies) * wdl.signum());
+        }
+
+        // The table only stores the other side to move, so look one move ahead
+        let mut best_dtz = 0xFFFF;
+        for mv in move_generator.generate_moves() {
+            let zeroing = is_zeroing(&move_generator.board, &mv);
+            move_generator.board.move_piece(&mv);
+            let dtz = if zeroing {
+                self.search_wdl(move_generator, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.probe_dtz(move_generator).map(|dtz| -dtz)
+            };
+            let is_mate = dtz == Some(1) && is_checkmate(move_generator);
+            move_generator.board.unmake_move(&mv).unwrap();
+
+            let mut dtz = dtz?;
+            if is_mate {
+                best_dtz = 1;
+            }
+            // Zeroing moves already count the move itself
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < best_dtz && dtz.signum() == wdl.signum() {
+                best_dtz = dtz;
+            }
+        }
+
+        // Without legal moves the side to move is mated
+        Some(if best_dtz == 0xFFFF { -1 } else { best_dtz })
+    }
+
+    pub fn filter_root_moves(
+        &self,
+        move_generator: &mut MoveGenerator,
+        moves: &mut [Move],
+    ) -> Option<usize> {
+        // Without the distance to zeroing tables the result alone still rules out the moves
+        // that throw it away
+        let ranks = self
+            .rank_root_moves(move_generator, moves)
+            .or_else(|| self.rank_root_moves_by_wdl(move_generator, moves))?;
+        let mut ranked_moves: Vec<(Move, i32)> = moves.iter().cloned().zip(ranks).collect();
+        ranked_moves.sort_by_key(|&(_, rank)| Reverse(rank));
+
+        let best_rank = ranked_moves.first()?.1;
+        let best_count = ranked_moves
+            .iter()
+            .filter(|&&(_, rank)| rank == best_rank)
+            .count();
+        for (slot, (mv, _)) in moves.iter_mut().zip(ranked_moves) {
+            *slot = mv;
+        }
+
+        Some(best_count)
+    }
+
+    // Ranks every root move by the distance to zeroing after it, keeping the fifty move rule in
+    // mind: quicker wins rank higher, and losses that hold out longer rank higher.
+    pub fn rank_root_moves(
+        &self,
+        move_generator: &mut MoveGenerator,
+        moves: &[Move],
+    ) -> Option<Vec<i32>> {
+        let half_move_clock = move_generator.board.board_state.half_move_clock as i32;
+        let mut ranks = Vec::with_capacity(moves.len());
+
+        for mv in moves {
+            move_generator.board.move_piece(mv);
+            let dtz = if move_generator.board.board_state.half_move_clock == 0 {
+                self.search_wdl(move_generator, false)
+                    .map(|(wdl, _)| dtz_before_zeroing(-wdl))
+            } else if move_generator.board.is_repetition()
+                || move_generator.board.is_fifty_move_rule_draw()
+            {
+                Some(0)
+            } else {
+                self.probe_dtz(move_generator)
+                    .map(|dtz| -dtz + (-dtz).signum())
+            };
+            let is_mate = dtz == Some(2) && is_checkmate(move_generator);
+            move_generator.board.unmake_move(mv).unwrap();
+
+            let dtz = if is_mate { 1 } else { dtz? };
+            ranks.push(root_rank(dtz, half_move_clock));
+        }
+
+        Some(ranks)
+    }
+
+    fn rank_root_moves_by_wdl(
+        &self,
+        move_generator: &mut MoveGenerator,
+        moves: &[Move],
+    ) -> Option<Vec<i32>> {
+        moves
+            .iter()
+            .map(|mv| {
+                move_generator.board.move_piece(mv);
+                let wdl = self.probe_wdl(move_generator);
+                move_generator.board.unmake_move(mv).unwrap();
+                Some(-(wdl? as i32))
+            })
+            .collect()
+    }
+
+    // The tables leave out positions where a capture (or a pawn move, for distance to zeroing)
+    // is best, so those have to be searched. Also returns whether such a move is best.
+    fn search_wdl(
+        &self,
+        move_generator: &mut MoveGenerator,
+        check_zeroing_moves: bool,
+    ) -> Option<(Wdl, bool)> {
+        let moves = move_generator.generate_moves();
+        let mut best = Wdl::Loss;
+        let mut searched_moves = 0;
+
+        for mv in &moves {
+            let is_pawn_move =
+                move_generator.board.squares[mv.starting_square] == Some(Piece::Pawn);
+            if !is_capture(mv) && (!check_zeroing_moves || !is_pawn_move) {
+                continue;
+            }
+            searched_moves += 1;
+
+            move_generator.board.move_piece(mv);
+            let wdl = self.search_wdl(move_generator, false);
+            move_generator.board.unmake_move(mv).unwrap();
+
+            let wdl = -wdl?.0;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // With every move searched the stored value could be wrong, en passant is not part of
+        // the tables for example
+        let no_more_moves = searched_moves > 0 && searched_moves == moves.len();
+        let wdl = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(&move_generator.board)?
+        };
+
+        if best >= wdl {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((wdl, false))
+        }
+    }
+
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        if piece_count(board) == 2 {
+            return Some(Wdl::Draw);
+        }
+
+        let entry = self.tables.get(&board_key(board))?;
+        let table = entry
+            .wdl
+            .get_or_init(|| Table::load(&entry.wdl_path, &entry.material, TableKind::Wdl).ok())
+            .as_ref()?;
+        match table.probe(&entry.material, board)? {
+            Probe::Value(_, value) => Wdl::from_value(value - 2),
+            Probe::ChangeSideToMove => None,
+        }
+    }
+
+    // None if the table could not be probed, Some(None) if it only stores the other side to move
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Option<Option<i32>> {
+        let entry = self.tables.get(&board_key(board))?;
+        let table = entry
+            .dtz
+            .get_or_init(|| {
+                let path = entry.dtz_path.as_ref()?;
+                Table::load(path, &entry.material, TableKind::Dtz).ok()
+            })
+            .as_ref()?;
+        match table.probe(&entry.material, board)? {
+            Probe::Value(file, value) => Some(Some(table.map_dtz(file, value, wdl)?)),
+            Probe::ChangeSideToMove => Some(None),
+        }
+    }
+}
+
+fn root_rank(dtz: i32, half_move_clock: i32) -> i32 {
+    if dtz > 0 {
+        // Only a win if zeroing comes before the fifty move rule kicks in
+        if dtz + half_move_clock <= 99 {
+            MAX_DTZ - dtz
+        } else {
+            MAX_DTZ - (dtz + half_move_clock)
+        }
+    } else if dtz < 0 {
+        -MAX_DTZ + (-dtz + half_move_clock)
+    } else {
+        0
+    }
+}
+
+// The distance to zeroing of a position whose best move zeroes the fifty move counter
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn is_capture(mv: &Move) -> bool {
+    matches!(
+        mv.flag,
+        Flag::EnPassantCapture | Flag::Capture(_) | Flag::CaptureWithPromotion(_, _)
+    )
+}
+
+pub(crate) fn is_zeroing(board: &Board, mv: &Move) -> bool {
+    is_capture(mv) || board.squares[mv.starting_square] == Some(Piece::Pawn)
+}
+
+fn is_checkmate(move_generator: &mut MoveGenerator) -> bool {
+    let to_move = move_generator.board.to_move;
+    move_generator.is_in_check(to_move) && move_generator.generate_moves().is_empty()
+}
+
+fn board_key(board: &Board) -> String {
+    let counts = &board.piece_scores.piece_counts;
+    material_key(&counts[Color::White as usize], &counts[Color::Black as usize])
+}
+
+// Table names list the pieces from the king down, like KRPvKR
+const NAME_ORDER: [(Piece, char); 6] = [
+    (Piece::King, 'K'),
+    (Piece::Queen, 'Q'),
+    (Piece::Rook, 'R'),
+    (Piece::Bishop, 'B'),
+    (Piece::Knight, 'N'),
+    (Piece::Pawn, 'P'),
+];
+
+fn material_key(white: &[u8; 6], black: &[u8; 6]) -> String {
+    let side = |counts: &[u8; 6]| -> String {
+        NAME_ORDER
+            .iter()
+            .flat_map(|&(piece, letter)| {
+                std::iter::repeat_n(letter, counts[piece as usize] as usize)
+            })
+            .collect()
+    };
+    format!("{}v{}", side(white), side(black))
+}
+
+// What a table holds, with white as the side listed first in its name
+struct Material {
+    counts: [[u8; 6]; 2],
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // Pawns of the leading colour first, which is the side with fewer pawns (but at least one)
+    pawn_count: [usize; 2],
+}
+
+impl Material {
+    fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0u8; 6]; 2];
+        for (side, pieces) in [white, black].into_iter().enumerate() {
+            if !pieces.starts_with('K') {
+                return None;
+            }
+            for letter in pieces.chars() {
+                let &(piece, _) = NAME_ORDER.iter().find(|&&(_, l)| l == letter)?;
+                counts[side][piece as usize] += 1;
+            }
+            if counts[side][Piece::King as usize] != 1 {
+                return None;
+            }
+        }
+
+        let material = Self::from_counts(counts);
+        (material.piece_count <= MAX_PIECES).then_some(material)
+    }
+
+    fn from_counts(counts: [[u8; 6]; 2]) -> Self {
+        let piece_count = counts.iter().flatten().map(|&count| count as usize).sum();
+        let pawns = [
+            counts[0][Piece::Pawn as usize] as usize,
+            counts[1][Piece::Pawn as usize] as usize,
+        ];
+        let has_unique_pieces = counts
+            .iter()
+            .any(|side| side[..Piece::King as usize].contains(&1));
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        Self {
+            counts,
+            piece_count,
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads {
+                pawns
+            } else {
+                [pawns[1], pawns[0]]
+            },
+        }
+    }
+
+    fn key(&self) -> String {
+        material_key(&self.counts[0], &self.counts[1])
+    }
+
+    fn swapped_key(&self) -> String {
+        material_key(&self.counts[1], &self.counts[0])
+    }
+
+    // Both sides have the same pieces, so only white to move is stored
+    fn is_symmetric(&self) -> bool {
+        self.counts[0] == self.counts[1]
+    }
+}
+
+struct TableEntry {
+    material: Material,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    // Loaded the first time they are probed, None if that failed
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+enum Probe {
+    // The file of the leading pawn the value was found under, and the stored value
+    Value(usize, i32),
+    // Distance to zeroing tables only store one side to move
+    ChangeSideToMove,
+}
+
+// Offsets into the table data of everything needed to decompress one sub-table
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    sparse_index: usize,
+    data: usize,
+    // base64[l] is the lowest symbol of length l + min_sym_len, padded to 64 bits
+    base64: Vec<u64>,
+    // How many values (minus one) a symbol expands into
+    symlen: Vec<u8>,
+    // The order the pieces are encoded in, as Stockfish style piece codes
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    // Where the win, loss, cursed win and blessed loss distance maps start
+    map_idx: [u16; 4],
+}
+
+struct Table {
+    data: Vec<u8>,
+    // Indexed by side to move (only for win/draw/loss tables of unequal material), then by the
+    // file of the leading pawn (only for tables with pawns)
+    pairs: Vec<Vec<PairsData>>,
+    // Start of the maps from stored values to distances in a distance to zeroing table
+    map: usize,
+    kind: TableKind,
+}
+
+impl Table {
+    fn load(path: &Path, material: &Material, kind: TableKind) -> Result<Self> {
+        let data = fs::read(path)
+            .map_err(|e| anyhow!("could not read tablebase {}: {e}", path.display()))?;
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if data.get(..4) != Some(&magic[..]) {
+            bail!("{} is not a Syzygy tablebase", path.display());
+        }
+
+        Self::parse(data, material, kind)
+    }
+
+    fn parse(data: Vec<u8>, material: &Material, kind: TableKind) -> Result<Self> {
+        let indices = indices();
+        let mut offset = 4;
+
+        let flags = read_u8(&data, offset)?;
+        let is_split = flags & 1 != 0;
+        let has_pawns = flags & 2 != 0;
+        if has_pawns != material.has_pawns || is_split == material.is_symmetric() {
+            bail!("tablebase does not match its file name");
+        }
+        offset += 1;
+
+        let sides = if kind == TableKind::Wdl && is_split {
+            2
+        } else {
+            1
+        };
+        let files = if has_pawns { 4 } else { 1 };
+        let both_sides_have_pawns = has_pawns && material.pawn_count[1] > 0;
+        let mut pairs = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let first = read_u8(&data, offset)?;
+            let second = if both_sides_have_pawns {
+                read_u8(&data, offset + 1)?
+            } else {
+                0xFF
+            };
+            let orders = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            offset += 1 + both_sides_have_pawns as usize;
+
+            for k in 0..material.piece_count {
+                let byte = read_u8(&data, offset)?;
+                for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                    let piece = if side == 0 { byte & 0xF } else { byte >> 4 };
+                    if !matches!(piece, 1..=6 | 9..=14) {
+                        bail!("invalid piece in tablebase");
+                    }
+                    side_pairs[file].pieces[k] = piece;
+                }
+                offset += 1;
+            }
+
+            for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                set_groups(material, &mut side_pairs[file], orders[side], file, indices);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                offset = set_sizes(&mut side_pairs[file], &data, offset)?;
+            }
+        }
+
+        let map = offset;
+        if kind == TableKind::Dtz {
+            for d in pairs[0].iter_mut() {
+                if d.flags & MAPPED_FLAG == 0 {
+                    continue;
+                }
+                // Four maps, one per result, each a length followed by that many entries
+                if d.flags & WIDE_FLAG != 0 {
+                    offset += offset & 1;
+                    for map_idx in d.map_idx.iter_mut() {
+                        *map_idx = ((offset - map) / 2 + 1) as u16;
+                        offset += 2 * read_u16(&data, offset)? as usize + 2;
+                    }
+                } else {
+                    for map_idx in d.map_idx.iter_mut() {
+                        *map_idx = (offset - map + 1) as u16;
+                        offset += read_u8(&data, offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                let d = &mut side_pairs[file];
+                d.sparse_index = offset;
+                offset += d.sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                let d = &mut side_pairs[file];
+                d.block_length = offset;
+                offset += d.block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                let d = &mut side_pairs[file];
+                offset = (offset + 0x3F) & !0x3F;
+                d.data = offset;
+                offset += d.num_blocks * d.block_size;
+            }
+        }
+        if offset > data.len() {
+            bail!("tablebase is truncated");
+        }
+
+        Ok(Self {
+            data,
+            pairs,
+            map,
+            kind,
+        })
+    }
+
+    // Finds the value stored for the position, see do_probe_table in Stockfish
+    fn probe(&self, material: &Material, board: &Board) -> Option<Probe> {
+        let indices = indices();
+
+        // Tables are stored with white as the stronger side, and symmetric ones only with white
+        // to move, otherwise the colours are swapped and the board flipped
+        let key = material_key(&material.counts[0], &material.counts[1]);
+        let black_stronger = board_key(board) != key;
+        let symmetric_black_to_move = material.is_symmetric() && board.to_move == Color::Black;
+        let flip = black_stronger || symmetric_black_to_move;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let side_to_move = flip as usize ^ board.to_move as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0u64;
+        let mut lead_pawn_count = 0;
+        let mut file = 0;
+
+        // Tables with pawns are split by the file of the leading pawn, the one furthest towards
+        // the edge and then the lowest
+        if material.has_pawns {
+            let lead_piece = self.pairs[0][0].pieces[0] ^ flip_color;
+            let lead_color = if lead_piece & 8 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+            for square in 0..64 {
+                if board.is_piece_at_square(square, Piece::Pawn, lead_color) {
+                    lead_pawns |= 1 << square;
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_pawn_count = size;
+            let lead = (0..lead_pawn_count).max_by_key(|&i| indices.map_pawns[squares[i]])?;
+            squares.swap(0, lead);
+            file = std::cmp::min(squares[0] % 8, 7 - squares[0] % 8);
+        }
+
+        let side = if self.pairs.len() == 2 {
+            side_to_move
+        } else {
+            0
+        };
+        let d = &self.pairs[side][file];
+        if self.kind == TableKind::Dtz && self.stores_other_side(material, d, side_to_move) {
+            return Some(Probe::ChangeSideToMove);
+        }
+
+        for square in 0..64 {
+            if lead_pawns & (1 << square) != 0 {
+                continue;
+            }
+            if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+                if size == MAX_PIECES {
+                    return None;
+                }
+                squares[size] = square ^ flip_squares;
+                pieces[size] = piece_code(piece, color) ^ flip_color;
+                size += 1;
+            }
+        }
+        if size != material.piece_count {
+            return None;
+        }
+
+        // Put the pieces in the order the table encodes them in
+        for i in lead_pawn_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| pieces[j] == d.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // Mirror the leading piece onto files a-d
+        if squares[0] % 8 > 3 {
+            for square in squares[..size].iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+        if material.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawn_count][squares[0]];
+            squares[1..lead_pawn_count].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawn_count).skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            // Without pawns the leading piece also goes below the fifth rank, and below the
+            // a1-h8 diagonal
+            if squares[0] / 8 > 3 {
+                for square in squares[..size].iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let diagonal_offset = off_a1h8(squares[i]);
+                if diagonal_offset == 0 {
+                    continue;
+                }
+                if diagonal_offset > 0 {
+                    for square in squares[i..size].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            idx = encode_leading_pieces(&squares, material.has_unique_pieces, indices);
+        }
+
+        // The remaining groups, each placed on the squares the earlier groups left free
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_end = group_start + d.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+            let mut n = 0;
+            for i in group_start..group_end {
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|&&square| squares[i] > square)
+                    .count();
+                let free_square = squares[i].checked_sub(adjust + 8 * remaining_pawns as usize)?;
+                n += indices.binomial[i - group_start + 1][free_square];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        let value = decompress_pairs(&self.data, d, idx)?;
+        Some(Probe::Value(file, value))
+    }
+
+    fn stores_other_side(&self, material: &Material, d: &PairsData, side_to_move: usize) -> bool {
+        let stored_side = (d.flags & STM_FLAG) as usize;
+        // Symmetric tables without pawns answer for both sides to move
+        let stores_both_sides = material.is_symmetric() && !material.has_pawns;
+        stored_side != side_to_move && !stores_both_sides
+    }
+
+    // Turns a value from a distance to zeroing table into plies
+    fn map_dtz(&self, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        let d = &self.pairs[0][file];
+        let mut value = value;
+        if d.flags & MAPPED_FLAG != 0 {
+            let map = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let index = d.map_idx[map] as usize + value as usize;
+            value = if d.flags & WIDE_FLAG != 0 {
+                read_u16(&self.data, self.map + 2 * index).ok()? as i32
+            } else {
+                read_u8(&self.data, self.map + index).ok()? as i32
+            };
+        }
+
+        // Stored in moves unless the flags say plies
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & WIN_PLIES_FLAG == 0,
+            Wdl::Loss => d.flags & LOSS_PLIES_FLAG == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false,
+        };
+        if in_moves {
+            value *= 2;
+        }
+
+        Some(value + 1)
+    }
+}
+
+// Stockfish numbers pieces 1-6 for white and 9-14 for black
+fn piece_code(piece: Piece, color: Color) -> u8 {
+    piece as u8 + 1 + 8 * color as u8
+}
+
+fn off_a1h8(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+// The leading group without pawns: the two kings, or the three unique pieces with the most
+// symmetry left to use
+fn encode_leading_pieces(squares: &[usize], has_unique_pieces: bool, indices: &Indices) -> u64 {
+    if !has_unique_pieces {
+        return indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]] as u64;
+    }
+
+    let adjust1 = (squares[1] > squares[0]) as usize;
+    let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+    let rank = |square: usize| square / 8;
+
+    let idx = if off_a1h8(squares[0]) != 0 {
+        (indices.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2] - adjust2
+    } else if off_a1h8(squares[1]) != 0 {
+        (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]]) * 62 + squares[2]
+            - adjust2
+    } else if off_a1h8(squares[2]) != 0 {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(squares[0]) * 7 * 28
+            + (rank(squares[1]) - adjust1) * 28
+            + indices.map_b1h1h7[squares[2]]
+    } else {
+        6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(squares[0]) * 7 * 6
+            + (rank(squares[1]) - adjust1) * 6
+            + (rank(squares[2]) - adjust2)
+    };
+
+    idx as u64
+}
+
+// Splits the pieces into groups of identical pieces (the leading group aside) and works out what
+// every group is multiplied by in the index, in the order the table asks for
+fn set_groups(
+    material: &Material,
+    d: &mut PairsData,
+    order: [u8; 2],
+    file: usize,
+    indices: &Indices,
+) {
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns {
+        0
+    } else if material.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    d.group_len[0] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let both_sides_have_pawns = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if both_sides_have_pawns { 2 } else { 1 };
+    let mut free_squares = 64
+        - d.group_len[0]
+        - if both_sides_have_pawns {
+            d.group_len[1]
+        } else {
+            0
+        };
+    let mut idx: u64 = 1;
+
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if material.has_pawns {
+                indices.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= indices.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= indices.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+// Reads the compression parameters of a sub-table and returns the offset after them
+fn set_sizes(d: &mut PairsData, data: &[u8], mut offset: usize) -> Result<usize> {
+    d.flags = read_u8(data, offset)?;
+    offset += 1;
+    if d.flags & SINGLE_VALUE_FLAG != 0 {
+        // Every position has the same value, stored right here
+        d.min_sym_len = read_u8(data, offset)?;
+        return Ok(offset + 1);
+    }
+
+    let group_count = d
+        .group_len
+        .iter()
+        .position(|&len| len == 0)
+        .unwrap_or(MAX_PIECES);
+    let table_size = d.group_idx[group_count] as usize;
+
+    d.block_size = 1 << read_u8(data, offset)?;
+    d.span = 1 << read_u8(data, offset + 1)?;
+    d.sparse_index_size = table_size.div_ceil(d.span);
+    let padding = read_u8(data, offset + 2)? as usize;
+    d.num_blocks = read_u32(data, offset + 3)? as usize;
+    d.block_length_size = d.num_blocks + padding;
+    let max_sym_len = read_u8(data, offset + 7)?;
+    d.min_sym_len = read_u8(data, offset + 8)?;
+    offset += 9;
+    if d.min_sym_len == 0 || max_sym_len < d.min_sym_len {
+        bail!("invalid symbol lengths in tablebase");
+    }
+
+    // Canonical Huffman code: longer symbols have lower values
+    // Source: https://en.wikipedia.org/wiki/Canonical_Huffman_code
+    d.lowest_sym = offset;
+    let lengths = (max_sym_len - d.min_sym_len) as usize + 1;
+    let mut base64 = vec![0u64; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16(data, d.lowest_sym + 2 * i)? as u64;
+        let next_lowest = read_u16(data, d.lowest_sym + 2 * (i + 1))? as u64;
+        base64[i] = (base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+    }
+    for (i, base) in base64.iter_mut().enumerate() {
+        *base = base
+            .checked_shl((64 - i - d.min_sym_len as usize) as u32)
+            .unwrap_or(0);
+    }
+    d.base64 = base64;
+    offset += lengths * 2;
+
+    // Recursive pairing: every symbol either stands for a value or for a pair of symbols
+    let symbol_count = read_u16(data, offset)? as usize;
+    offset += 2;
+    d.btree = offset;
+    d.symlen = vec![0; symbol_count];
+    let mut visited = vec![false; symbol_count];
+    for symbol in 0..symbol_count {
+        if !visited[symbol] {
+            d.symlen[symbol] = set_symlen(d, data, symbol, &mut visited)?;
+        }
+    }
+
+    Ok(offset + symbol_count * 3 + (symbol_count & 1))
+}
+
+fn set_symlen(d: &mut PairsData, data: &[u8], symbol: usize, visited: &mut [bool]) -> Result<u8> {
+    visited[symbol] = true;
+    let (left, right) = btree_pair(data, d.btree, symbol)?;
+    if right == 0xFFF {
+        return Ok(0);
+    }
+
+    for child in [left, right] {
+        if child >= visited.len() {
+            bail!("invalid symbol in tablebase");
+        }
+        if !visited[child] {
+            d.symlen[child] = set_symlen(d, data, child, visited)?;
+        }
+    }
+
+    Ok((d.symlen[left] as u32 + d.symlen[right] as u32 + 1) as u8)
+}
+
+// The two symbols a symbol expands into, packed into three bytes. Leaf symbols have 0xFFF on the
+// right and their value on the left.
+fn btree_pair(data: &[u8], btree: usize, symbol: usize) -> Result<(usize, usize)> {
+    let offset = btree + 3 * symbol;
+    let bytes = data
+        .get(offset..offset + 3)
+        .ok_or_else(|| anyhow!("tablebase is truncated"))?;
+    let left = ((bytes[1] as usize & 0xF) << 8) | bytes[0] as usize;
+    let right = ((bytes[2] as usize) << 4) | (bytes[1] as usize >> 4);
+
+    Ok((left, right))
+}
+
+// The value stored at an index of a sub-table
+fn decompress_pairs(data: &[u8], d: &PairsData, idx: u64) -> Option<i32> {
+    if d.flags & SINGLE_VALUE_FLAG != 0 {
+        return Some(d.min_sym_len as i32);
+    }
+
+    // The sparse index gives the block and offset of every span-th value, counted from the
+    // middle of the span, and the block lengths get us from there to the right block
+    let idx = idx as usize;
+    let k = idx / d.span;
+    let entry = d.sparse_index + 6 * k;
+    let mut block = read_u32(data, entry).ok()? as usize;
+    let mut offset = read_u16(data, entry + 4).ok()? as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+    let block_length = |block: usize| -> Option<i64> {
+        Some(read_u16(data, d.block_length + 2 * block).ok()? as i64)
+    };
+    while offset < 0 {
+        block = block.checked_sub(1)?;
+        offset += block_length(block)? + 1;
+    }
+    while offset > block_length(block)? {
+        offset -= block_length(block)? + 1;
+        block += 1;
+    }
+
+    // Walk the Huffman coded symbols of the block until the one covering our offset
+    let mut pointer = d.data + block * d.block_size;
+    let mut buffer = read_u64_be(data, pointer);
+    pointer += 8;
+    let mut buffer_size = 64;
+    let mut symbol;
+    loop {
+        let mut len = 0;
+        while buffer < *d.base64.get(len)? {
+            len += 1;
+        }
+        let code_length = len + d.min_sym_len as usize;
+        symbol = ((buffer - d.base64[len]) >> (64 - code_length)) as usize;
+        symbol += read_u16(data, d.lowest_sym + 2 * len).ok()? as usize;
+
+        let symbol_values = *d.symlen.get(symbol)? as i64 + 1;
+        if offset < symbol_values {
+            break;
+        }
+        offset -= symbol_values;
+        buffer <<= code_length;
+        buffer_size -= code_length;
+        if buffer_size <= 32 {
+            buffer_size += 32;
+            buffer |= (read_u32_be(data, pointer) as u64) << (64 - buffer_size);
+            pointer += 4;
+        }
+    }
+
+    // Expand the symbol until the value at our offset is left
+    while d.symlen[symbol] != 0 {
+        let (left, right) = btree_pair(data, d.btree, symbol).ok()?;
+        let left_values = *d.symlen.get(left)? as i64 + 1;
+        if offset < left_values {
+            symbol = left;
+        } else {
+            offset -= left_values;
+            symbol = right;
+        }
+    }
+
+    let (value, _) = btree_pair(data, d.btree, symbol).ok()?;
+    Some(value as i32)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    data.get(offset)
+        .copied()
+        .ok_or_else(|| anyhow!("tablebase is truncated"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow!("tablebase is truncated"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("tablebase is truncated"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Compressed data is read big endian, with zeros past the end of the file
+fn read_u64_be(data: &[u8], offset: usize) -> u64 {
+    (0..8).fold(0, |value, i| {
+        (value << 8) | *data.get(offset + i).unwrap_or(&0) as u64
+    })
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> u32 {
+    (0..4).fold(0, |value, i| {
+        (value << 8) | *data.get(offset + i).unwrap_or(&0) as u32
+    })
+}
+
+// Lookup tables for turning piece placements into table indexes
+struct Indices {
+    // Pawn squares a2-h7 to 47..0, so the leading pawn has the highest value
+    map_pawns: [usize; 64],
+    // Squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [usize; 64],
+    // The a1-d1-d4 triangle to 0..9, with the diagonal last
+    map_a1d1d4: [usize; 64],
+    // The 462 placements of two kings with the first in the a1-d1-d4 triangle
+    map_kk: [[usize; 64]; 10],
+    // binomial[k][n] is the number of ways to pick k of n squares
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(Indices::new)
+}
+
+impl Indices {
+    fn new() -> Self {
+        let mut indices = Indices {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_a1h8(square) < 0 {
+                indices.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for square in 0..=27 {
+            if off_a1h8(square) < 0 && square % 8 <= 3 {
+                indices.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_a1h8(square) == 0 && square % 8 <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // Kings can not touch, and with the first king on the diagonal the second one stays
+        // on or below it
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            for first in 0..=27 {
+                // b1 is the only square that maps to 0, every other unused square does too
+                if indices.map_a1d1d4[first] != idx || (idx == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    if crate::endgame::distance(first, second) <= 1
+                        || (off_a1h8(first) == 0 && off_a1h8(second) > 0)
+                    {
+                        continue;
+                    }
+                    if off_a1h8(first) == 0 && off_a1h8(second) == 0 {
+                        both_on_diagonal.push((idx, second));
+                    } else {
+                        indices.map_kk[idx][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, second) in both_on_diagonal {
+            indices.map_kk[idx][second] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..std::cmp::min(MAX_PIECES, n + 1) {
+                let with = if k > 0 {
+                    indices.binomial[k - 1][n - 1]
+                } else {
+                    0
+                };
+                let without = if k < n { indices.binomial[k][n - 1] } else { 0 };
+                indices.binomial[k][n] = with + without;
+            }
+        }
+
+        let mut available_squares = 47;
+        for lead_pawn_count in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..=6 {
+                    let square = rank * 8 + file;
+                    if lead_pawn_count == 1 {
+                        indices.map_pawns[square] = available_squares;
+                        indices.map_pawns[square ^ 7] = available_squares - 1;
+                        available_squares = available_squares.saturating_sub(2);
+                    }
+                    indices.lead_pawn_idx[lead_pawn_count][square] = idx;
+                    idx += indices.binomial[lead_pawn_count - 1][indices.map_pawns[square]];
+                }
+                indices.lead_pawns_size[lead_pawn_count][file] = idx;
+            }
+        }
+
+        indices
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{
+        decompress_pairs, indices, root_rank, Material, TableKind, Tablebases, Wdl, DTZ_MAGIC,
+        MAX_DTZ,
+    };
+
+    // Syzygy tables for KQvK, KRvK, KBvK, KNvK and KPvK
+    pub(crate) fn test_tablebases() -> Result<Tablebases> {
+        Tablebases::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy"))
+    }
+
+    fn move_generator(fen: &str) -> Result<MoveGenerator> {
+        Ok(MoveGenerator::new(BoardBuilder::try_from_fen(fen)?))
+    }
+
+    #[test]
+    fn test_index_tables() {
+        let indices = indices();
+
+        assert_eq!(indices.map_kk.iter().flatten().max(), Some(&461));
+        assert_eq!(indices.map_b1h1h7.iter().max(), Some(&27));
+        assert_eq!(indices.map_a1d1d4[0], 6);
+        assert_eq!(indices.map_pawns[8], 47);
+        assert_eq!(indices.map_pawns[15], 46);
+        assert_eq!(indices.binomial[2][5], 10);
+        // With a single leading pawn every file has six squares for it
+        assert_eq!(indices.lead_pawns_size[1], [6, 6, 6, 6]);
+    }
+
+    #[test]
+    fn test_material_from_name() {
+        let material = Material::from_name("KRPvKR").unwrap();
+
+        assert_eq!(material.piece_count, 5);
+        assert!(material.has_pawns);
+        assert_eq!(material.key(), "KRPvKR");
+        assert_eq!(material.swapped_key(), "KRvKRP");
+        assert!(Material::from_name("KQQQQQvKQ").is_none());
+        assert!(Material::from_name("QvK").is_none());
+        assert!(Material::from_name("notes").is_none());
+    }
+
+    #[test]
+    fn test_probe_wdl() -> Result<()> {
+        let tablebases = test_tablebases()?;
+        assert_eq!(tablebases.table_count(), 5);
+        assert_eq!(tablebases.largest_table(), 3);
+
+        let probe = |fen: &str| -> Result<Option<Wdl>> {
+            Ok(tablebases.probe_wdl(&mut move_generator(fen)?))
+        };
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1")?, Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2Q1K3 b - - 0 1")?, Some(Wdl::Loss));
+        // The same table answers for black having the queen
+        assert_eq!(probe("2q1k3/8/8/3K4/8/8/8/8 b - - 0 1")?, Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2R1K3 b - - 0 1")?, Some(Wdl::Loss));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2B1K3 w - - 0 1")?, Some(Wdl::Draw));
+        // Stalemate
+        assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")?, Some(Wdl::Draw));
+
+        // With the king two squares in front of the pawn the opposition decides it, on the sixth
+        // rank it wins either way, and the rook pawn is a draw
+        assert_eq!(probe("4k3/8/8/4K3/4P3/8/8/8 w - - 0 1")?, Some(Wdl::Win));
+        assert_eq!(probe("4k3/8/8/4K3/4P3/8/8/8 b - - 0 1")?, Some(Wdl::Draw));
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")?, Some(Wdl::Loss));
+        assert_eq!(probe("k7/8/K7/P7/8/8/8/8 w - - 0 1")?, Some(Wdl::Draw));
+        // And with black having the pawn
+        assert_eq!(probe("8/8/8/8/8/3k4/4p3/4K3 w - - 0 1")?, Some(Wdl::Loss));
+        assert_eq!(probe("8/8/8/8/3k4/8/4p3/6K1 b - - 0 1")?, Some(Wdl::Win));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_dtz() -> Result<()> {
+        let tablebases = test_tablebases()?;
+        let probe = |fen: &str| -> Result<Option<i32>> {
+            Ok(tablebases.probe_dtz(&mut move_generator(fen)?))
+        };
+
+        // The longest wins, mate in 10 with the queen and mate in 16 with the rook
+        assert_eq!(probe("8/8/8/5k2/8/8/1Q6/K7 w - - 0 1")?, Some(19));
+        assert_eq!(probe("8/8/8/8/8/2k5/1R6/K7 w - - 0 1")?, Some(31));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1")?, Some(15));
+        // Only white to move is stored, so black to move is looked up one move ahead
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2Q1K3 b - - 0 1")?, Some(-16));
+        assert_eq!(probe("8/8/8/8/8/8/8/kQK5 b - - 0 1")?, Some(-1));
+        assert_eq!(probe("8/8/8/3k4/8/8/8/2B1K3 w - - 0 1")?, Some(0));
+
+        // Pawn endings count until the next pawn move
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1")?, Some(3));
+        assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1")?, Some(-4));
+        assert_eq!(probe("8/8/8/8/8/1P1k4/8/K7 w - - 0 1")?, Some(15));
+        // Promoting straight away
+        assert_eq!(probe("8/4P3/8/8/8/8/k7/4K3 w - - 0 1")?, Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_captures_are_searched() -> Result<()> {
+        let tablebases = test_tablebases()?;
+
+        // Black simply takes the queen
+        let mut hanging_queen = move_generator("8/8/8/3k4/3Q4/8/8/4K3 b - - 0 1")?;
+        // Or the pawn
+        let mut hanging_pawn = move_generator("8/8/8/8/8/8/3kP3/7K b - - 0 1")?;
+
+        assert_eq!(tablebases.probe_wdl(&mut hanging_queen), Some(Wdl::Draw));
+        assert_eq!(tablebases.probe_dtz(&mut hanging_queen), Some(0));
+        assert_eq!(tablebases.probe_wdl(&mut hanging_pawn), Some(Wdl::Draw));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_moves_keep_the_win() -> Result<()> {
+        let tablebases = test_tablebases()?;
+
+        let mut queen_ending = move_generator("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1")?;
+        let mut moves = queen_ending.generate_moves();
+        let winning_count = tablebases
+            .filter_root_moves(&mut queen_ending, &mut moves)
+            .unwrap();
+        let hanging_queen = Move::from_square(Square::C1, Square::C4, Flag::None);
+
+        // Every move left keeps to the shortest distance to zeroing
+        assert!(winning_count > 0);
+        assert!(!moves[..winning_count].contains(&hanging_queen));
+        for mv in &moves[..winning_count] {
+            queen_ending.board.move_piece(mv);
+            assert_eq!(tablebases.probe_dtz(&mut queen_ending), Some(-14));
+            queen_ending.board.unmake_move(mv)?;
+        }
+
+        // Pushing the pawn straight away lets the king catch it
+        let mut pawn_ending = move_generator("7k/8/8/8/8/4P3/8/K7 w - - 0 1")?;
+        let mut moves = pawn_ending.generate_moves();
+        let winning_count = tablebases
+            .filter_root_moves(&mut pawn_ending, &mut moves)
+            .unwrap();
+        let pawn_push = Move::from_square(Square::E3, Square::E4, Flag::None);
+
+        assert!(winning_count > 0);
+        assert!(moves.contains(&pawn_push));
+        assert!(!moves[..winning_count].contains(&pawn_push));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_reads_symbols() -> Result<()> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy/KPvK.rtbw");
+        let material = Material::from_name("KPvK").unwrap();
+        let table = super::Table::parse(std::fs::read(path)?, &material, TableKind::Wdl)?;
+
+        // A table per file of the pawn and side to move, with wins, draws and losses stored as
+        // the result plus 2
+        assert_eq!(table.pairs.len(), 2);
+        assert_eq!(table.pairs[0].len(), 4);
+        // Six squares for the pawn on the a-file, then the two kings
+        let values: Vec<i32> = (0..6 * 63 * 62)
+            .map(|idx| decompress_pairs(&table.data, &table.pairs[0][0], idx).unwrap())
+            .collect();
+        assert!(values.contains(&2));
+        assert!(values.contains(&4));
+        assert!(values.iter().all(|value| [2, 4].contains(value)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_tables_are_not_probed() -> Result<()> {
+        let directory = std::env::temp_dir().join("talia_test_syzygy_invalid");
+        std::fs::create_dir_all(&directory)?;
+        std::fs::write(directory.join("KQvK.rtbw"), DTZ_MAGIC)?;
+        std::fs::write(directory.join("README.txt"), "not a table")?;
+        let tablebases = Tablebases::open(directory.to_str().unwrap())?;
+
+        let mut move_generator =
+            MoveGenerator::new(BoardBuilder::try_from_fen("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1")?);
+
+        assert_eq!(tablebases.table_count(), 1);
+        assert_eq!(tablebases.probe_wdl(&mut move_generator), None);
+        assert!(Tablebases::open("/does/not/exist").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_rank() {
+        // Quicker wins first, then wins the fifty move rule spoils, draws, and losses
+        assert!(root_rank(1, 0) > root_rank(5, 0));
+        assert!(root_rank(5, 0) > root_rank(60, 50));
+        assert!(root_rank(60, 50) > 0);
+        assert!(root_rank(-10, 0) < 0);
+        assert!(root_rank(-10, 0) > root_rank(-2, 0));
+        assert_eq!(root_rank(1, 0), MAX_DTZ - 1);
+    }
+}
@@ -0,0 +1,683 @@
+// Where the root position gets looked up in an endgame tablebase. The engine talks to a
+// TablebaseProvider, which can be a server speaking the Lichess tablebase API, the local Syzygy
+// files, or nothing at all, optionally with a cache of earlier answers in front of it.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    board_builder::BoardBuilder,
+    move_generation::MoveGenerator,
+    syzygy::{self, Tablebases, Wdl},
+};
+
+pub const DEFAULT_TABLEBASE_URL: &str = "http://tablebase.lichess.ovh/standard";
+pub const DEFAULT_TABLEBASE_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_TABLEBASE_RETRIES: u32 = 2;
+
+static PROVIDER: RwLock<Option<Arc<dyn TablebaseProvider>>> = RwLock::new(None);
+
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TablebaseResponse {
+    pub dtz: Option<i32>,
+    pub precise_dtz: Option<i32>,
+    pub dtm: Option<i32>,
+    pub checkmate: bool,
+    pub stalemate: bool,
+    pub insufficient_material: bool,
+    pub category: Category,
+    pub moves: Vec<TablebaseMove>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TablebaseMove {
+    pub uci: String,
+    // Only the Lichess API fills this in
+    pub san: Option<String>,
+    pub dtz: Option<i32>,
+    pub precise_dtz: Option<i32>,
+    pub dtm: Option<i32>,
+    pub zeroing: bool,
+    pub checkmate: bool,
+    pub stalemate: bool,
+    pub insufficient_material: bool,
+    pub category: Category,
+}
+
+// Ordered from the worst to the best result for the side the category belongs to. Moves are
+// categorised from the opponent's point of view, so the lower their category the better.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    Loss,
+    // Lost according to the Syzygy tables, but without a known distance to mate
+    SyzygyLoss,
+    // Lost, unless rounding in the tables hides a fifty move rule draw
+    MaybeLoss,
+    // Lost, but drawn because of the fifty move rule
+    BlessedLoss,
+    Draw,
+    Unknown,
+    CursedWin,
+    MaybeWin,
+    SyzygyWin,
+    Win,
+}
+
+impl Category {
+    pub fn is_win(self) -> bool {
+        matches!(
+            self,
+            Category::CursedWin | Category::MaybeWin | Category::SyzygyWin | Category::Win
+        )
+    }
+
+    pub fn is_loss(self) -> bool {
+        matches!(
+            self,
+            Category::Loss | Category::SyzygyLoss | Category::MaybeLoss | Category::BlessedLoss
+        )
+    }
+}
+
+impl TablebaseResponse {
+    // The move leaving the opponent with the worst result. Wins go for the quickest mate when
+    // the distance is known, otherwise for resetting the fifty move counter as soon as possible.
+    // Losses hold out as long as they can.
+    pub fn get_best_move(&self) -> Option<&TablebaseMove> {
+        self.moves.iter().min_by(|a, b| {
+            a.category
+                .cmp(&b.category)
+                .then_with(|| compare_distances(a, b))
+        })
+    }
+}
+
+// Less means a is the better move, for two moves with the same category
+fn compare_distances(a: &TablebaseMove, b: &TablebaseMove) -> Ordering {
+    let dtm = |mv: &TablebaseMove| mv.dtm.map(i32::abs);
+    let dtz = |mv: &TablebaseMove| mv.dtz.map(i32::abs);
+
+    if a.category.is_loss() {
+        b.checkmate
+            .cmp(&a.checkmate)
+            .then_with(|| dtm(a).unwrap_or(i32::MAX).cmp(&dtm(b).unwrap_or(i32::MAX)))
+            .then_with(|| b.zeroing.cmp(&a.zeroing))
+            .then_with(|| dtz(a).unwrap_or(i32::MAX).cmp(&dtz(b).unwrap_or(i32::MAX)))
+    } else if a.category.is_win() {
+        dtm(b)
+            .cmp(&dtm(a))
+            .then_with(|| a.zeroing.cmp(&b.zeroing))
+            .then_with(|| dtz(b).cmp(&dtz(a)))
+    } else {
+        Ordering::Equal
+    }
+}
+
+pub trait TablebaseProvider: Send + Sync {
+    // The tablebase entry for the position, or None if the tablebase does not cover it
+    fn probe(&self, fen: &str) -> Result<Option<TablebaseResponse>>;
+}
+
+// Lichess keeps the default tablebase server, cached in memory, until something else is set
+pub fn active_provider() -> Arc<dyn TablebaseProvider> {
+    if let Some(provider) = PROVIDER.read().unwrap().as_ref() {
+        return provider.clone();
+    }
+
+    let provider = TablebaseConfig::default()
+        .build()
+        .expect("the default tablebase provider must build");
+    PROVIDER.write().unwrap().get_or_insert(provider).clone()
+}
+
+pub fn set_provider(provider: Arc<dyn TablebaseProvider>) {
+    *PROVIDER.write().unwrap() = Some(provider);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Http,
+    Local,
+    None,
+}
+
+impl TryFrom<&str> for Backend {
+    type Error = anyhow::Error;
+
+    fn try_from(name: &str) -> Result<Self> {
+        match name {
+            "http" => Ok(Backend::Http),
+            "local" => Ok(Backend::Local),
+            "none" => Ok(Backend::None),
+            _ => bail!("unknown tablebase backend: {name}"),
+        }
+    }
+}
+
+// Everything the UCI options can change about the provider, which gets rebuilt on every change
+#[derive(Debug, Clone)]
+pub struct TablebaseConfig {
+    pub backend: Backend,
+    pub url: String,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub cache_directory: Option<PathBuf>,
+}
+
+impl Default for TablebaseConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Http,
+            url: DEFAULT_TABLEBASE_URL.to_string(),
+            timeout: DEFAULT_TABLEBASE_TIMEOUT,
+            retries: DEFAULT_TABLEBASE_RETRIES,
+            cache_directory: None,
+        }
+    }
+}
+
+impl TablebaseConfig {
+    pub fn build(&self) -> Result<Arc<dyn TablebaseProvider>> {
+        let provider: Arc<dyn TablebaseProvider> = match self.backend {
+            Backend::Http => {
+                let http = HttpTablebase::new(&self.url, self.timeout, self.retries)?;
+                Arc::new(CachedTablebase::new(http, self.cache_directory.clone())?)
+            }
+            // Local files are quick to probe, so there is nothing to gain from a cache
+            Backend::Local => match syzygy::active_tablebases() {
+                Some(tablebases) => Arc::new(LocalTablebase::new(tablebases)),
+                None => bail!("the local tablebase backend needs SyzygyPath to be set first"),
+            },
+            Backend::None => Arc::new(NoTablebase),
+        };
+
+        Ok(provider)
+    }
+}
+
+// Anything that answers GET <base url>?fen=<fen> like tablebase.lichess.ovh does
+pub struct HttpTablebase {
+    client: Client,
+    base_url: String,
+    retries: u32,
+}
+
+impl HttpTablebase {
+    pub fn new(base_url: &str, timeout: Duration, retries: u32) -> Result<Self> {
+        let client = Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.to_string(),
+            retries,
+        })
+    }
+
+    fn request(&self, fen: &str) -> Result<TablebaseResponse, RequestError> {
+        // Make FEN URL friendly
+        let params = [("fen", fen.replace(' ', "_"))];
+        let response = self
+            .client
+            .get(&self.base_url)
+            .query(&params)
+            .send()
+            .map_err(|err| RequestError::Retry(err.into()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            response
+                .json()
+                .map_err(|err| RequestError::Fatal(err.into()))
+        } else if status.is_server_error() || status.as_u16() == 429 {
+            Err(RequestError::Retry(anyhow!("tablebase server returned {status}")))
+        } else {
+            Err(RequestError::Fatal(anyhow!("tablebase server returned {status}")))
+        }
+    }
+}
+
+enum RequestError {
+    // Timeouts, dropped connections and server side errors might go away on their own
+    Retry(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl TablebaseProvider for HttpTablebase {
+    fn probe(&self, fen: &str) -> Result<Option<TablebaseResponse>> {
+        let mut attempts_left = self.retries + 1;
+        loop {
+            attempts_left -= 1;
+            match self.request(fen) {
+                Ok(response) => return Ok(Some(response)),
+                Err(RequestError::Retry(_)) if attempts_left > 0 => {}
+                Err(RequestError::Retry(err) | RequestError::Fatal(err)) => {
+                    return Err(err.context("tablebase lookup failed"))
+                }
+            }
+        }
+    }
+}
+
+// Builds the same answer the Lichess API gives out of the local Syzygy files
+pub struct LocalTablebase {
+    tablebases: Arc<Tablebases>,
+}
+
+impl LocalTablebase {
+    pub fn new(tablebases: Arc<Tablebases>) -> Self {
+        Self { tablebases }
+    }
+}
+
+impl TablebaseProvider for LocalTablebase {
+    fn probe(&self, fen: &str) -> Result<Option<TablebaseResponse>> {
+        let mut move_generator = MoveGenerator::new(BoardBuilder::try_from_fen(fen)?);
+        let Some(wdl) = self.tablebases.probe_wdl(&mut move_generator) else {
+            return Ok(None);
+        };
+        let dtz = self.tablebases.probe_dtz(&mut move_generator);
+        let position = position_state(&mut move_generator);
+
+        let mut moves = Vec::new();
+        for mv in move_generator.generate_moves() {
+            let zeroing = syzygy::is_zeroing(&move_generator.board, &mv);
+            move_generator.board.move_piece(&mv);
+            let after_move = position_state(&mut move_generator);
+            let wdl = self.tablebases.probe_wdl(&mut move_generator);
+            let dtz = self.tablebases.probe_dtz(&mut move_generator);
+            move_generator.board.unmake_move(&mv)?;
+
+            // Every position a move leads to is in a table that is loaded, or in a smaller one
+            let Some(wdl) = wdl else {
+                return Ok(None);
+            };
+            moves.push(TablebaseMove {
+                uci: mv.to_string(),
+                san: None,
+                dtz,
+                precise_dtz: dtz,
+                dtm: None,
+                zeroing,
+                checkmate: after_move.checkmate,
+                stalemate: after_move.stalemate,
+                insufficient_material: after_move.insufficient_material,
+                category: Category::from(wdl),
+            });
+        }
+
+        Ok(Some(TablebaseResponse {
+            dtz,
+            precise_dtz: dtz,
+            dtm: None,
+            checkmate: position.checkmate,
+            stalemate: position.stalemate,
+            insufficient_material: position.insufficient_material,
+            category: Category::from(wdl),
+            moves,
+        }))
+    }
+}
+
+struct PositionState {
+    checkmate: bool,
+    stalemate: bool,
+    insufficient_material: bool,
+}
+
+fn position_state(move_generator: &mut MoveGenerator) -> PositionState {
+    let to_move = move_generator.board.to_move;
+    let no_moves = move_generator.generate_moves().is_empty();
+    let in_check = move_generator.is_in_check(to_move);
+    PositionState {
+        checkmate: no_moves && in_check,
+        stalemate: no_moves && !in_check,
+        insufficient_material: move_generator.board.is_insufficient_material(),
+    }
+}
+
+impl From<Wdl> for Category {
+    // Without distance to mate tables a win is only known from the Syzygy tables
+    fn from(wdl: Wdl) -> Self {
+        match wdl {
+            Wdl::Win => Category::SyzygyWin,
+            Wdl::CursedWin => Category::CursedWin,
+            Wdl::Draw => Category::Draw,
+            Wdl::BlessedLoss => Category::BlessedLoss,
+            Wdl::Loss => Category::SyzygyLoss,
+        }
+    }
+}
+
+pub struct NoTablebase;
+
+impl TablebaseProvider for NoTablebase {
+    fn probe(&self, _fen: &str) -> Result<Option<TablebaseResponse>> {
+        Ok(None)
+    }
+}
+
+// Remembers answers by FEN, in memory and optionally as one JSON file per position in a
+// directory, so they survive a restart. Failed lookups are not remembered.
+pub struct CachedTablebase<P: TablebaseProvider> {
+    provider: P,
+    memory: Mutex<HashMap<String, Option<TablebaseResponse>>>,
+    directory: Option<PathBuf>,
+}
+
+impl<P: TablebaseProvider> CachedTablebase<P> {
+    pub fn new(provider: P, directory: Option<PathBuf>) -> Result<Self> {
+        if let Some(directory) = &directory {
+            std::fs::create_dir_all(directory)?;
+        }
+        Ok(Self {
+            provider,
+            memory: Mutex::new(HashMap::new()),
+            directory,
+        })
+    }
+
+    fn cache_file(directory: &Path, fen: &str) -> PathBuf {
+        let name: String = fen
+            .chars()
+            .map(|c| match c {
+                '/' => '-',
+                ' ' => '_',
+                c => c,
+            })
+            .collect();
+        directory.join(format!("{name}.json"))
+    }
+}
+
+impl<P: TablebaseProvider> TablebaseProvider for CachedTablebase<P> {
+    fn probe(&self, fen: &str) -> Result<Option<TablebaseResponse>> {
+        if let Some(response) = self.memory.lock().unwrap().get(fen) {
+            return Ok(response.clone());
+        }
+
+        let cache_file = self
+            .directory
+            .as_deref()
+            .map(|directory| Self::cache_file(directory, fen));
+        // A cache file that cannot be read is treated like a miss and written again
+        let from_disk = cache_file
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok());
+
+        let response = match from_disk {
+            Some(response) => Some(response),
+            None => {
+                let response = self.provider.probe(fen)?;
+                if let (Some(path), Some(response)) = (&cache_file, &response) {
+                    std::fs::write(path, serde_json::to_string(response)?)?;
+                }
+                response
+            }
+        };
+
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(fen.to_string(), response.clone());
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use super::{
+        CachedTablebase, Category, HttpTablebase, LocalTablebase, NoTablebase, TablebaseMove,
+        TablebaseProvider, TablebaseResponse,
+    };
+    use crate::syzygy::tests::test_tablebases;
+
+    const FEN: &str = "8/8/8/8/8/2k5/P7/K7 w - - 0 1";
+    const RESPONSE: &str = r#"{"checkmate":false,"stalemate":false,"insufficient_material":false,
+        "dtz":1,"precise_dtz":1,"dtm":null,"category":"draw","moves":[
+        {"uci":"a1b1","san":"Kb1","zeroing":false,"checkmate":false,"stalemate":false,
+         "insufficient_material":false,"dtz":0,"precise_dtz":0,"dtm":null,"category":"draw"},
+        {"uci":"a2a4","san":"a4","zeroing":true,"checkmate":false,"stalemate":false,
+         "insufficient_material":false,"dtz":-1,"precise_dtz":-1,"dtm":null,"category":"win"}]}"#;
+
+    // Answers one request per response with it, and counts the requests that came in
+    fn stand_in_server(responses: Vec<(u16, &'static str)>) -> Result<(String, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/standard", listener.local_addr()?);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+
+        std::thread::spawn(move || {
+            for (status, body) in responses {
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend(&buffer[..read]),
+                    }
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} Stand In\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+
+        Ok((url, requests))
+    }
+
+    fn http_tablebase(url: &str, retries: u32) -> Result<HttpTablebase> {
+        HttpTablebase::new(url, Duration::from_secs(5), retries)
+    }
+
+    #[test]
+    fn test_http_tablebase_picks_the_best_move() -> Result<()> {
+        let (url, _) = stand_in_server(vec![(200, RESPONSE)])?;
+
+        let response = http_tablebase(&url, 0)?.probe(FEN)?.unwrap();
+
+        assert_eq!(response.category, Category::Draw);
+        assert_eq!(response.moves.len(), 2);
+        // A draw for the opponent beats a win for them
+        assert_eq!(response.get_best_move().unwrap().uci, "a1b1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_tablebase_retries_server_errors() -> Result<()> {
+        let (url, requests) = stand_in_server(vec![(500, ""), (503, ""), (200, RESPONSE)])?;
+
+        assert!(http_tablebase(&url, 2)?.probe(FEN)?.is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_tablebase_gives_up() -> Result<()> {
+        let (url, requests) = stand_in_server(vec![(500, ""), (500, "")])?;
+        assert!(http_tablebase(&url, 1)?.probe(FEN).is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Bad requests are not going to get any better
+        let (url, requests) = stand_in_server(vec![(400, ""), (200, RESPONSE)])?;
+        assert!(http_tablebase(&url, 1)?.probe(FEN).is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_tablebase_times_out() -> Result<()> {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/standard", listener.local_addr()?);
+
+        let tablebase = HttpTablebase::new(&url, Duration::from_millis(200), 0)?;
+
+        assert!(tablebase.probe(FEN).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_avoids_repeated_requests() -> Result<()> {
+        let (url, requests) = stand_in_server(vec![(200, RESPONSE)])?;
+        let directory = std::env::temp_dir().join("talia_tablebase_cache");
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let cached = CachedTablebase::new(http_tablebase(&url, 0)?, Some(directory.clone()))?;
+        assert!(cached.probe(FEN)?.is_some());
+        assert!(cached.probe(FEN)?.is_some());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // A fresh cache over the same directory still knows the answer
+        let from_disk = CachedTablebase::new(NoTablebase, Some(directory))?;
+        let response = from_disk.probe(FEN)?.unwrap();
+        assert_eq!(response.moves[1].san.as_deref(), Some("a4"));
+        Ok(())
+    }
+
+    fn tablebase_move(
+        uci: &str,
+        category: Category,
+        dtz: Option<i32>,
+        dtm: Option<i32>,
+        zeroing: bool,
+    ) -> TablebaseMove {
+        TablebaseMove {
+            uci: uci.to_string(),
+            san: None,
+            dtz,
+            precise_dtz: dtz,
+            dtm,
+            zeroing,
+            checkmate: false,
+            stalemate: false,
+            insufficient_material: false,
+            category,
+        }
+    }
+
+    fn best_move(moves: Vec<TablebaseMove>) -> Option<String> {
+        let response = TablebaseResponse {
+            dtz: None,
+            precise_dtz: None,
+            dtm: None,
+            checkmate: false,
+            stalemate: false,
+            insufficient_material: false,
+            category: Category::Unknown,
+            moves,
+        };
+        response.get_best_move().map(|mv| mv.uci.clone())
+    }
+
+    #[test]
+    fn test_every_category_parses() -> Result<()> {
+        let categories: Vec<Category> = serde_json::from_str(
+            r#"["loss", "syzygy-loss", "maybe-loss", "blessed-loss", "draw", "unknown",
+                "cursed-win", "maybe-win", "syzygy-win", "win"]"#,
+        )?;
+
+        assert_eq!(categories.len(), 10);
+        assert!(categories.windows(2).all(|pair| pair[0] < pair[1]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_move_prefers_the_worst_category_for_the_opponent() {
+        let moves = vec![
+            tablebase_move("a1a2", Category::Win, Some(-5), None, false),
+            tablebase_move("a1b1", Category::CursedWin, Some(-105), None, false),
+            tablebase_move("a1b2", Category::Unknown, None, None, false),
+            tablebase_move("a1a3", Category::BlessedLoss, Some(105), None, false),
+        ];
+
+        assert_eq!(best_move(moves).as_deref(), Some("a1a3"));
+        assert_eq!(best_move(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_best_move_wins_quickly() {
+        // The quickest mate, whatever the distance to zeroing says
+        let moves = vec![
+            tablebase_move("a1a2", Category::Loss, Some(-3), Some(-15), true),
+            tablebase_move("a1b1", Category::Loss, Some(-1), Some(-9), false),
+            tablebase_move("a1b2", Category::Loss, Some(-1), None, false),
+        ];
+        assert_eq!(best_move(moves).as_deref(), Some("a1b1"));
+
+        // Without distances to mate, zeroing the counter comes first
+        let moves = vec![
+            tablebase_move("a1a2", Category::SyzygyLoss, Some(-2), None, false),
+            tablebase_move("a1b1", Category::SyzygyLoss, Some(-20), None, true),
+            tablebase_move("a1b2", Category::SyzygyLoss, Some(-10), None, true),
+        ];
+        assert_eq!(best_move(moves).as_deref(), Some("a1b2"));
+    }
+
+    #[test]
+    fn test_best_move_loses_slowly() {
+        let moves = vec![
+            tablebase_move("a1a2", Category::Win, Some(3), Some(11), false),
+            tablebase_move("a1b1", Category::Win, Some(1), Some(21), false),
+            tablebase_move("a1b2", Category::Win, Some(9), Some(21), true),
+        ];
+        assert_eq!(best_move(moves).as_deref(), Some("a1b1"));
+
+        let moves = vec![
+            tablebase_move("a1a2", Category::SyzygyWin, Some(3), None, false),
+            tablebase_move("a1b1", Category::SyzygyWin, Some(7), None, false),
+        ];
+        assert_eq!(best_move(moves).as_deref(), Some("a1b1"));
+    }
+
+    #[test]
+    fn test_no_tablebase() -> Result<()> {
+        assert!(NoTablebase.probe(FEN)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_tablebase() -> Result<()> {
+        let local = LocalTablebase::new(Arc::new(test_tablebases()?));
+
+        let response = local.probe("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1")?.unwrap();
+
+        assert_eq!(response.category, Category::SyzygyWin);
+        assert_eq!(response.dtz, Some(15));
+        let find = |uci: &str| response.moves.iter().find(|mv| mv.uci == uci).unwrap();
+        // Black takes the queen
+        assert_eq!(find("c1c4").category, Category::Draw);
+        assert_eq!(find("c1c4").dtz, Some(0));
+        // And the best moves keep black lost
+        assert!(response
+            .moves
+            .iter()
+            .any(|mv| mv.category == Category::SyzygyLoss && mv.dtz == Some(-14)));
+        // Not covered by the loaded tables
+        assert!(local.probe("8/8/8/3k4/8/8/8/1RQ1K3 w - - 0 1")?.is_none());
+        Ok(())
+    }
+}
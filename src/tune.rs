@@ -0,0 +1,358 @@
+// Texel tuning of the evaluation weights
+// Source: https://www.chessprogramming.org/Texel%27s_Tuning_Method
+//
+// Every position in the dataset is labelled with the result of the game it was taken from. The
+// evaluation of a position is squashed into an expected result with a sigmoid, and the weights
+// are nudged one at a time for as long as that lowers the mean squared error over the dataset.
+use std::fs;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    board::Board,
+    board_builder::BoardBuilder,
+    eval_params::{eval_params, EvalParams},
+    evaluate::{evaluate, evaluate_with_params},
+    move_generation::{Flag, Move, MoveGenerator},
+};
+
+pub struct TuningPosition {
+    move_generator: MoveGenerator,
+    // From white's point of view: 1 for a win, 0.5 for a draw and 0 for a loss
+    result: f64,
+}
+
+pub struct Tuner {
+    positions: Vec<TuningPosition>,
+    params: EvalParams,
+    // Scales centipawns into the sigmoid, fitted to the starting weights
+    k: f64,
+    error: f64,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TuningPosition>, params: EvalParams) -> Self {
+        let mut tuner = Self {
+            positions,
+            params,
+            k: 1.0,
+            error: 0.0,
+        };
+        tuner.k = tuner.fit_k();
+        tuner.error = tuner.mean_squared_error(&tuner.params);
+        tuner
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+
+    pub fn error(&self) -> f64 {
+        self.error
+    }
+
+    // Tries to move every weight one step up or down, keeping whatever lowers the error.
+    // Returns false once no single step helps any more.
+    pub fn run_iteration(&mut self) -> bool {
+        let mut weights = self.params.weights();
+        let mut candidate = self.params.clone();
+        let mut improved = false;
+
+        for index in 0..weights.len() {
+            for step in [1, -1] {
+                weights[index] += step;
+                candidate.set_weights(&weights);
+
+                let error = self.mean_squared_error(&candidate);
+                if error < self.error {
+                    self.error = error;
+                    improved = true;
+                    break;
+                }
+                weights[index] -= step;
+            }
+        }
+
+        candidate.set_weights(&weights);
+        self.params = candidate;
+        improved
+    }
+
+    fn mean_squared_error(&self, params: &EvalParams) -> f64 {
+        let total: f64 = self
+            .positions
+            .iter()
+            .map(|position| {
+                let eval = evaluate_with_params(&position.move_generator, params);
+                (position.result - sigmoid(eval, self.k)).powi(2)
+            })
+            .sum();
+
+        total / self.positions.len() as f64
+    }
+
+    fn fit_k(&self) -> f64 {
+        let evals: Vec<i32> = self
+            .positions
+            .iter()
+            .map(|position| evaluate_with_params(&position.move_generator, &self.params))
+            .collect();
+        let error = |k: f64| -> f64 {
+            self.positions
+                .iter()
+                .zip(&evals)
+                .map(|(position, &eval)| (position.result - sigmoid(eval, k)).powi(2))
+                .sum()
+        };
+
+        (1..=60)
+            .map(|step| step as f64 * 0.05)
+            .min_by(|&a, &b| error(a).total_cmp(&error(b)))
+            .unwrap()
+    }
+}
+
+// The expected result for white given an evaluation in centipawns
+pub fn sigmoid(eval: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0))
+}
+
+pub fn run_tuning(dataset_path: &str, output_path: &str, iterations: u32) -> Result<()> {
+    let positions = load_dataset(dataset_path)?;
+    if positions.is_empty() {
+        bail!("no positions found in {dataset_path}");
+    }
+    println!("Loaded {} positions", positions.len());
+
+    // Carries on from a loaded parameter file, if there is one
+    let starting_params = EvalParams::clone(&eval_params());
+    let mut tuner = Tuner::new(positions, starting_params);
+    println!("K: {:.2}, starting error: {:.6}", tuner.k(), tuner.error());
+
+    for iteration in 1..=iterations {
+        let improved = tuner.run_iteration();
+        // Written after every iteration so a long run can be stopped at any point
+        fs::write(output_path, serde_json::to_string_pretty(tuner.params())?)?;
+        println!("Iteration {iteration}: error {:.6}", tuner.error());
+
+        if !improved {
+            println!("No weight can be improved any further");
+            break;
+        }
+    }
+    println!("Tuned weights written to {output_path}");
+
+    Ok(())
+}
+
+pub fn load_dataset(path: &str) -> Result<Vec<TuningPosition>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| anyhow!("could not read dataset {path}: {e}"))?;
+    let mut positions = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (board, result) =
+            parse_dataset_line(line).map_err(|e| anyhow!("line {}: {e}", line_number + 1))?;
+        let mut move_generator = MoveGenerator::new(board);
+        let quiet_board = quiet_position(&mut move_generator);
+        positions.push(TuningPosition {
+            move_generator: MoveGenerator::new(quiet_board),
+            result,
+        });
+    }
+
+    Ok(positions)
+}
+
+// Accepts a FEN or EPD position followed by the game result in any of the usual forms:
+// '[1.0]', '[0.5]', '"1-0";', '1/2-1/2' or 'c9 "0-1";'
+pub fn parse_dataset_line(line: &str) -> Result<(Board, f64)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 5 {
+        bail!("expected a position followed by a result");
+    }
+
+    // EPD positions come without the move clocks
+    let has_clocks =
+        fields.len() >= 6 && fields[4].parse::<u32>().is_ok() && fields[5].parse::<u32>().is_ok();
+    let fen = if has_clocks {
+        fields[..6].join(" ")
+    } else {
+        format!("{} 0 1", fields[..4].join(" "))
+    };
+    let board = BoardBuilder::try_from_fen(&fen)?;
+
+    let rest = if has_clocks {
+        &fields[6..]
+    } else {
+        &fields[4..]
+    };
+    let result = rest
+        .iter()
+        .rev()
+        .find_map(|field| parse_result(field))
+        .ok_or(anyhow!("could not find a game result"))?;
+
+    Ok((board, result))
+}
+
+fn parse_result(field: &str) -> Option<f64> {
+    let field = field.trim_end_matches(';');
+    let is_labelled = (field.starts_with('[') && field.ends_with(']'))
+        || (field.starts_with('"') && field.ends_with('"'));
+    let label = field.trim_matches(|c| c == '[' || c == ']' || c == '"');
+
+    match label {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        // Bare numbers could just as well be move clocks
+        _ if is_labelled => label
+            .parse::<f64>()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result)),
+        _ => None,
+    }
+}
+
+// Plays out the captures quiescence search expects, so tuning sees the quiet position at the end
+// of an exchange instead of a position with a piece hanging
+fn quiet_position(move_generator: &mut MoveGenerator) -> Board {
+    let (_, line) = resolve_captures(move_generator, -i32::MAX, i32::MAX);
+    let mut board = move_generator.board.clone();
+    for mv in &line {
+        board.move_piece(mv);
+    }
+
+    board
+}
+
+fn resolve_captures(move_generator: &mut MoveGenerator, alpha: i32, beta: i32) -> (i32, Vec<Move>) {
+    let eval = evaluate(move_generator);
+    if eval >= beta {
+        return (beta, Vec::new());
+    }
+
+    let mut alpha = std::cmp::max(alpha, eval);
+    let mut best_line = Vec::new();
+    let capture_moves: Vec<Move> = move_generator
+        .generate_moves()
+        .into_iter()
+        .filter(|mv| {
+            matches!(
+                mv.flag,
+                Flag::EnPassantCapture | Flag::Capture(_) | Flag::CaptureWithPromotion(_, _)
+            )
+        })
+        .collect();
+
+    for mv in capture_moves {
+        move_generator.board.move_piece(&mv);
+        let (eval, line) = resolve_captures(move_generator, -beta, -alpha);
+        move_generator.board.unmake_move(&mv).unwrap();
+
+        let eval = -eval;
+        if eval >= beta {
+            return (beta, Vec::new());
+        }
+        if eval > alpha {
+            alpha = eval;
+            best_line = std::iter::once(mv).chain(line).collect();
+        }
+    }
+
+    (alpha, best_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board_builder::BoardBuilder,
+        eval_params::EvalParams,
+        move_generation::MoveGenerator,
+        piece::{Color, Piece},
+        square::Square,
+    };
+    use anyhow::Result;
+
+    use super::{parse_dataset_line, quiet_position, sigmoid, Tuner, TuningPosition};
+
+    #[test]
+    fn test_parse_dataset_line_formats() -> Result<()> {
+        let lines = [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1 [1.0]", 1.0),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - [0.5]", 0.5),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 3 40 \"0-1\";", 0.0),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - c9 \"1/2-1/2\";", 0.5),
+        ];
+
+        for (line, expected_result) in lines {
+            let (board, result) = parse_dataset_line(line)?;
+            assert_eq!(board.to_fen().split(' ').next(), Some("4k3/8/8/8/8/8/8/3QK3"));
+            assert_eq!(result, expected_result);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_dataset_line_without_result() {
+        assert!(parse_dataset_line("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_sigmoid() {
+        assert_eq!(sigmoid(0, 1.0), 0.5);
+        assert!(sigmoid(400, 1.0) > 0.9);
+        assert!(sigmoid(-400, 1.0) < 0.1);
+    }
+
+    #[test]
+    fn test_quiet_position_resolves_hanging_piece() -> Result<()> {
+        // The black queen can simply be taken
+        let board = BoardBuilder::try_from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1")?;
+        let quiet_board = quiet_position(&mut MoveGenerator::new(board));
+
+        assert!(quiet_board.is_piece_at_square(Square::D5.as_index(), Piece::Rook, Color::White));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tuning_lowers_error() -> Result<()> {
+        // White's extra knight only ever draws, so the weights have to make it worth less
+        let labelled_positions = [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", 1.0),
+            ("3qk3/8/8/8/8/8/8/4K3 w - - 0 1", 0.0),
+            ("4k3/pppp4/8/8/8/8/PPPP4/1N2K3 w - - 0 1", 0.5),
+            ("1n2k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1", 0.5),
+        ];
+        let positions = labelled_positions
+            .iter()
+            .map(|(fen, result)| {
+                Ok(TuningPosition {
+                    move_generator: MoveGenerator::new(BoardBuilder::try_from_fen(fen)?),
+                    result: *result,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tuner = Tuner::new(positions, EvalParams::default());
+        let starting_error = tuner.error();
+
+        assert!(tuner.run_iteration());
+        assert!(tuner.error() < starting_error);
+        assert_ne!(*tuner.params(), EvalParams::default());
+
+        Ok(())
+    }
+}
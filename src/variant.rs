@@ -0,0 +1,187 @@
+// The rules Talia can play by besides standard chess. Move generation, the game end checks of
+// the board and the evaluation all ask the variant of the board what to do. Chess960 only
+// changes where the pieces start, so it stays a flag on the board that mixes with any of these.
+use anyhow::{bail, Result};
+
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::Square,
+};
+
+// The squares the king has to reach in King of the Hill
+pub const HILL_SQUARES: [Square; 4] = [Square::D4, Square::E4, Square::D5, Square::E5];
+pub const CHECKS_TO_WIN: u8 = 3;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // Captured pieces change sides and can be dropped back on the board
+    Crazyhouse,
+    // Getting the king to the centre wins
+    KingOfTheHill,
+    // Checking the opponent three times wins
+    ThreeCheck,
+    // Captures blow up the capturing piece and everything but pawns around the target square
+    Atomic,
+}
+
+// Names as used by UCI_Variant
+impl TryFrom<&str> for Variant {
+    type Error = anyhow::Error;
+
+    fn try_from(name: &str) -> Result<Self> {
+        match name {
+            "chess" => Ok(Variant::Standard),
+            "crazyhouse" => Ok(Variant::Crazyhouse),
+            "kingofthehill" => Ok(Variant::KingOfTheHill),
+            "3check" => Ok(Variant::ThreeCheck),
+            "atomic" => Ok(Variant::Atomic),
+            _ => bail!("unknown variant: {name}"),
+        }
+    }
+}
+
+impl Variant {
+    // As written in the Variant tag of a PGN
+    pub fn pgn_name(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Atomic => "Atomic",
+        }
+    }
+
+    pub fn from_pgn_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "standard" | "from position" => Some(Variant::Standard),
+            "crazyhouse" => Some(Variant::Crazyhouse),
+            "king of the hill" | "kingofthehill" => Some(Variant::KingOfTheHill),
+            "three-check" | "threecheck" | "3check" => Some(Variant::ThreeCheck),
+            "atomic" => Some(Variant::Atomic),
+            _ => None,
+        }
+    }
+
+    pub fn has_drops(self) -> bool {
+        self == Variant::Crazyhouse
+    }
+
+    // Tablebases, the KPK bitbase, the specialised endgame evaluations and the opening book
+    // only know standard chess
+    pub fn is_standard(self) -> bool {
+        self == Variant::Standard
+    }
+
+    // Whether the side to move has already lost by a rule of the variant. There are no legal
+    // moves left then, but unlike stalemate it is no draw.
+    pub fn is_lost(self, board: &Board) -> bool {
+        let opponent = board.to_move.opposite_color();
+        match self {
+            Variant::KingOfTheHill => HILL_SQUARES
+                .iter()
+                .any(|square| board.is_piece_at_square(square.as_index(), Piece::King, opponent)),
+            Variant::ThreeCheck => checks_given(board, opponent) >= CHECKS_TO_WIN,
+            // The king was caught in an explosion
+            Variant::Atomic => board.king_square(board.to_move).is_none(),
+            Variant::Standard | Variant::Crazyhouse => false,
+        }
+    }
+}
+
+pub fn checks_given(board: &Board, color: Color) -> u8 {
+    board.board_state.checks_given[color as usize]
+}
+
+// The up to eight squares around a square, which is as far as an atomic explosion reaches
+pub fn adjacent_squares(square: usize) -> impl Iterator<Item = usize> {
+    let (file, rank) = ((square % 8) as isize, (square / 8) as isize);
+    (-1..=1)
+        .flat_map(move |rank_offset| (-1..=1).map(move |file_offset| (file_offset, rank_offset)))
+        .filter(|&offsets| offsets != (0, 0))
+        .map(move |(file_offset, rank_offset)| (file + file_offset, rank + rank_offset))
+        .filter(|&(file, rank)| (0..8).contains(&file) && (0..8).contains(&rank))
+        .map(|(file, rank)| (rank * 8 + file) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move, MoveGenerator},
+        piece::Color,
+        square::Square::*,
+    };
+    use anyhow::Result;
+
+    use super::{checks_given, Variant};
+
+    #[test]
+    fn test_variant_names() -> Result<()> {
+        assert_eq!(Variant::try_from("kingofthehill")?, Variant::KingOfTheHill);
+        assert_eq!(Variant::try_from("3check")?, Variant::ThreeCheck);
+        assert_eq!(Variant::try_from("atomic")?, Variant::Atomic);
+        assert!(Variant::try_from("atomic960").is_err());
+
+        assert_eq!(
+            Variant::from_pgn_name("King of the Hill"),
+            Some(Variant::KingOfTheHill)
+        );
+        assert_eq!(Variant::from_pgn_name("three-check"), Some(Variant::ThreeCheck));
+        assert_eq!(Variant::from_pgn_name("From Position"), Some(Variant::Standard));
+        Ok(())
+    }
+
+    #[test]
+    fn test_king_of_the_hill() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen("4k3/8/8/8/8/3K4/8/8 w - - 0 1")?;
+        board.variant = Variant::KingOfTheHill;
+        let mut move_generator = MoveGenerator::new(board);
+
+        // Both king moves to the hill end the game, so black can't answer them
+        assert_eq!(move_generator.perft_test(1), 8);
+        assert_eq!(move_generator.perft_test(2), 6 * 5);
+
+        move_generator
+            .board
+            .move_piece(&Move::from_square(D3, E4, Flag::None));
+        assert!(move_generator.board.variant.is_lost(&move_generator.board));
+        assert!(move_generator.generate_moves().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_three_check() -> Result<()> {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0";
+        let mut board = BoardBuilder::try_from_fen(fen)?;
+        assert_eq!(board.variant, Variant::ThreeCheck);
+        assert_eq!(board.to_fen(), fen);
+
+        // Ra8+ is the third check, which leaves black without the three king moves it would
+        // have in standard chess
+        let standard = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1")?;
+        assert_eq!(
+            MoveGenerator::new(board.clone()).perft_test(2),
+            MoveGenerator::new(standard).perft_test(2) - 3
+        );
+
+        let check = Move::from_square(A1, A8, Flag::None);
+        board.move_piece(&check);
+        assert_eq!(checks_given(&board, Color::White), 3);
+        assert!(board.variant.is_lost(&board));
+        assert_eq!(board.to_fen(), "R3k3/8/8/8/8/8/8/4K3 b - - 1 1 +3+0");
+        board.unmake_move(&check)?;
+        assert_eq!(board.to_fen(), fen);
+
+        assert!(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +4+0").is_err());
+        assert!(BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 2+0").is_err());
+        assert!(!Board::starting_position()
+            .variant
+            .is_lost(&Board::starting_position()));
+        Ok(())
+    }
+}
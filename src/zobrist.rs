@@ -0,0 +1,243 @@
+// Zobrist hashing, used to recognise positions that have been seen before
+// Source: https://www.chessprogramming.org/Zobrist_Hashing
+use crate::board::{Board, BoardState, POCKET_PIECES};
+use crate::piece::{Color, Piece};
+
+// Keys are generated at compile time so they are identical on every run
+const fn generate_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        // xorshift64*
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        keys[i] = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        i += 1;
+    }
+    keys
+}
+
+// Indexed by color * 384 + piece * 64 + square
+const PIECE_KEYS: [u64; 768] = generate_keys(0x1D87_2B41_E5A3_96F1);
+// White kingside, white queenside, black kingside, black queenside
+const CASTLING_KEYS: [u64; 4] = generate_keys(0x5C1B_77E0_0A3D_42C9);
+// Indexed by the file of the en passant square
+const EN_PASSANT_KEYS: [u64; 8] = generate_keys(0x93E4_0F6B_D218_7A55);
+// Indexed by color * 85 + piece * 17 + the number of those pieces in the pocket
+const POCKET_KEYS: [u64; 170] = generate_keys(0xB6D0_3E8A_7F15_C249);
+// Indexed by color * 4 + the number of checks given in Three-check
+const CHECKS_GIVEN_KEYS: [u64; 8] = generate_keys(0x7A41_D29C_0E8B_53F6);
+const BLACK_TO_MOVE_KEY: u64 = generate_keys::<1>(0x4F2A_C8D3_61B9_0E17)[0];
+
+pub fn piece_key(piece: Piece, color: Color, square: usize) -> u64 {
+    PIECE_KEYS[color as usize * 384 + piece as usize * 64 + square]
+}
+
+pub fn side_to_move_key() -> u64 {
+    BLACK_TO_MOVE_KEY
+}
+
+// All the castling rights still held, hashed together
+pub fn castling_key(board_state: &BoardState) -> u64 {
+    let castling_priviledges = [
+        board_state.white_kingside_castling_priviledge,
+        board_state.white_queenside_castling_priviledge,
+        board_state.black_kingside_castling_priviledge,
+        board_state.black_queenside_castling_priviledge,
+    ];
+    CASTLING_KEYS
+        .iter()
+        .zip(castling_priviledges)
+        .filter(|(_, can_castle)| *can_castle)
+        .fold(0, |key, (castling_key, _)| key ^ castling_key)
+}
+
+pub fn en_passant_key(square: usize) -> u64 {
+    EN_PASSANT_KEYS[square % 8]
+}
+
+// Zero checks and empty pockets don't change the key, so standard positions hash the same
+// no matter the variant
+pub fn checks_given_key(color: Color, checks: u8) -> u64 {
+    match checks {
+        0 => 0,
+        _ => CHECKS_GIVEN_KEYS[color as usize * 4 + checks.min(3) as usize],
+    }
+}
+
+pub fn pocket_key(color: Color, piece: Piece, count: u8) -> u64 {
+    match count {
+        0 => 0,
+        _ => POCKET_KEYS[color as usize * 85 + piece as usize * 17 + count.min(16) as usize],
+    }
+}
+
+// Board keeps its keys up to date move by move, this is for setting up a new position and
+// for checking the incremental keys in debug builds
+pub fn compute_zobrist_key(board: &Board) -> u64 {
+    let mut key = 0;
+
+    for square in 0..64 {
+        if let (Some(piece), Some(color)) = (board.squares[square], board.colors[square]) {
+            key ^= piece_key(piece, color, square);
+        }
+    }
+
+    if board.to_move == Color::Black {
+        key ^= BLACK_TO_MOVE_KEY;
+    }
+
+    key ^= castling_key(&board.board_state);
+
+    if let Some(square) = board.board_state.en_passant_square {
+        key ^= en_passant_key(square);
+    }
+
+    for color in [Color::White, Color::Black] {
+        key ^= checks_given_key(color, board.board_state.checks_given[color as usize]);
+    }
+
+    // The same pieces on the board with different pockets are different crazyhouse positions
+    for color in [Color::White, Color::Black] {
+        for piece in POCKET_PIECES {
+            key ^= pocket_key(color, piece, board.pockets.count(color, piece));
+        }
+    }
+
+    key
+}
+
+pub fn compute_pawn_key(board: &Board) -> u64 {
+    let mut key = 0;
+
+    for square in 0..64 {
+        if let (Some(Piece::Pawn), Some(color)) = (board.squares[square], board.colors[square]) {
+            key ^= piece_key(Piece::Pawn, color, square);
+        }
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        board_builder::BoardBuilder,
+        move_generation::{Flag, Move},
+        piece::Piece::*,
+        square::Square::*,
+    };
+    use anyhow::Result;
+
+    use super::{compute_pawn_key, compute_zobrist_key};
+
+    #[test]
+    fn test_empty_board_key_is_zero() {
+        assert_eq!(compute_zobrist_key(&Board::default()), 0);
+    }
+
+    #[test]
+    fn test_transposition_has_same_key() -> Result<()> {
+        let board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(G1, F3, Flag::None))
+            .make_move(Move::from_square(G8, F6, Flag::None))
+            .make_move(Move::from_square(B1, C3, Flag::None))
+            .try_into()?;
+        let transposed_board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(B1, C3, Flag::None))
+            .make_move(Move::from_square(G8, F6, Flag::None))
+            .make_move(Move::from_square(G1, F3, Flag::None))
+            .try_into()?;
+
+        assert_eq!(
+            board.board_state.zobrist_key,
+            transposed_board.board_state.zobrist_key
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_side_to_move_changes_key() -> Result<()> {
+        let white_to_move = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1")?;
+        let black_to_move = BoardBuilder::try_from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1")?;
+
+        assert_ne!(
+            white_to_move.board_state.zobrist_key,
+            black_to_move.board_state.zobrist_key
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pockets_change_key() -> Result<()> {
+        let keys: Vec<u64> = ["[]", "[P]", "[PP]", "[p]"]
+            .iter()
+            .map(|pockets| {
+                let fen = format!("4k3/8/8/8/8/8/8/4K3{pockets} w - - 0 1");
+                Ok(BoardBuilder::try_from_fen(&fen)?.board_state.zobrist_key)
+            })
+            .collect::<Result<_>>()?;
+
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_key_matches_full_recompute() -> Result<()> {
+        let mut board = BoardBuilder::try_from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )?;
+        let starting_key = board.board_state.zobrist_key;
+        // Castling, a double push, en passant, a promotion and a rook capture that takes castling
+        // rights
+        let moves = [
+            Move::from_square(E1, G1, Flag::KingsideCastle),
+            Move::from_square(C7, C5, Flag::PawnDoublePush),
+            Move::from_square(D5, C6, Flag::EnPassantCapture),
+            Move::from_square(E7, D8, Flag::None),
+            Move::from_square(F3, F6, Flag::Capture(Knight)),
+            Move::from_square(H3, G2, Flag::Capture(Pawn)),
+            Move::from_square(F6, G7, Flag::Capture(Bishop)),
+            Move::from_square(G2, F1, Flag::CaptureWithPromotion(Rook, Queen)),
+            Move::from_square(G7, H8, Flag::Capture(Rook)),
+        ];
+
+        for mv in &moves {
+            board.move_piece(mv);
+            assert_eq!(board.board_state.zobrist_key, compute_zobrist_key(&board));
+            assert_eq!(board.board_state.pawn_key, compute_pawn_key(&board));
+        }
+        for mv in moves.iter().rev() {
+            board.unmake_move(mv)?;
+        }
+        assert_eq!(board.board_state.zobrist_key, starting_key);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pawn_key_ignores_pieces() -> Result<()> {
+        let board: Board = BoardBuilder::from_starting_position()
+            .make_move(Move::from_square(G1, F3, Flag::None))
+            .try_into()?;
+
+        assert_eq!(
+            compute_pawn_key(&board),
+            compute_pawn_key(&Board::starting_position())
+        );
+        assert_ne!(
+            board.board_state.zobrist_key,
+            Board::starting_position().board_state.zobrist_key
+        );
+
+        Ok(())
+    }
+}
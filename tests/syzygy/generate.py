@@ -0,0 +1,745 @@
+#!/usr/bin/env python3
+# Regenerates the Syzygy tables in this directory: run it from anywhere with `python3
+# tests/syzygy/generate.py` and it overwrites the .rtbw and .rtbz files next to it.
+#
+# Every ending is solved by retrograde analysis with its own move generation, independent of
+# the engine, and written in the Syzygy format that src/syzygy.rs reads:
+# Source: https://www.chessprogramming.org/Syzygy_Bases, and the probing code in Stockfish
+# (src/syzygy/tbprobe.cpp) for the layout.
+#
+# The tables are laid out with white as the side that has the extra piece, like the official
+# ones. Values are compressed with recursive pairing and a canonical Huffman code, split into
+# blocks with a sparse index pointing into them. Only the index and compression scheme match
+# the official tables, the choices of the official generator (which values to store for
+# positions that can't happen, how far to pair) are not reproduced, so the files differ byte
+# for byte while probing the same results.
+import heapq
+import os
+import struct
+from collections import Counter, deque
+
+WDL_MAGIC = bytes([0x71, 0xE8, 0x23, 0x5D])
+DTZ_MAGIC = bytes([0xD7, 0x66, 0x0C, 0xA5])
+
+# Stockfish piece codes, black adds 8
+PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING = 1, 2, 3, 4, 5, 6
+BLACK_KING = KING + 8
+
+WHITE, BLACK = 0, 1
+WIN, DRAW, LOSS = 2, 0, -2
+
+# Flags of a compressed table
+STM_FLAG = 1
+WIN_PLIES_FLAG = 4
+LOSS_PLIES_FLAG = 8
+SINGLE_VALUE_FLAG = 128
+
+BLOCK_SIZE_LOG = 6
+SPAN_LOG = 8
+MAX_SYMBOLS = 4095
+# A symbol can stand for at most 256 values, its length is stored in a byte
+MAX_SYMBOL_VALUES = 256
+
+PIECE_NAMES = {"Q": QUEEN, "R": ROOK, "B": BISHOP, "N": KNIGHT, "P": PAWN}
+
+
+def rank(square):
+    return square // 8
+
+
+def file(square):
+    return square % 8
+
+
+def off_a1h8(square):
+    return rank(square) - file(square)
+
+
+def distance(a, b):
+    return max(abs(rank(a) - rank(b)), abs(file(a) - file(b)))
+
+
+# Index tables, the same as Indices in src/syzygy.rs
+
+
+def binomial_table():
+    binomial = [[0] * 64 for _ in range(7)]
+    binomial[0][0] = 1
+    for n in range(1, 64):
+        for k in range(min(7, n + 1)):
+            with_n = binomial[k - 1][n - 1] if k > 0 else 0
+            without_n = binomial[k][n - 1] if k < n else 0
+            binomial[k][n] = with_n + without_n
+    return binomial
+
+
+BINOMIAL = binomial_table()
+
+MAP_B1H1H7 = [0] * 64
+_code = 0
+for _square in range(64):
+    if off_a1h8(_square) < 0:
+        MAP_B1H1H7[_square] = _code
+        _code += 1
+
+MAP_A1D1D4 = [0] * 64
+_code = 0
+_diagonal = []
+for _square in range(28):
+    if off_a1h8(_square) < 0 and file(_square) <= 3:
+        MAP_A1D1D4[_square] = _code
+        _code += 1
+    elif off_a1h8(_square) == 0 and file(_square) <= 3:
+        _diagonal.append(_square)
+for _square in _diagonal:
+    MAP_A1D1D4[_square] = _code
+    _code += 1
+
+MAP_PAWNS = [0] * 64
+LEAD_PAWN_IDX = [0] * 64
+_available = 47
+for _file in range(4):
+    _idx = 0
+    for _rank in range(1, 7):
+        _square = _rank * 8 + _file
+        MAP_PAWNS[_square] = _available
+        MAP_PAWNS[_square ^ 7] = _available - 1
+        _available -= 2
+        LEAD_PAWN_IDX[_square] = _idx
+        _idx += 1
+# A single leading pawn has six squares on every file
+LEAD_PAWNS_SIZE = 6
+
+
+def encode_leading_pieces(squares):
+    # The three unique pieces of a table without pawns, see encode_leading_pieces in
+    # src/syzygy.rs
+    s0, s1, s2 = squares
+    adjust1 = int(s1 > s0)
+    adjust2 = int(s2 > s0) + int(s2 > s1)
+    if off_a1h8(s0) != 0:
+        return (MAP_A1D1D4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+    if off_a1h8(s1) != 0:
+        return (6 * 63 + rank(s0) * 28 + MAP_B1H1H7[s1]) * 62 + s2 - adjust2
+    if off_a1h8(s2) != 0:
+        return (
+            6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(s0) * 7 * 28
+            + (rank(s1) - adjust1) * 28
+            + MAP_B1H1H7[s2]
+        )
+    return (
+        6 * 63 * 62
+        + 4 * 28 * 62
+        + 4 * 7 * 28
+        + rank(s0) * 7 * 6
+        + (rank(s1) - adjust1) * 6
+        + (rank(s2) - adjust2)
+    )
+
+
+class Table:
+    # One three piece ending: the white king, one more white piece and the black king
+
+    def __init__(self, piece):
+        self.piece = piece
+        self.has_pawns = piece == PAWN
+        # The order the pieces are encoded in: the leading pawn first, or the kings around the
+        # extra piece without pawns
+        if self.has_pawns:
+            self.order = [PAWN, KING, BLACK_KING]
+            self.files = 4
+            self.size = LEAD_PAWNS_SIZE * 63 * 62
+        else:
+            self.order = [KING, piece, BLACK_KING]
+            self.files = 1
+            self.size = 31332
+
+    def name(self):
+        letter = next(name for name, piece in PIECE_NAMES.items() if piece == self.piece)
+        return f"K{letter}vK"
+
+    def encode(self, white_king, piece, black_king):
+        # Returns the file of the leading pawn and the index of the position, see Table::probe
+        # in src/syzygy.rs
+        by_code = {KING: white_king, self.piece: piece, BLACK_KING: black_king}
+        squares = [by_code[code] for code in self.order]
+
+        if squares[0] % 8 > 3:
+            squares = [square ^ 7 for square in squares]
+
+        if self.has_pawns:
+            lead_file = file(squares[0])
+            idx = LEAD_PAWN_IDX[squares[0]]
+            group_idx = LEAD_PAWNS_SIZE
+            for i in (1, 2):
+                adjust = sum(1 for square in squares[:i] if squares[i] > square)
+                idx += (squares[i] - adjust) * group_idx
+                group_idx *= 64 - i
+            return lead_file, idx
+
+        if rank(squares[0]) > 3:
+            squares = [square ^ 56 for square in squares]
+        for square in squares:
+            if off_a1h8(square) == 0:
+                continue
+            if off_a1h8(square) > 0:
+                squares = [((s >> 3) | (s << 3)) & 63 for s in squares]
+            break
+        return 0, encode_leading_pieces(squares)
+
+
+# Move generation for the three pieces, from scratch rather than through the engine
+
+KING_STEPS = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)]
+KNIGHT_STEPS = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)]
+ROOK_DIRECTIONS = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+BISHOP_DIRECTIONS = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+
+
+def step(square, d_rank, d_file):
+    new_rank, new_file = rank(square) + d_rank, file(square) + d_file
+    if 0 <= new_rank < 8 and 0 <= new_file < 8:
+        return new_rank * 8 + new_file
+    return None
+
+
+def piece_targets(piece, square, occupied):
+    # The squares a white piece attacks, sliding pieces stop at the first occupied square
+    if piece == PAWN:
+        return [t for t in (step(square, 1, -1), step(square, 1, 1)) if t is not None]
+    if piece in (KING, KNIGHT):
+        steps = KING_STEPS if piece == KING else KNIGHT_STEPS
+        return [t for t in (step(square, *s) for s in steps) if t is not None]
+
+    directions = {
+        ROOK: ROOK_DIRECTIONS,
+        BISHOP: BISHOP_DIRECTIONS,
+        QUEEN: ROOK_DIRECTIONS + BISHOP_DIRECTIONS,
+    }[piece]
+    targets = []
+    for direction in directions:
+        target = step(square, *direction)
+        while target is not None:
+            targets.append(target)
+            if target in occupied:
+                break
+            target = step(target, *direction)
+    return targets
+
+
+class Position:
+    __slots__ = ("to_move", "white_king", "piece", "black_king")
+
+    def __init__(self, to_move, white_king, piece, black_king):
+        self.to_move = to_move
+        self.white_king = white_king
+        self.piece = piece
+        self.black_king = black_king
+
+
+def black_in_check(table, white_king, piece, black_king):
+    if piece is None:
+        return False
+    occupied = {white_king, black_king}
+    return black_king in piece_targets(table.piece, piece, occupied)
+
+
+def is_legal(table, position):
+    squares = {position.white_king, position.piece, position.black_king}
+    if len(squares) != 3 or distance(position.white_king, position.black_king) <= 1:
+        return False
+    if table.has_pawns and rank(position.piece) in (0, 7):
+        return False
+    # The side that just moved can't have left the other king in check
+    return position.to_move == BLACK or not black_in_check(
+        table, position.white_king, position.piece, position.black_king
+    )
+
+
+def generate_moves(table, position):
+    # Yields (position after the move, whether it zeroes the fifty move counter, the promoted
+    # piece if any). A captured piece leaves a bare king position, given as None.
+    wk, piece, bk = position.white_king, position.piece, position.black_king
+    if position.to_move == WHITE:
+        for target in piece_targets(KING, wk, set()):
+            if target != piece and distance(target, bk) > 1:
+                yield Position(BLACK, target, piece, bk), False, None
+        if table.has_pawns:
+            yield from pawn_pushes(position)
+            return
+        for target in piece_targets(table.piece, piece, {wk, bk}):
+            if target not in (wk, bk):
+                yield Position(BLACK, wk, target, bk), False, None
+    else:
+        for target in piece_targets(KING, bk, set()):
+            if distance(target, wk) <= 1:
+                continue
+            if target == piece:
+                yield None, True, None
+            elif target in piece_targets(table.piece, piece, {wk}):
+                continue
+            else:
+                yield Position(WHITE, wk, piece, target), False, None
+
+
+def pawn_pushes(position):
+    wk, pawn, bk = position.white_king, position.piece, position.black_king
+    target = pawn + 8
+    if target in (wk, bk):
+        return
+    if rank(target) == 7:
+        for promotion in (QUEEN, ROOK, BISHOP, KNIGHT):
+            yield Position(BLACK, wk, target, bk), True, promotion
+        return
+    yield Position(BLACK, wk, target, bk), True, None
+    if rank(pawn) == 1 and target + 8 not in (wk, bk):
+        yield Position(BLACK, wk, target + 8, bk), True, None
+
+
+class Solution:
+    # Win/draw/loss and distance to zeroing of every position of a table, by side to move, file
+    # of the leading pawn and index
+
+    def __init__(self, table, solved):
+        self.table = table
+        self.solved = solved
+        self.nodes = {}
+        self.wdl = {}
+        self.dtz = {}
+
+    def key(self, position):
+        lead_file, idx = self.table.encode(
+            position.white_king, position.piece, position.black_king
+        )
+        return position.to_move, lead_file, idx
+
+    def enumerate_positions(self):
+        for to_move in (WHITE, BLACK):
+            for wk in range(64):
+                for piece in range(64):
+                    for bk in range(64):
+                        position = Position(to_move, wk, piece, bk)
+                        if is_legal(self.table, position):
+                            self.nodes.setdefault(self.key(position), position)
+
+    def external_wdl(self, position, promotion):
+        # The result for the side to move after a capture, which leaves the bare kings, or a
+        # promotion, which leads into another table
+        if position is None:
+            return DRAW
+        promoted = self.solved[promotion]
+        return promoted.wdl[promoted.key(position)]
+
+    def solve(self):
+        self.enumerate_positions()
+
+        # Moves within the table, and the best result already known from the ones leaving it
+        children = {}
+        zeroing_children = {}
+        external_best = {}
+        in_check = {}
+        for key, position in self.nodes.items():
+            internal = []
+            zeroing = []
+            best = None
+            for child, is_zeroing, promotion in generate_moves(self.table, position):
+                if child is None or promotion is not None:
+                    value = -self.external_wdl(child, promotion)
+                    best = value if best is None else max(best, value)
+                elif is_zeroing:
+                    zeroing.append(self.key(child))
+                else:
+                    internal.append(self.key(child))
+            children[key] = internal
+            zeroing_children[key] = zeroing
+            external_best[key] = best
+            in_check[key] = position.to_move == BLACK and black_in_check(
+                self.table, position.white_king, position.piece, position.black_king
+            )
+
+        self.solve_wdl(children, zeroing_children, external_best, in_check)
+        self.solve_dtz(children, zeroing_children, external_best)
+
+    def solve_wdl(self, children, zeroing_children, external_best, in_check):
+        parents = {key: [] for key in self.nodes}
+        unresolved = {}
+        for key in self.nodes:
+            moves = children[key] + zeroing_children[key]
+            unresolved[key] = len(moves)
+            for child in moves:
+                parents[child].append(key)
+
+        queue = deque()
+        for key in self.nodes:
+            has_moves = unresolved[key] > 0 or external_best[key] is not None
+            if not has_moves:
+                self.wdl[key] = LOSS if in_check[key] else DRAW
+                queue.append(key)
+            elif external_best[key] == WIN:
+                self.wdl[key] = WIN
+                queue.append(key)
+            elif unresolved[key] == 0:
+                self.wdl[key] = external_best[key]
+                queue.append(key)
+
+        while queue:
+            key = queue.popleft()
+            # A drawn move keeps the parent from losing, which it already does by staying
+            # unresolved
+            if self.wdl[key] == DRAW:
+                continue
+            for parent in parents[key]:
+                if parent in self.wdl:
+                    continue
+                if self.wdl[key] == LOSS:
+                    self.wdl[parent] = WIN
+                    queue.append(parent)
+                    continue
+                unresolved[parent] -= 1
+                if unresolved[parent] == 0:
+                    # Every move within the table loses, the ones leaving it may still draw
+                    best = external_best[parent]
+                    self.wdl[parent] = LOSS if best is None else max(best, LOSS)
+                    queue.append(parent)
+
+        for key in self.nodes:
+            self.wdl.setdefault(key, DRAW)
+
+    def solve_dtz(self, children, zeroing_children, external_best):
+        # Plies until the next zeroing move with best play: winners hurry, losers hold out. A
+        # checkmated position counts 0 here, so mating counts one ply like a zeroing move.
+        parents = {key: [] for key in self.nodes}
+        unresolved = {}
+        queue = deque()
+        for key in self.nodes:
+            if self.wdl[key] == DRAW:
+                continue
+            winning_zeroing = any(self.wdl[child] == LOSS for child in zeroing_children[key])
+            if self.wdl[key] == WIN and (winning_zeroing or external_best[key] == WIN):
+                self.dtz[key] = 1
+            # Only moves within the table that keep the result lead on
+            useful = [child for child in children[key] if self.wdl[child] == -self.wdl[key]]
+            for child in useful:
+                parents[child].append(key)
+            unresolved[key] = len(useful)
+
+        for key in self.nodes:
+            if self.wdl[key] != LOSS or unresolved[key] > 0:
+                continue
+            # Checkmated, or only zeroing moves left
+            has_moves = children[key] or zeroing_children[key] or external_best[key] is not None
+            self.dtz[key] = 1 if has_moves else 0
+        queue.extend(sorted((key for key in self.dtz), key=lambda key: self.dtz[key]))
+
+        while queue:
+            key = queue.popleft()
+            for parent in parents[key]:
+                if parent in self.dtz:
+                    continue
+                if self.wdl[parent] == WIN:
+                    self.dtz[parent] = self.dtz[key] + 1
+                    queue.append(parent)
+                    continue
+                unresolved[parent] -= 1
+                if unresolved[parent] == 0:
+                    self.dtz[parent] = self.dtz[key] + 1
+                    queue.append(parent)
+
+        # The fifty move rule would turn longer ones into cursed wins and blessed losses
+        assert all(dtz <= 100 for dtz in self.dtz.values())
+
+    def values(self, to_move, lead_file, value):
+        # The value of every index of a sub-table, None for indexes no legal position maps to
+        values = [None] * self.table.size
+        for (side, key_file, idx), _ in self.nodes.items():
+            if side == to_move and key_file == lead_file:
+                values[idx] = value((side, key_file, idx))
+        return values
+
+
+# Compression
+
+
+def fill_unused(values):
+    # Indexes no position maps to repeat the value before them, which pairs well
+    known = next((value for value in values if value is not None), 0)
+    filled = []
+    for value in values:
+        if value is not None:
+            known = value
+        filled.append(known)
+    return filled
+
+
+def pair_symbols(values):
+    # Recursive pairing: the most frequent pair of neighbouring symbols keeps being replaced by
+    # a new symbol. Returns the symbol stream and what every symbol stands for.
+    leaves = sorted(set(values))
+    symbols = [("value", value) for value in leaves]
+    lengths = [1] * len(leaves)
+    symbol_of = {value: i for i, value in enumerate(leaves)}
+    stream = [symbol_of[value] for value in values]
+
+    while len(symbols) < MAX_SYMBOLS:
+        pairs = Counter(zip(stream, stream[1:]))
+        candidates = [
+            (count, pair)
+            for pair, count in pairs.items()
+            if lengths[pair[0]] + lengths[pair[1]] <= MAX_SYMBOL_VALUES
+        ]
+        if not candidates:
+            break
+        count, pair = max(candidates, key=lambda candidate: (candidate[0], -candidate[1][0],
+                                                             -candidate[1][1]))
+        if count < 4:
+            break
+
+        new_symbol = len(symbols)
+        symbols.append(("pair", pair))
+        lengths.append(lengths[pair[0]] + lengths[pair[1]])
+        paired = []
+        i = 0
+        while i < len(stream):
+            if i + 1 < len(stream) and (stream[i], stream[i + 1]) == pair:
+                paired.append(new_symbol)
+                i += 2
+            else:
+                paired.append(stream[i])
+                i += 1
+        stream = paired
+
+    return stream, symbols, lengths
+
+
+def huffman_lengths(frequencies):
+    # Code length of every symbol that is used
+    heap = [(frequency, symbol, [symbol]) for symbol, frequency in frequencies.items()]
+    heapq.heapify(heap)
+    lengths = {symbol: 0 for symbol in frequencies}
+    if len(heap) == 1:
+        return {symbol: 1 for symbol in frequencies}
+    while len(heap) > 1:
+        first = heapq.heappop(heap)
+        second = heapq.heappop(heap)
+        for symbol in first[2] + second[2]:
+            lengths[symbol] += 1
+        heapq.heappush(heap, (first[0] + second[0], min(first[1], second[1]),
+                              first[2] + second[2]))
+    return lengths
+
+
+class Compressed:
+    # One sub-table, ready to be written
+
+    def __init__(self, values, flags):
+        values = fill_unused(values)
+        self.flags = flags
+        self.size = len(values)
+        if len(set(values)) == 1:
+            self.flags |= SINGLE_VALUE_FLAG
+            self.single_value = values[0]
+            return
+
+        stream, symbols, lengths = pair_symbols(values)
+        code_lengths = huffman_lengths(Counter(stream))
+        if len(code_lengths) == 1:
+            # A single code still needs a sibling to form a complete prefix code
+            unused = next(s for s in range(len(symbols)) if s not in code_lengths)
+            code_lengths[unused] = 1
+
+        # Longer codes get the lower symbol numbers, unused symbols go last
+        by_length = sorted(code_lengths, key=lambda symbol: (-code_lengths[symbol], symbol))
+        unused = [symbol for symbol in range(len(symbols)) if symbol not in code_lengths]
+        renumber = {old: new for new, old in enumerate(by_length + unused)}
+
+        self.symbols = [None] * len(symbols)
+        for old, symbol in enumerate(symbols):
+            kind, content = symbol
+            if kind == "pair":
+                content = (renumber[content[0]], renumber[content[1]])
+            self.symbols[renumber[old]] = (kind, content)
+        self.symbol_values = [0] * len(symbols)
+        for old, length in enumerate(lengths):
+            self.symbol_values[renumber[old]] = length
+
+        self.min_len = min(code_lengths.values())
+        self.max_len = max(code_lengths.values())
+        assert self.max_len <= 32
+        count = {length: 0 for length in range(self.min_len, self.max_len + 1)}
+        for length in code_lengths.values():
+            count[length] += 1
+
+        # lowest[l] is the first symbol with a code of length l, base[l] its code
+        self.lowest = {}
+        base = {}
+        lowest = 0
+        for length in range(self.max_len, self.min_len - 1, -1):
+            self.lowest[length] = lowest
+            lowest += count[length]
+        base[self.max_len] = 0
+        for length in range(self.max_len - 1, self.min_len - 1, -1):
+            total = base[length + 1] + count[length + 1]
+            assert total % 2 == 0
+            base[length] = total // 2
+
+        codes = {}
+        for old in code_lengths:
+            symbol = renumber[old]
+            length = code_lengths[old]
+            codes[symbol] = (base[length] + symbol - self.lowest[length], length)
+
+        self.write_blocks([renumber[symbol] for symbol in stream], codes)
+
+    def write_blocks(self, stream, codes):
+        block_bits = 8 << BLOCK_SIZE_LOG
+        self.blocks = []
+        self.block_values = []
+        bits = []
+        values_in_block = 0
+        for symbol in stream:
+            code, length = codes[symbol]
+            values = self.symbol_values[symbol]
+            if len(bits) + length > block_bits or values_in_block + values > 1 << 16:
+                self.finish_block(bits, values_in_block)
+                bits = []
+                values_in_block = 0
+            bits.extend((code >> (length - 1 - i)) & 1 for i in range(length))
+            values_in_block += values
+        self.finish_block(bits, values_in_block)
+
+        starts = []
+        start = 0
+        for values in self.block_values:
+            starts.append(start)
+            start += values
+        assert start == self.size
+
+        # Every span-th value, counted from the middle of the span, with its block and offset
+        span = 1 << SPAN_LOG
+        self.sparse_index = []
+        block = 0
+        for k in range((self.size + span - 1) // span):
+            middle = k * span + span // 2
+            while block + 1 < len(starts) and starts[block + 1] <= middle:
+                block += 1
+            offset = middle - starts[block]
+            assert offset < 1 << 16
+            self.sparse_index.append((block, offset))
+
+    def finish_block(self, bits, values):
+        bits = bits + [0] * ((8 << BLOCK_SIZE_LOG) - len(bits))
+        data = bytes(
+            int("".join(map(str, bits[i:i + 8])), 2) for i in range(0, len(bits), 8)
+        )
+        self.blocks.append(data)
+        self.block_values.append(values)
+
+    def sizes(self):
+        # The compression parameters, see set_sizes in src/syzygy.rs
+        if self.flags & SINGLE_VALUE_FLAG:
+            return bytes([self.flags, self.single_value])
+
+        header = bytes([self.flags, BLOCK_SIZE_LOG, SPAN_LOG, 0])
+        header += struct.pack("<I", len(self.blocks))
+        header += bytes([self.max_len, self.min_len])
+        for length in range(self.min_len, self.max_len + 1):
+            header += struct.pack("<H", self.lowest[length])
+        header += struct.pack("<H", len(self.symbols))
+        for kind, content in self.symbols:
+            left, right = (content, 0xFFF) if kind == "value" else content
+            header += bytes([left & 0xFF, (left >> 8) | ((right & 0xF) << 4), right >> 4])
+        if len(self.symbols) % 2:
+            header += b"\0"
+        return header
+
+    def sparse_index_bytes(self):
+        if self.flags & SINGLE_VALUE_FLAG:
+            return b""
+        return b"".join(struct.pack("<IH", block, offset) for block, offset in self.sparse_index)
+
+    def block_length_bytes(self):
+        if self.flags & SINGLE_VALUE_FLAG:
+            return b""
+        return b"".join(struct.pack("<H", values - 1) for values in self.block_values)
+
+    def data(self):
+        if self.flags & SINGLE_VALUE_FLAG:
+            return b""
+        return b"".join(self.blocks)
+
+
+def write_table(path, magic, table, sub_tables):
+    # sub_tables[file] lists the sub-tables by side to move, see Table::parse in
+    # src/syzygy.rs for the layout
+    out = bytearray(magic)
+    out.append(1 | (2 if table.has_pawns else 0))
+    for _ in range(table.files):
+        out.append(0)
+        for code in table.order:
+            out.append(code | (code << 4))
+    if len(out) % 2:
+        out.append(0)
+    for file_tables in sub_tables:
+        for sub_table in file_tables:
+            out += sub_table.sizes()
+    if magic == DTZ_MAGIC and len(out) % 2:
+        out.append(0)
+    for file_tables in sub_tables:
+        for sub_table in file_tables:
+            out += sub_table.sparse_index_bytes()
+    for file_tables in sub_tables:
+        for sub_table in file_tables:
+            out += sub_table.block_length_bytes()
+    for file_tables in sub_tables:
+        for sub_table in file_tables:
+            out += bytes(-len(out) % 64)
+            out += sub_table.data()
+
+    with open(path, "wb") as f:
+        f.write(out)
+
+
+def main():
+    directory = os.path.dirname(os.path.abspath(__file__))
+    solved = {}
+    # The pawn ending promotes into the others, so it comes last
+    for piece in (QUEEN, ROOK, BISHOP, KNIGHT, PAWN):
+        table = Table(piece)
+        solution = Solution(table, solved)
+        solution.solve()
+        solved[piece] = solution
+
+        # Win/draw/loss values are stored as the result plus 2, for both sides to move
+        wdl_tables = [
+            [
+                Compressed(
+                    solution.values(to_move, lead_file, lambda key: solution.wdl[key] + 2), 0
+                )
+                for to_move in (WHITE, BLACK)
+            ]
+            for lead_file in range(table.files)
+        ]
+        write_table(os.path.join(directory, table.name() + ".rtbw"), WDL_MAGIC, table,
+                    wdl_tables)
+
+        # Distance to zeroing is only stored with white to move, in plies minus one. Draws
+        # are never looked up.
+        def dtz_value(key):
+            if solution.wdl[key] == DRAW:
+                return None
+            return max(solution.dtz[key], 1) - 1
+
+        dtz_tables = [
+            [Compressed(solution.values(WHITE, lead_file, dtz_value),
+                        WIN_PLIES_FLAG | LOSS_PLIES_FLAG)]
+            for lead_file in range(table.files)
+        ]
+        write_table(os.path.join(directory, table.name() + ".rtbz"), DTZ_MAGIC, table,
+                    dtz_tables)
+        print(f"Wrote {table.name()}: {len(solution.nodes)} positions")
+
+
+if __name__ == "__main__":
+    main()